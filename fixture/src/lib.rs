@@ -0,0 +1,61 @@
+/*
+Scratch directories for tests. Add the paths a test needs, `build` to create them, and `teardown` to remove them once it's done.
+*/
+use std::fs::{create_dir_all, remove_dir_all};
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fixture {
+    dirs: Vec<PathBuf>,
+}
+
+impl Fixture {
+    pub fn new() -> Fixture {
+        Fixture::default()
+    }
+
+    /// Adds a directory for `build` to create and `teardown` to remove.
+    pub fn add_dirpath(mut self, path: String) -> Fixture {
+        self.dirs.push(PathBuf::from(path));
+        self
+    }
+
+    /// Creates every directory added, along with its parents.
+    pub fn build(self) -> Fixture {
+        for dir in self.dirs.iter() {
+            create_dir_all(dir).unwrap_or_else(|e| panic!("failed to create {}: {}", dir.display(), e));
+        }
+        self
+    }
+
+    /// Removes every directory added and everything in it, unless `remove` is false,
+    /// say to look at what a failing test left behind. Ones already gone are skipped.
+    pub fn teardown(&mut self, remove: bool) {
+        if !remove {
+            return;
+        }
+        for dir in self.dirs.iter().filter(|dir| dir.exists()) {
+            remove_dir_all(dir).unwrap_or_else(|e| panic!("failed to remove {}: {}", dir.display(), e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod dirs {
+    use super::*;
+
+    #[test]
+    fn build_and_teardown() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("fixture-{}", std::process::id()));
+        let nested = dir.join("a").join("b");
+
+        let mut fixture = Fixture::new().add_dirpath(nested.display().to_string()).add_dirpath(dir.display().to_string()).build();
+        assert!(nested.is_dir());
+
+        fixture.teardown(false);
+        assert!(nested.is_dir());
+        fixture.teardown(true);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,516 @@
+/*
+This module is the typed model of `$HOME/.repoint/account.toml`, which can hold several named accounts and which of them is the default.
+
+```ignore
+default = "7db9a"
+
+[accounts.7db9a]
+pubaddr = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S"
+profile = "Point to your repos."
+avatar = "https://example.com/7db9a.png"
+xpriv_kdf = "scrypt-chacha20poly1305"
+...
+```
+
+Files written before this had a single `['account']` table with a `name` field. They still load, as a config with that one account as default, and are rewritten in the new layout the next time they're saved.
+*/
+extern crate toml;
+
+use crate::keystore::EncryptedKey;
+use crate::manifest;
+use crate::repoint_file::{self, value, Document, Item};
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+use err::Error;
+pub use err::{AccountError, ErrorKind};
+
+/// How an account holds its private key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AccountKey {
+    Plain(String),
+    Encrypted(EncryptedKey),
+    Missing,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountEntry {
+    /// The on-chain account name, also the entry's key in the file.
+    pub name: String,
+    pub pubaddr: String,
+    pub key: AccountKey,
+    /// Profile text, published with `push --account`.
+    pub profile: Option<String>,
+    /// Profile picture uri, published with `push --account`.
+    pub avatar: Option<String>,
+}
+
+impl AccountEntry {
+    /// Checks the profile fits in an op-return and the avatar is a uri, before anything is signed.
+    pub fn check_profile(&self) -> Result<(), AccountError> {
+        if let Some(profile) = &self.profile {
+            manifest::check_text(profile).map_err(|msg| invalid(&format!("profile of {} {}", self.name, msg)))?;
+        }
+        if let Some(avatar) = &self.avatar {
+            if !manifest::is_uri(avatar) {
+                return Err(invalid(&format!("avatar of {} isn't a uri like https://host/path", self.name)));
+            }
+            manifest::check_text(avatar).map_err(|msg| invalid(&format!("avatar of {} {}", self.name, msg)))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountConfig {
+    pub default: Option<String>,
+    pub accounts: BTreeMap<String, AccountEntry>,
+}
+
+impl AccountConfig {
+    pub fn parse<T: AsRef<str>>(data: T) -> Result<AccountConfig, AccountError> {
+        let doc = data.as_ref().parse::<Document>()?;
+        let mut config = AccountConfig::default();
+
+        if let Some(accounts) = doc["accounts"].as_table() {
+            for (name, item) in accounts.iter() {
+                let entry = entry(name, item)?;
+                config.accounts.insert(name.to_string(), entry);
+            }
+            config.default = doc["default"].as_str().map(|s| s.to_string());
+        } else if doc["account"].is_table() {
+            let name = doc["account"]["name"]
+                .as_str()
+                .ok_or_else(|| invalid("[account] has no name"))?
+                .to_string();
+            config.accounts.insert(name.clone(), entry(&name, &doc["account"])?);
+            config.default = Some(name);
+        }
+
+        if let Some(default) = &config.default {
+            if !config.accounts.contains_key(default) {
+                return Err(invalid(&format!("default account {} isn't in the file", default)));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The default account, or the only one if there's just one.
+    pub fn default_account(&self) -> Result<&AccountEntry, AccountError> {
+        if let Some(default) = &self.default {
+            return self.get(default);
+        }
+        match self.accounts.len() {
+            0 => Err(AccountError::from(Error::new("no accounts, run repoint account add", ErrorKind::NoDefault))),
+            1 => Ok(self.accounts.values().next().unwrap()),
+            _ => Err(AccountError::from(Error::new(
+                "no default account, run repoint account switch NAME",
+                ErrorKind::NoDefault,
+            ))),
+        }
+    }
+
+    pub fn get<T: AsRef<str>>(&self, name: T) -> Result<&AccountEntry, AccountError> {
+        self.accounts.get(name.as_ref()).ok_or_else(|| unknown(name.as_ref()))
+    }
+
+    /// Adds an account, making it the default if it's the first.
+    pub fn add(&mut self, entry: AccountEntry) -> Result<(), AccountError> {
+        if self.accounts.contains_key(&entry.name) {
+            let err = Error::new(&format!("account {} already exists", entry.name), ErrorKind::DuplicateAccount);
+            return Err(AccountError::from(err));
+        }
+        if self.accounts.is_empty() {
+            self.default = Some(entry.name.clone());
+        }
+        self.accounts.insert(entry.name.clone(), entry);
+
+        Ok(())
+    }
+
+    pub fn switch<T: AsRef<str>>(&mut self, name: T) -> Result<(), AccountError> {
+        self.get(name.as_ref())?;
+        self.default = Some(name.as_ref().to_string());
+
+        Ok(())
+    }
+
+    /// Removes an account. Removing the default leaves no default.
+    pub fn remove<T: AsRef<str>>(&mut self, name: T) -> Result<AccountEntry, AccountError> {
+        let entry = self.accounts.remove(name.as_ref()).ok_or_else(|| unknown(name.as_ref()))?;
+        if self.default.as_deref() == Some(name.as_ref()) {
+            self.default = None;
+        }
+
+        Ok(entry)
+    }
+
+    /// Writes the config over `doc`, leaving everything it doesn't own untouched.
+    pub fn edit(&self, doc: &mut Document) {
+        match &self.default {
+            Some(default) => doc["default"] = value(default.as_str()),
+            None => {
+                doc.as_table_mut().remove("default");
+            }
+        }
+
+        let stale: Vec<String> = doc["accounts"]
+            .as_table()
+            .map(|accounts| accounts.iter().map(|(name, _)| name.to_string()).collect())
+            .unwrap_or_default();
+        for name in stale.iter().filter(|name| !self.accounts.contains_key(name.as_str())) {
+            if let Some(accounts) = doc["accounts"].as_table_mut() {
+                accounts.remove(name);
+            }
+        }
+
+        for entry in self.accounts.values() {
+            let table = &mut doc["accounts"][entry.name.as_str()];
+            table["pubaddr"] = value(entry.pubaddr.as_str());
+            set_or_remove(table, "profile", entry.profile.as_deref());
+            set_or_remove(table, "avatar", entry.avatar.as_deref());
+
+            let (plain, encrypted) = match &entry.key {
+                AccountKey::Plain(xpriv) => (Some(xpriv.as_str()), None),
+                AccountKey::Encrypted(key) => (None, Some(key)),
+                AccountKey::Missing => (None, None),
+            };
+            set_or_remove(table, "xpriv", plain);
+            set_or_remove(table, "xpriv_kdf", encrypted.map(|key| key.kdf.as_str()));
+            match encrypted {
+                Some(key) => table["xpriv_log_n"] = value(i64::from(key.log_n)),
+                None => set_or_remove(table, "xpriv_log_n", None),
+            }
+            set_or_remove(table, "xpriv_salt", encrypted.map(|key| key.salt.as_str()));
+            set_or_remove(table, "xpriv_nonce", encrypted.map(|key| key.nonce.as_str()));
+            set_or_remove(table, "xpriv_ciphertext", encrypted.map(|key| key.ciphertext.as_str()));
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        if let Some(default) = &self.default {
+            toml.push_str(&format!("default = {}\n", quote(default)));
+        }
+
+        for entry in self.accounts.values() {
+            toml.push_str(&format!("\n[accounts.{}]\n", quote(&entry.name)));
+            toml.push_str(&format!("pubaddr = {}\n", quote(&entry.pubaddr)));
+            if let Some(profile) = &entry.profile {
+                toml.push_str(&format!("profile = {}\n", quote(profile)));
+            }
+            if let Some(avatar) = &entry.avatar {
+                toml.push_str(&format!("avatar = {}\n", quote(avatar)));
+            }
+            match &entry.key {
+                AccountKey::Plain(xpriv) => toml.push_str(&format!("xpriv = {}\n", quote(xpriv))),
+                AccountKey::Encrypted(key) => {
+                    toml.push_str(&format!("xpriv_kdf = {}\n", quote(&key.kdf)));
+                    toml.push_str(&format!("xpriv_log_n = {}\n", key.log_n));
+                    toml.push_str(&format!("xpriv_salt = {}\n", quote(&key.salt)));
+                    toml.push_str(&format!("xpriv_nonce = {}\n", quote(&key.nonce)));
+                    toml.push_str(&format!("xpriv_ciphertext = {}\n", quote(&key.ciphertext)));
+                }
+                AccountKey::Missing => (),
+            }
+        }
+
+        toml
+    }
+}
+
+/// Loads the account file. A missing file is an empty config.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<AccountConfig, AccountError> {
+    if !path.as_ref().exists() {
+        return Ok(AccountConfig::default());
+    }
+
+    AccountConfig::parse(repoint_file::read_to_string(path.as_ref())?)
+}
+
+/// Saves the account file readable by the owner only, since it holds keys.
+/// An existing file is edited in place, so its comments and layout survive.
+pub fn save<P: AsRef<Path>>(config: &AccountConfig, path: P) -> Result<(), AccountError> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let toml = match existing(path)? {
+        Some(mut doc) => {
+            config.edit(&mut doc);
+            doc.to_string()
+        }
+        None => config.to_toml(),
+    };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(toml.as_bytes())?;
+
+    Ok(())
+}
+
+// The file as it is, unless it's missing or in the legacy layout, which is rewritten from scratch.
+fn existing(path: &Path) -> Result<Option<Document>, AccountError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let doc = repoint_file::read_to_string(path)?.parse::<Document>()?;
+    if doc["account"].is_table() {
+        return Ok(None);
+    }
+
+    Ok(Some(doc))
+}
+
+fn entry(name: &str, item: &Item) -> Result<AccountEntry, AccountError> {
+    let pubaddr = item["pubaddr"]
+        .as_str()
+        .ok_or_else(|| invalid(&format!("account {} has no pubaddr", name)))?;
+
+    Ok(AccountEntry {
+        name: name.to_string(),
+        pubaddr: pubaddr.to_string(),
+        key: entry_key(name, item)?,
+        profile: item["profile"].as_str().map(|s| s.to_string()),
+        avatar: item["avatar"].as_str().map(|s| s.to_string()),
+    })
+}
+
+// The private key fields of one account table, without decrypting them.
+fn entry_key(name: &str, item: &Item) -> Result<AccountKey, AccountError> {
+    let field = |key: &str| item[key].as_str().map(|s| s.to_string());
+
+    if let (Some(kdf), Some(log_n), Some(salt), Some(nonce), Some(ciphertext)) = (
+        field("xpriv_kdf"),
+        item["xpriv_log_n"].as_integer(),
+        field("xpriv_salt"),
+        field("xpriv_nonce"),
+        field("xpriv_ciphertext"),
+    ) {
+        let log_n = u8::try_from(log_n)
+            .map_err(|_| invalid(&format!("xpriv_log_n of {} is out of range: {}", name, log_n)))?;
+        Ok(AccountKey::Encrypted(EncryptedKey { kdf, log_n, salt, nonce, ciphertext }))
+    } else if let Some(xpriv) = field("xpriv") {
+        Ok(AccountKey::Plain(xpriv))
+    } else {
+        Ok(AccountKey::Missing)
+    }
+}
+
+fn set_or_remove(table: &mut Item, key: &str, field: Option<&str>) {
+    match field {
+        Some(field) => table[key] = value(field),
+        None => {
+            if let Some(table) = table.as_table_mut() {
+                table.remove(key);
+            }
+        }
+    }
+}
+
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+fn unknown(name: &str) -> AccountError {
+    AccountError::from(Error::new(&format!("no account named {}", name), ErrorKind::UnknownAccount))
+}
+
+fn invalid(msg: &str) -> AccountError {
+    AccountError::from(Error::new(msg, ErrorKind::InvalidAccount))
+}
+
+mod err {
+    pub use toml_edit::TomlError;
+
+    #[derive(Debug)]
+    pub enum AccountError {
+        IoError(std::io::Error),
+        TomlError(TomlError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        UnknownAccount,
+        DuplicateAccount,
+        NoDefault,
+        InvalidAccount,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for AccountError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                AccountError::IoError(e) => write!(f, "{}", e),
+                AccountError::TomlError(e) => write!(f, "{}", e),
+                AccountError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for AccountError {
+        fn from(error: std::io::Error) -> Self {
+            AccountError::IoError(error)
+        }
+    }
+
+    impl From<TomlError> for AccountError {
+        fn from(error: TomlError) -> Self {
+            AccountError::TomlError(error)
+        }
+    }
+
+    impl From<Error> for AccountError {
+        fn from(error: Error) -> Self {
+            AccountError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod accounts {
+    use super::*;
+    use crate::index::testing::{ALICE, BOB};
+
+    fn setup_entry(name: &str, pubaddr: &str) -> AccountEntry {
+        AccountEntry {
+            name: name.to_string(),
+            pubaddr: pubaddr.to_string(),
+            key: AccountKey::Missing,
+            profile: None,
+            avatar: None,
+        }
+    }
+
+    #[test]
+    fn account_legacy_file_migrates() {
+        let legacy = format!("['account']\nname = \"7db9a\"\npubaddr = \"{}\"\nxpriv = \"5Hue\"\n", ALICE);
+        let config = AccountConfig::parse(legacy).unwrap();
+
+        assert_eq!(config.default.as_deref(), Some("7db9a"));
+        assert_eq!(config.default_account().unwrap().key, AccountKey::Plain("5Hue".to_string()));
+
+        let migrated = AccountConfig::parse(config.to_toml()).unwrap();
+
+        assert!(config.to_toml().contains("[accounts.\"7db9a\"]"));
+        assert_eq!(migrated, config);
+    }
+
+    #[test]
+    fn account_add_switch_remove() {
+        let mut config = AccountConfig::default();
+        config.add(setup_entry("7db9a", ALICE)).unwrap();
+        config.add(setup_entry("acme", BOB)).unwrap();
+
+        assert_eq!(config.default_account().unwrap().pubaddr, ALICE);
+        assert!(config.add(setup_entry("acme", BOB)).is_err());
+
+        config.switch("acme").unwrap();
+        assert_eq!(AccountConfig::parse(config.to_toml()).unwrap().default_account().unwrap().pubaddr, BOB);
+        assert!(config.switch("nobody").is_err());
+
+        config.remove("acme").unwrap();
+        assert_eq!(config.default, None);
+        assert_eq!(config.default_account().unwrap().name, "7db9a");
+    }
+
+    #[test]
+    fn account_rejects_unknown_default() {
+        let toml = format!("default = \"acme\"\n\n[accounts.7db9a]\npubaddr = \"{}\"\n", ALICE);
+
+        assert!(AccountConfig::parse(toml).is_err());
+    }
+
+    #[test]
+    fn account_encrypted_key() {
+        let key = crate::keystore::encrypt_with_cost("5Hue", "hunter2", 4).unwrap();
+        let mut config = AccountConfig::default();
+        config.add(AccountEntry { key: AccountKey::Encrypted(key.clone()), ..setup_entry("7db9a", ALICE) }).unwrap();
+        let toml = config.to_toml();
+
+        assert!(!toml.contains("5Hue"));
+        assert_eq!(AccountConfig::parse(&toml).unwrap(), config);
+        assert!(AccountConfig::parse(toml.replace("xpriv_log_n = 4", "xpriv_log_n = 260")).is_err());
+    }
+
+    #[test]
+    fn account_profile() {
+        let toml = format!("[accounts.7db9a]\npubaddr = \"{}\"\nprofile = \"Point to your repos.\"\navatar = \"https://example.com/a.png\"\n", ALICE);
+        let config = AccountConfig::parse(toml).unwrap();
+        let entry = config.get("7db9a").unwrap();
+
+        assert_eq!(entry.profile.as_deref(), Some("Point to your repos."));
+        assert_eq!(AccountConfig::parse(config.to_toml()).unwrap(), config);
+        assert!(entry.check_profile().is_ok());
+
+        let bad_avatar = AccountEntry { avatar: Some("example.com/a.png".to_string()), ..entry.clone() };
+        let long_profile = AccountEntry { profile: Some("x".repeat(manifest::MAX_TEXT_LEN + 1)), ..entry.clone() };
+        assert!(bad_avatar.check_profile().is_err());
+        assert!(long_profile.check_profile().is_err());
+    }
+
+    #[test]
+    fn account_save_edits_in_place() {
+        let mut home = std::env::temp_dir();
+        home.push(format!("repoint-account-save-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+        let path = home.join(".repoint").join("account.toml");
+
+        let mut config = AccountConfig::default();
+        config.add(AccountEntry { key: AccountKey::Plain("5Hue".to_string()), ..setup_entry("7db9a", ALICE) }).unwrap();
+        save(&config, &path).unwrap();
+        let commented = format!("# my accounts\n{}", fs::read_to_string(&path).unwrap());
+        fs::write(&path, commented).unwrap();
+
+        let key = crate::keystore::encrypt_with_cost("5Hue", "hunter2", 4).unwrap();
+        config.accounts.get_mut("7db9a").unwrap().key = AccountKey::Encrypted(key);
+        config.add(setup_entry("acme", BOB)).unwrap();
+        save(&config, &path).unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+
+        assert!(saved.starts_with("# my accounts\n"), "{}", saved);
+        assert!(!saved.contains("5Hue"), "{}", saved);
+        assert_eq!(load(&path).unwrap(), config);
+
+        config.remove("acme").unwrap();
+        save(&config, &path).unwrap();
+        assert_eq!(load(&path).unwrap(), config);
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+/*
+This module is repoint's view of the chain: broadcasting signed transactions and reading back transactions, address histories and spendable outputs. Which backend is used comes from config.toml's `[backend]` table.
+
+```ignore
+[backend]
+kind = "whatsonchain"
+url = "https://api.whatsonchain.com/v1/bsv/test"
+```
+
+A node of your own is `kind = "rpc"`, with `cookie = "PATH"` or `user` and `password` to log in. `kind = "mock"` is a local stand-in chain kept at `path`.
+*/
+use crate::mock_chain::MockChain;
+use crate::rpc::{self, Rpc, RpcAuth};
+use crate::tx::Transaction;
+use crate::whatsonchain::{self, WhatsOnChain};
+
+use std::path::PathBuf;
+
+use std::str::FromStr;
+
+pub use err::{BackendError, Error, ErrorKind};
+
+/// A transaction as a backend returns it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainTx {
+    pub txid: String,
+    pub hex: String,
+    /// Block height, or `None` while unconfirmed.
+    pub height: Option<u64>,
+}
+
+/// One transaction in an address's history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxRef {
+    pub txid: String,
+    pub height: Option<u64>,
+}
+
+/// An unspent output, spendable by the address it was fetched for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    /// In satoshis.
+    pub value: u64,
+    pub height: Option<u64>,
+}
+
+pub trait ChainBackend {
+    /// Sends raw hex to the network and returns its txid.
+    fn broadcast(&self, hex: &str) -> Result<String, BackendError>;
+
+    fn get_tx(&self, txid: &str) -> Result<ChainTx, BackendError>;
+
+    /// Every transaction touching `address`, oldest first.
+    fn address_history(&self, address: &str) -> Result<Vec<TxRef>, BackendError>;
+
+    fn utxos(&self, address: &str) -> Result<Vec<Utxo>, BackendError>;
+
+    /// Satoshis per byte the network asks for, if the backend can tell.
+    fn fee_rate(&self) -> Result<Option<f64>, BackendError> {
+        Ok(None)
+    }
+}
+
+/// What a broadcast of `hex` that failed with `error` comes to: its txid if the
+/// network only said it has the transaction already, in the mempool or a block.
+pub fn already_sent(hex: &str, error: BackendError) -> Result<String, BackendError> {
+    let known = match &error {
+        BackendError::Error(e) => {
+            let details = e.details.to_lowercase();
+            ["txn-already-known", "txn-already-in-mempool", "already in block chain", "already in the mempool"]
+                .iter()
+                .any(|m| details.contains(m))
+        }
+        _ => false,
+    };
+    if !known {
+        return Err(error);
+    }
+
+    Transaction::from_hex(hex)
+        .map(|tx| tx.txid())
+        .map_err(|e| BackendError::from(Error::new(&e.to_string(), ErrorKind::InvalidResponse)))
+}
+
+/// `[backend] kind`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackendKind {
+    #[default]
+    WhatsOnChain,
+    /// A node's JSON-RPC interface.
+    Rpc,
+    Mock,
+}
+
+impl FromStr for BackendKind {
+    type Err = BackendError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "whatsonchain" => Ok(BackendKind::WhatsOnChain),
+            "rpc" => Ok(BackendKind::Rpc),
+            "mock" => Ok(BackendKind::Mock),
+            _ => {
+                let err = Error::new(
+                    &format!("unknown backend {}, expected whatsonchain, rpc or mock", s),
+                    ErrorKind::UnknownBackend,
+                );
+                Err(BackendError::from(err))
+            }
+        }
+    }
+}
+
+/// The `[backend]` table of config.toml.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    /// Base URL, or the backend's mainnet default.
+    pub url: Option<String>,
+    /// RPC only: the node's cookie file, used over `user` and `password`.
+    pub cookie: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Mock only: the file the chain is kept in, and who sent broadcasts without inputs.
+    pub path: Option<String>,
+    pub sender: Option<String>,
+}
+
+impl BackendConfig {
+    pub fn backend(&self) -> Result<Box<dyn ChainBackend>, BackendError> {
+        Ok(match self.kind {
+            BackendKind::WhatsOnChain => {
+                Box::new(WhatsOnChain::new(self.url.as_deref().unwrap_or(whatsonchain::MAINNET_URL)))
+            }
+            BackendKind::Rpc => Box::new(Rpc::new(self.url.as_deref().unwrap_or(rpc::DEFAULT_URL), self.rpc_auth())),
+            BackendKind::Mock => {
+                let mut chain = match &self.path {
+                    Some(path) => MockChain::open(path)?,
+                    None => MockChain::new(),
+                };
+                chain.sender = self.sender.clone();
+                Box::new(chain)
+            }
+        })
+    }
+
+    fn rpc_auth(&self) -> RpcAuth {
+        match (&self.cookie, &self.user, &self.password) {
+            (Some(cookie), _, _) => RpcAuth::Cookie(PathBuf::from(cookie)),
+            (None, Some(user), password) => RpcAuth::UserPass {
+                user: user.clone(),
+                password: password.clone().unwrap_or_default(),
+            },
+            _ => RpcAuth::None,
+        }
+    }
+}
+
+mod err {
+    #[derive(Debug)]
+    pub enum BackendError {
+        IoError(std::io::Error),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        /// No connection, or no HTTP response.
+        Unreachable,
+        Unauthorized,
+        NotFound,
+        /// The network refused a broadcast.
+        Rejected,
+        InvalidResponse,
+        UnknownBackend,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for BackendError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                BackendError::IoError(e) => write!(f, "{}", e),
+                BackendError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for BackendError {
+        fn from(error: std::io::Error) -> Self {
+            BackendError::IoError(error)
+        }
+    }
+
+    impl From<Error> for BackendError {
+        fn from(error: Error) -> Self {
+            BackendError::Error(error)
+        }
+    }
+}
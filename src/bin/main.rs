@@ -1,11 +1,12 @@
-#[macro_use]
 extern crate seahorse;
 extern crate repoint;
 extern crate dirs;
+extern crate rpassword;
 
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::env;
-use std::fs::{File, metadata};
+use std::fs::File;
+use std::io::Write;
 use seahorse::{App, Command, Context, Flag, FlagType};
 use repoint::repoint_file;
 
@@ -21,9 +22,25 @@ fn main() {
         .flag(Flag::new("age", "cli [name] --age(-a)", FlagType::Int).alias("a"))
         .command(calc_command())
         .command(create_account())
+        .command(account())
         .command(init())
-        .flag(Flag::new("opreturn-script-path", "cli init --opreturn-script-path(-r)", FlagType::String).alias("r"))
-        .command(send());
+        .command(query())
+        .command(get())
+        .command(encrypt_account())
+        .command(login())
+        .command(logout())
+        .command(push())
+        .command(check())
+        .command(history())
+        .command(broadcast())
+        .command(like())
+        .command(unlike())
+        .command(follow())
+        .command(unfollow())
+        .command(flag())
+        .command(unflag())
+        .command(tip())
+        .command(redirect());
 
     app.run(args);
 }
@@ -38,135 +55,920 @@ fn default_action(c: &Context) {
     if let Some(age) = c.int_flag("age") {
         println!("{:?} is {} years old", c.args, age);
     }
-
-    if let Some(opreturn_script_path) = c.string_flag("opreturn-script-path") {
-        println!("opreturn_script-path: {:?}", opreturn_script_path);
-    }
 }
 
 fn init() -> Command {
     Command::new()
         .name("init")
-        .usage("cli [dir] --opreturn-script-path(-r)")
-        .flag(Flag::new("opreturn-script-path", "cli init --opreturn-script-path(-r)", FlagType::String).alias("r"))
+        .usage("cli [dir]")
         .action(init_action)
 }
 
 fn create_account() -> Command {
     Command::new()
         .name("create-account")
-        .usage("cli [name] [pub-addr]")
+        .usage("cli create-account [--stdin] [name] [pub-addr]")
+        .flag(Flag::new("stdin", "cli create-account --stdin", FlagType::Bool))
         .action(create_account_action)
 }
 
-fn send() -> Command {
+fn account() -> Command {
     Command::new()
-        .name("send")
-        .usage("cli [test]")
-        .action(send_action)
+        .name("account")
+        .usage("cli account [list | add [--stdin] NAME PUBADDR | switch NAME | remove NAME]")
+        .flag(Flag::new("stdin", "cli account add --stdin", FlagType::Bool))
+        .action(account_action)
 }
 
-fn send_action(c: &Context) {
-    let mut args = c.args.iter();
-    let mut test = "";
-    let arg_count = args.clone().count();
-    let account_tx_already_exists: bool;
-    let repo_tx_already_exists: bool;
-    match arg_count {
-       1 => {
-           test = args.next().unwrap();
-       },
-       _ => ()
-    };
+fn login() -> Command {
+    Command::new()
+        .name("login")
+        .usage("cli login [--stdin] [--ttl seconds]")
+        .flag(Flag::new("stdin", "cli login --stdin", FlagType::Bool))
+        .flag(Flag::new("ttl", "cli login --ttl [seconds]", FlagType::Int))
+        .action(login_action)
+}
+
+fn logout() -> Command {
+    Command::new()
+        .name("logout")
+        .usage("cli logout")
+        .action(logout_action)
+}
+
+fn query() -> Command {
+    Command::new()
+        .name("query")
+        .usage("cli query [-q] [--show-flagged] [--author(-a) name-or-addr] [repo-name]")
+        .flag(Flag::new("quiet", "cli query --quiet(-q)", FlagType::Bool).alias("q"))
+        .flag(Flag::new("show-flagged", "cli query --show-flagged", FlagType::Bool))
+        .flag(Flag::new("author", "cli query --author(-a) [name-or-addr]", FlagType::String).alias("a"))
+        .flag(Flag::new("source", "cli query --source(-s) [chain.toml]", FlagType::String).alias("s"))
+        .action(query_action)
+}
+
+fn get() -> Command {
+    Command::new()
+        .name("get")
+        .usage("cli get [--name(-n) name | --addr(-a) addr] [--show-uri | --show-addr | --show-name | --show-profile | --show-likes | --show-followers | --show-tips]")
+        .flag(Flag::new("name", "cli get --name(-n) [account | repo | account/repo]", FlagType::String).alias("n"))
+        .flag(Flag::new("addr", "cli get --addr(-a) [addr]", FlagType::String).alias("a"))
+        .flag(Flag::new("show-uri", "cli get --show-uri", FlagType::Bool))
+        .flag(Flag::new("show-addr", "cli get --show-addr", FlagType::Bool))
+        .flag(Flag::new("show-name", "cli get --show-name", FlagType::Bool))
+        .flag(Flag::new("show-profile", "cli get --show-profile", FlagType::Bool))
+        .flag(Flag::new("show-likes", "cli get --show-likes", FlagType::Bool))
+        .flag(Flag::new("show-followers", "cli get --show-followers", FlagType::Bool))
+        .flag(Flag::new("show-tips", "cli get --show-tips", FlagType::Bool))
+        .flag(Flag::new("source", "cli get --source(-s) [chain.toml]", FlagType::String).alias("s"))
+        .action(get_action)
+}
+
+fn encrypt_account() -> Command {
+    Command::new()
+        .name("encrypt-account")
+        .usage("cli encrypt-account")
+        .action(encrypt_account_action)
+}
+
+fn push() -> Command {
+    Command::new()
+        .name("push")
+        .usage("cli push [--account] [--yes(-y)] [--batch n] [--key-source account | env | stdin] [--source(-s) chain.toml] [dir]")
+        .flag(Flag::new("account", "cli push --account", FlagType::Bool))
+        .flag(Flag::new("yes", "cli push --yes(-y)", FlagType::Bool).alias("y"))
+        .flag(Flag::new("batch", "cli push --batch [actions per transaction]", FlagType::Int))
+        .flag(Flag::new("key-source", "cli push --key-source [account | env | stdin]", FlagType::String))
+        .flag(Flag::new("source", "cli push --source(-s) [chain.toml]", FlagType::String).alias("s"))
+        .action(push_action)
+}
+
+fn like() -> Command {
+    social_command("like", |c| social_action(c, repoint::opcode::Opcode::LikeRepo))
+}
+
+fn unlike() -> Command {
+    social_command("unlike", |c| social_action(c, repoint::opcode::Opcode::UnlikeRepo))
+}
+
+fn follow() -> Command {
+    social_command("follow", |c| social_action(c, repoint::opcode::Opcode::FollowRepo))
+}
 
-    // Get file hash of account and repo tomls.
-    // If no match, use toml fields to write opreturn...
-    // and then save appropriate toml hashes to /tmp.
+fn unfollow() -> Command {
+    social_command("unfollow", |c| social_action(c, repoint::opcode::Opcode::UnfollowRepo))
+}
+
+fn flag() -> Command {
+    social_command("flag", |c| social_action(c, repoint::opcode::Opcode::FlagRepo))
+}
+
+fn unflag() -> Command {
+    social_command("unflag", |c| social_action(c, repoint::opcode::Opcode::UnflagRepo))
+}
+
+fn tip() -> Command {
+    Command::new()
+        .name("tip")
+        .usage("cli tip [--yes(-y)] [--key-source account | env | stdin] [--source(-s) chain.toml] REPO-NAME AMOUNT")
+        .flag(Flag::new("yes", "cli tip --yes(-y)", FlagType::Bool).alias("y"))
+        .flag(Flag::new("key-source", "cli tip --key-source [account | env | stdin]", FlagType::String))
+        .flag(Flag::new("source", "cli tip --source(-s) [chain.toml]", FlagType::String).alias("s"))
+        .action(tip_action)
+}
+
+fn redirect() -> Command {
+    Command::new()
+        .name("redirect")
+        .usage("cli redirect [--yes(-y)] [--key-source account | env | stdin] [--source(-s) chain.toml] [account TO-ADDR | repo NAME TO-ADDR TO-INDEX]")
+        .flag(Flag::new("yes", "cli redirect --yes(-y)", FlagType::Bool).alias("y"))
+        .flag(Flag::new("key-source", "cli redirect --key-source [account | env | stdin]", FlagType::String))
+        .flag(Flag::new("source", "cli redirect --source(-s) [chain.toml]", FlagType::String).alias("s"))
+        .action(redirect_action)
+}
+
+fn social_command(name: &str, action: fn(&Context)) -> Command {
+    Command::new()
+        .name(name)
+        .usage(format!("cli {} [--yes(-y)] [--key-source account | env | stdin] [--source(-s) chain.toml] REPO-NAME", name).as_str())
+        .flag(Flag::new("yes", format!("cli {} --yes(-y)", name).as_str(), FlagType::Bool).alias("y"))
+        .flag(Flag::new("key-source", format!("cli {} --key-source [account | env | stdin]", name).as_str(), FlagType::String))
+        .flag(Flag::new("source", format!("cli {} --source(-s) [chain.toml]", name).as_str(), FlagType::String).alias("s"))
+        .action(action)
+}
 
-    let test: bool = (test == "test");
-    
-    if test {
-        account_tx_already_exists = repoint_file::hash_file(repoint_file::FileType::Account).expect("fail to write hash for account file");
-        repo_tx_already_exists = repoint_file::hash_file(repoint_file::FileType::Repo).expect("fail to write hash for repository file");
+fn check() -> Command {
+    Command::new()
+        .name("check")
+        .usage("cli check [dir]")
+        .action(check_action)
+}
+
+fn history() -> Command {
+    Command::new()
+        .name("history")
+        .usage("cli history [--pending] [--refresh]")
+        .flag(Flag::new("pending", "cli history --pending", FlagType::Bool))
+        .flag(Flag::new("refresh", "cli history --refresh", FlagType::Bool))
+        .action(history_action)
+}
+
+fn broadcast() -> Command {
+    Command::new()
+        .name("broadcast")
+        .usage("cli broadcast")
+        .action(broadcast_action)
+}
+
+// Sends everything push signed but nobody broadcast yet, through the configured backend.
+fn broadcast_action(_c: &Context) {
+    let (address, mut ledger) = default_ledger();
+    let backend = repoint::chain_backend().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+
+    let result = ledger.broadcast(backend.as_ref());
+    ledger.save().expect("failed to write ledger");
+    match result {
+        Ok(sent) => {
+            for entry in ledger.entries.iter().filter(|e| sent.contains(&e.txid)) {
+                println!("{}\t{}", entry.txid, entry.summary);
+            }
+            let failed = ledger.entries.iter().filter(|e| e.status == repoint::ledger::Status::Failed).count();
+            if failed > 0 {
+                eprintln!("{} transactions of {} were rejected, see repoint history", failed, address);
+            }
+        }
+        Err(e) => {
+            eprintln!("broadcast stopped: {}", e);
+            std::process::exit(1)
+        }
+    }
+}
 
+// The default account's address and ledger.
+fn default_ledger() -> (String, repoint::ledger::Ledger) {
+    let (config, _) = load_accounts();
+    let account = config.default_account().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    let ledger = repoint::open_ledger(&account.pubaddr).unwrap_or_else(|e| {
+        eprintln!("failed to read ledger: {}", e);
+        std::process::exit(1)
+    });
+
+    (account.pubaddr.clone(), ledger)
+}
+
+// Lists what the default account has published, oldest first.
+fn history_action(c: &Context) {
+    let (_, mut ledger) = default_ledger();
+    if c.bool_flag("refresh") {
+        let backend = repoint::chain_backend().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        });
+        if let Err(e) = ledger.refresh(backend.as_ref()) {
+            eprintln!("failed to refresh: {}", e);
+        }
+        ledger.save().expect("failed to write ledger");
+    }
+
+    for entry in ledger.entries.iter().filter(|e| !c.bool_flag("pending") || e.status.is_outstanding()) {
+        let height = entry.height.map(|h| h.to_string()).unwrap_or_else(|| "-".to_string());
+        println!("{}\t{}\t{}\t{}\t{}", entry.timestamp, entry.status.as_str(), height, entry.txid, entry.summary);
+    }
+}
+
+// Looks up repos by name and/or author. Quiet mode prints `account/repo` per line, for fzf.
+// Repos flagged by someone trusted are hidden or marked, as config.toml's `[moderation]` says;
+// --show-flagged lists them all, marked.
+fn query_action(c: &Context) {
+    let author: Vec<String> = c.string_flag("author").into_iter().collect();
+    let mut addresses = watched_with(&author);
+    let mut index = load_index(c.string_flag("source"), addresses.clone());
+    let config = repoint::config::load(repoint::home_path("config.toml")).unwrap_or_else(|e| {
+        eprintln!("failed to read config.toml: {}", e);
+        std::process::exit(1)
+    });
+    let (accounts, _) = load_accounts();
+    let own: Vec<String> = accounts.accounts.values().map(|a| a.pubaddr.clone()).collect();
+
+    // Who your accounts follow is only known once they're indexed; the owners' flags
+    // count too, so they're indexed in a second pass.
+    if config.moderation.trust_followed {
+        let followed: Vec<String> =
+            own.iter().flat_map(|address| index.following(address)).map(|repo| repo.address.clone()).collect();
+        let before = addresses.len();
+        for address in followed {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+        if addresses.len() > before {
+            index = load_index(c.string_flag("source"), addresses);
+        }
+    }
+
+    let query = repoint::query::Query {
+        name: c.args.first().cloned(),
+        author: c.string_flag("author"),
+    };
+    let trusted = config.moderation.trusted_addresses(&index, &own);
+    let flagged = if c.bool_flag("show-flagged") {
+        repoint::moderation::FlagAction::Annotate
     } else {
-        account_tx_already_exists = false;
-        repo_tx_already_exists = false;
+        config.moderation.flagged
+    };
+    let matches = repoint::moderation::moderate(&index, repoint::query::query(&index, &query), &trusted, flagged);
+
+    if matches.is_empty() {
+        if !c.bool_flag("quiet") {
+            eprintln!("no repos found");
+        }
+        std::process::exit(1);
+    }
+
+    for m in matches.iter() {
+        if c.bool_flag("quiet") {
+            println!("{}", m.full_name());
+        } else {
+            println!("{}", m.summary());
+        }
+    }
+}
 
+// The index from `source`, chain.toml or the configured backend, in that order. Backends are asked about `addresses` only.
+fn load_index(source: Option<String>, addresses: Vec<String>) -> repoint::index::Index {
+    let backend = repoint::chain_backend().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+
+    repoint::load_index(source, backend.as_ref(), &addresses).unwrap_or_else(|e| {
+        eprintln!("failed to load index: {}", e);
+        std::process::exit(1)
+    })
+}
+
+// Your accounts, the addresses in config.toml's `[index] watch` and those whose flags you trust.
+fn watched() -> Vec<String> {
+    let (accounts, _) = load_accounts();
+    let config = repoint::config::load(repoint::home_path("config.toml")).unwrap_or_else(|e| {
+        eprintln!("failed to read config.toml: {}", e);
+        std::process::exit(1)
+    });
+
+    let mut addresses: Vec<String> = accounts.accounts.values().map(|a| a.pubaddr.clone()).collect();
+    for address in config.watch.into_iter().chain(config.moderation.trusted) {
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
     }
-    println!("test: {}", test)
+    addresses
 }
 
+// `watched()`, plus those of `also` that are addresses, such as one given on the command line.
+fn watched_with(also: &[String]) -> Vec<String> {
+    let mut addresses = watched();
+    for address in also.iter().filter(|a| repoint::key::validate_address(a).is_ok()) {
+        if !addresses.contains(address) {
+            addresses.push(address.clone());
+        }
+    }
+    addresses
+}
+
+// Resolves a name or address, following redirects, and prints the requested fields one per line.
+// With none, prints the name and address, and if it redirects, where it ends up.
+fn get_action(c: &Context) {
+    let asked: Vec<String> = c.string_flag("addr").into_iter().collect();
+    let index = load_index(c.string_flag("source"), watched_with(&asked));
+
+    let resolved = match (c.string_flag("name"), c.string_flag("addr")) {
+        (Some(name), None) => repoint::resolve::trace_name(&index, name),
+        (None, Some(addr)) => repoint::resolve::trace_addr(&index, addr),
+        _ => {
+            eprintln!("give exactly one of --name or --addr");
+            std::process::exit(1)
+        }
+    };
+    let resolved = resolved.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    let target = resolved.target.clone();
+
+    let mut shown = false;
+    if c.bool_flag("show-name") {
+        println!("{}", target.name());
+        shown = true;
+    }
+    if c.bool_flag("show-addr") {
+        println!("{}", target.account().address);
+        shown = true;
+    }
+    if c.bool_flag("show-uri") {
+        for uri in target.uris() {
+            println!("{}", uri);
+        }
+        shown = true;
+    }
+    if c.bool_flag("show-profile") {
+        let account = target.account();
+        println!("profile\t{}", account.profile.as_deref().unwrap_or(""));
+        println!("avatar\t{}", account.avatar.as_deref().unwrap_or(""));
+        shown = true;
+    }
+    if c.bool_flag("show-likes") || c.bool_flag("show-followers") || c.bool_flag("show-tips") {
+        let repo = match &target {
+            repoint::resolve::Target::Repo(owner, repo) => repoint::index::RepoRef::new(&owner.address, repo.index),
+            _ => {
+                eprintln!("{} is an account, only repos have likes, followers and tips", target.name());
+                std::process::exit(1)
+            }
+        };
+        if c.bool_flag("show-likes") {
+            println!("{}", index.like_count(&repo));
+        }
+        if c.bool_flag("show-followers") {
+            for follower in index.followers(&repo) {
+                println!("{}", follower);
+            }
+        }
+        if c.bool_flag("show-tips") {
+            for (from, sats) in index.tips(&repo) {
+                println!("{}\t{}", from, sats);
+            }
+        }
+        shown = true;
+    }
+    if !shown && resolved.redirected() {
+        let original = &resolved.original;
+        println!("{}\t{}\t-> {}\t{}", original.name(), original.account().address, target.name(), target.account().address);
+    } else if !shown {
+        println!("{}\t{}", target.name(), target.account().address);
+    }
+}
+
+// The private key is read from stdin or a prompt, never from the arguments, so it stays out of shell history.
 fn create_account_action(c: &Context) {
-    let mut args = c.args.iter();
-    let mut name = "";
-    let mut pub_addr = "";
-    let mut xpriv = "";
-    let arg_count = args.clone().count();
-    match arg_count {
-       3 => {
-           name = args.next().unwrap();
-           pub_addr = args.next().unwrap();
-           xpriv = args.next().unwrap();
-
-           println!("{}\n{}\n{}", name, pub_addr, xpriv)
-       },
-       _ => ()
+    match c.args.as_slice() {
+        [name, pub_addr] => add_account(name, pub_addr, c.bool_flag("stdin")),
+        _ => {
+            eprintln!("usage: repoint create-account [--stdin] NAME PUBADDR");
+            std::process::exit(1)
+        }
+    }
+}
+
+// `account list`, `account add NAME PUBADDR`, `account switch NAME` and `account remove NAME`.
+fn account_action(c: &Context) {
+    let args: Vec<&str> = c.args.iter().map(|a| a.as_str()).collect();
+    let (mut config, path) = load_accounts();
+
+    match args.as_slice() {
+        ["list"] => {
+            let default = config.default_account().ok().map(|a| a.name.clone());
+            for account in config.accounts.values() {
+                let mark = if Some(&account.name) == default.as_ref() { "*" } else { " " };
+                println!("{} {}\t{}", mark, account.name, account.pubaddr);
+            }
+            return;
+        }
+        ["add", name, pub_addr] => {
+            add_account(name, pub_addr, c.bool_flag("stdin"));
+            return;
+        }
+        ["switch", name] => config.switch(name).map(|_| println!("switched to {}", name)),
+        ["remove", name] => config.remove(name).map(|a| println!("removed {} ({})", a.name, a.pubaddr)),
+        _ => {
+            eprintln!("usage: repoint account [list | add NAME PUBADDR | switch NAME | remove NAME]");
+            std::process::exit(1)
+        }
+    }
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+
+    repoint::account_file::save(&config, &path).expect("failed to write account.toml");
+}
+
+// Adds an account alongside the others in account.toml, with its key encrypted.
+fn add_account(name: &str, pub_addr: &str, stdin: bool) {
+    if let Err(e) = repoint::key::validate_address(pub_addr) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let (mut config, path) = load_accounts();
+    if config.get(name).is_ok() {
+        eprintln!("account {} already exists", name);
+        std::process::exit(1);
+    }
+    println!("{}\n{}", name, pub_addr);
+
+    let xpriv = read_privkey(stdin);
+    if let Err(e) = repoint::key::check_address(xpriv.as_str(), pub_addr) {
+        eprintln!("refusing to create account: {}", e);
+        std::process::exit(1);
+    }
+    let passphrase = repoint::keystore::read_new_passphrase().expect("failed to read passphrase");
+    let key = repoint::keystore::encrypt(xpriv.as_str(), passphrase.as_str()).expect("failed to encrypt private key");
+
+    config
+        .add(repoint::account_file::AccountEntry {
+            name: name.to_string(),
+            pubaddr: pub_addr.to_string(),
+            key: repoint::account_file::AccountKey::Encrypted(key),
+            profile: None,
+            avatar: None,
+        })
+        .expect("failed to add account");
+    repoint::account_file::save(&config, &path).expect("failed to write account.toml");
+    println!("{:?}", path);
+}
+
+fn load_accounts() -> (repoint::account_file::AccountConfig, PathBuf) {
+    let path = repoint::home_path("account.toml");
+    match repoint::account_file::load(&path) {
+        Ok(config) => (config, path),
+        Err(e) => {
+            eprintln!("failed to read {:?}: {}", path, e);
+            std::process::exit(1)
+        }
+    }
+}
+
+// Checks the private key against the default account's address and caches it until the session expires.
+fn login_action(c: &Context) {
+    let (config, _) = load_accounts();
+    let pubaddr = match config.default_account() {
+        Ok(account) => account.pubaddr.clone(),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        }
     };
 
-    let mut pathbuf = dirs::home_dir().unwrap();
-    pathbuf.push(".repoint");
-    pathbuf.push("account.toml");
+    let ttl = c.int_flag("ttl").map(|t| t.max(0) as u64).unwrap_or(repoint::session::DEFAULT_TTL_SECS);
+    let privkey = read_privkey(c.bool_flag("stdin"));
+    let session = repoint::session::Session::start(privkey, pubaddr, ttl).unwrap_or_else(|e| {
+        eprintln!("login failed: {}", e);
+        std::process::exit(1)
+    });
 
-    println!("{:?}", pathbuf);
-    //File should already exist from install.
-    File::create(&pathbuf).expect("Failed to create file.");
+    repoint::session::save(&session, repoint::home_path("session.toml")).expect("failed to save session");
+    println!("logged in as {} for {}s", session.address, ttl);
+}
 
-    let doc = repoint_file::init_account(
-        pathbuf.as_path().to_str().unwrap(),
-        name,
-        pub_addr,
-        xpriv
-    ).unwrap();
+fn logout_action(_c: &Context) {
+    repoint::session::clear(repoint::home_path("session.toml")).expect("failed to remove session");
+    println!("logged out");
+}
 
-    //repoint_file::write(doc.clone(), repoint_path.as_ref()).expect("failed to write toml to disk");
-    repoint_file::write(doc.clone(),pathbuf.as_path().to_str().unwrap()).expect("failed to write toml to disk");
-    //let repoint_res = repoint_file::repoint(&doc, Some("example"), "name");
+// One line from stdin, e.g. piped from a password manager, or else a hidden prompt.
+fn read_privkey(stdin: bool) -> String {
+    let privkey = if stdin {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).expect("failed to read private key from stdin");
+        line
+    } else {
+        rpassword::read_password_from_tty(Some("Private key (WIF): ")).expect("failed to read private key")
+    };
+
+    privkey.trim().to_string()
 }
 
-// Instantiate's repoint: opreturns 0x7202 with app code with no other side-effects.
-fn init_action(c: &Context) {
-    let mut args = c.args.iter();
-    let mut path = "";
-    let mut pathbuf: PathBuf;
-    let arg_count = args.clone().count();
-    match arg_count {
-       1 => {
-           path = args.next().unwrap();
-       },
-       _ => ()
+// Encrypts every plaintext private key in account.toml in place, under one passphrase.
+fn encrypt_account_action(_c: &Context) {
+    let (mut config, path) = load_accounts();
+    let plain: Vec<String> = config
+        .accounts
+        .values()
+        .filter(|account| matches!(account.key, repoint::account_file::AccountKey::Plain(_)))
+        .map(|account| account.name.clone())
+        .collect();
+    if plain.is_empty() {
+        println!("private keys are already encrypted");
+        return;
+    }
+
+    let passphrase = repoint::keystore::read_new_passphrase().expect("failed to read passphrase");
+    for name in &plain {
+        let account = config.accounts.get_mut(name).expect("account listed above");
+        if let repoint::account_file::AccountKey::Plain(xpriv) = &account.key {
+            let key = repoint::keystore::encrypt(xpriv.as_str(), passphrase.as_str()).expect("failed to encrypt private key");
+            account.key = repoint::account_file::AccountKey::Encrypted(key);
+        }
+    }
+    repoint::account_file::save(&config, &path).expect("failed to write account.toml");
+    for name in &plain {
+        println!("encrypted private key of {} in {:?}", name, path);
+    }
+}
+
+// Plans the actions that bring the chain up to date with repoint.toml, shows them, and signs them once confirmed.
+// With --account, does the same for the default account's profile in account.toml instead.
+fn push_action(c: &Context) {
+    if c.bool_flag("account") {
+        return push_profile(c);
+    }
+    let (manifest, doc) = open_manifest(c.args.first());
+
+    let (config, _) = load_accounts();
+    let account = config.default_account().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    if let Some(address) = &manifest.address {
+        if *address != account.pubaddr {
+            eprintln!("repoint.toml belongs to {}, but the default account is {}", address, account.pubaddr);
+            std::process::exit(1);
+        }
+    }
+
+    let mut index = load_index(c.string_flag("source"), vec![account.pubaddr.clone()]);
+    let mut ledger = repoint::open_ledger(&account.pubaddr).unwrap_or_else(|e| {
+        eprintln!("failed to read ledger: {}", e);
+        std::process::exit(1)
+    });
+    // What was published but isn't indexed yet counts as done, so pushing twice doesn't repeat it.
+    ledger.overlay(&mut index, &account.pubaddr);
+    let plan = repoint::diff::plan(&index, account.pubaddr.as_str(), Some(account.name.as_str()), &manifest)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        });
+
+    for warning in plan.warnings.iter() {
+        eprintln!("warning: {}", warning);
+    }
+    if plan.is_empty() {
+        println!("{} is up to date", manifest.name);
+        return;
+    }
+    println!("{} ({}:{})", manifest.name, plan.address, plan.repo_index);
+    for line in plan.lines() {
+        println!("  {}", line);
+    }
+
+    let batch = c.int_flag("batch").map(|batch| batch.max(1) as usize);
+    let manifest_hash = repoint::ledger::manifest_hash(doc.to_string());
+    publish(c, &account.pubaddr, &mut ledger, &plan.actions, batch, Some(manifest_hash));
+}
+
+fn push_profile(c: &Context) {
+    let (config, _) = load_accounts();
+    let account = config.default_account().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    if let Err(e) = account.check_profile() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
+    let (address, index, mut ledger) = account_context(c, &[]);
+    let plan = repoint::diff::plan_profile(
+        &index,
+        address.as_str(),
+        Some(account.name.as_str()),
+        account.profile.as_deref(),
+        account.avatar.as_deref(),
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+
+    for warning in plan.warnings.iter() {
+        eprintln!("warning: {}", warning);
+    }
+    if plan.is_empty() {
+        println!("profile of {} is up to date", account.name);
+        return;
+    }
+    println!("{} ({})", account.name, plan.address);
+    for line in plan.lines() {
+        println!("  {}", line);
+    }
+
+    let batch = c.int_flag("batch").map(|batch| batch.max(1) as usize);
+    publish(c, &address, &mut ledger, &plan.actions, batch, None);
+}
+
+// Funds `actions` from `address`'s coins, chained after what's still outstanding, and once
+// confirmed signs them and records each transaction in the ledger.
+fn publish(
+    c: &Context,
+    address: &str,
+    ledger: &mut repoint::ledger::Ledger,
+    actions: &[repoint::opcode::Action],
+    batch: Option<usize>,
+    manifest_hash: Option<String>,
+) {
+    let (config, parent, utxos, fee_rate) = funding(address, ledger);
+    let batch = batch.or(config.batch).unwrap_or(repoint::DEFAULT_BATCH);
+    let funded = repoint::fund_actions(actions, parent, utxos, address, fee_rate, batch).unwrap_or_else(|e| {
+        eprintln!("can't fund {} actions from {}: {}", actions.len(), address, e);
+        std::process::exit(1)
+    });
+
+    let lines: Vec<String> = actions.iter().map(repoint::diff::describe).collect();
+    let summaries = lines.chunks(batch).map(|lines| lines.join("; ")).collect();
+    let fee: u64 = funded.iter().map(|f| f.fee).sum();
+    println!("Fee: {} sats for {} transactions at {} sat/byte", fee, funded.len(), fee_rate);
+    let prompt = format!("Publish {} actions?", actions.len());
+    sign_and_record(c, address, ledger, funded, summaries, &prompt, manifest_hash);
+}
+
+// config.toml, then what to fund from: the tip of what's still outstanding, so the new
+// transactions can't be mined before it, the rest of `address`'s coins and the fee rate.
+fn funding(
+    address: &str,
+    ledger: &repoint::ledger::Ledger,
+) -> (repoint::config::Config, Option<repoint::backend::Utxo>, Vec<repoint::backend::Utxo>, f64) {
+    let config = repoint::config::load(repoint::home_path("config.toml")).unwrap_or_else(|e| {
+        eprintln!("failed to read config.toml: {}", e);
+        std::process::exit(1)
+    });
+    let backend = repoint::chain_backend().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    // Outputs spent by what's signed but not yet broadcast still look unspent to the backend.
+    let spends = ledger.spends();
+    let utxos = backend.utxos(address).unwrap_or_else(|e| {
+        eprintln!("failed to read unspent outputs of {}: {}", address, e);
+        std::process::exit(1)
+    });
+    let utxos = utxos.into_iter().filter(|u| !spends.contains(&(u.txid.clone(), u.vout))).collect();
+    let fee_rate = repoint::fee_rate(&config, backend.as_ref());
+
+    (config, ledger.chain_tip(address), utxos, fee_rate)
+}
+
+// Once confirmed, signs `funded` and records each transaction with its summary.
+fn sign_and_record(
+    c: &Context,
+    address: &str,
+    ledger: &mut repoint::ledger::Ledger,
+    mut funded: Vec<repoint::wallet::Funded>,
+    summaries: Vec<String>,
+    prompt: &str,
+    manifest_hash: Option<String>,
+) {
+    if !c.bool_flag("yes") && !confirm(prompt) {
+        println!("nothing published");
+        return;
+    }
+
+    let provider = repoint::key_provider(c.string_flag("key-source")).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    let key = provider
+        .privkey()
+        .map_err(|e| e.to_string())
+        .and_then(|privkey| repoint::key::check_address(privkey.as_str(), address).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            eprintln!("failed to unlock private key: {}", e);
+            std::process::exit(1)
+        });
+
+    repoint::wallet::sign_chain(&mut funded, &key).expect("failed to sign transactions");
+    for (funded, summary) in funded.iter().zip(summaries.iter()) {
+        let hex = funded.tx.to_hex();
+        let mut entry = repoint::ledger::Entry::new(funded.tx.txid(), hex.clone(), summary.clone(), manifest_hash.clone());
+        entry.parent = funded.parent.clone();
+        ledger.record(entry);
+        println!("{}", hex);
+    }
+    ledger.save().expect("failed to write ledger");
+}
+
+// The default account's address, the index of watched addresses and `also`, with what the account
+// published but isn't indexed yet overlaid, and its ledger. What's signed counts, so liking twice
+// is refused before broadcast.
+fn account_context(c: &Context, also: &[String]) -> (String, repoint::index::Index, repoint::ledger::Ledger) {
+    let (config, _) = load_accounts();
+    let account = config.default_account().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+
+    let mut index = load_index(c.string_flag("source"), watched_with(also));
+    let ledger = repoint::open_ledger(&account.pubaddr).unwrap_or_else(|e| {
+        eprintln!("failed to read ledger: {}", e);
+        std::process::exit(1)
+    });
+    ledger.overlay(&mut index, &account.pubaddr);
+
+    (account.pubaddr.clone(), index, ledger)
+}
+
+// Resolves `name` to a repo, following redirects.
+fn repo_named(index: &repoint::index::Index, name: &str) -> repoint::index::RepoRef {
+    match repoint::resolve::resolve_name(index, name) {
+        Ok(repoint::resolve::Target::Repo(owner, repo)) => repoint::index::RepoRef::new(&owner.address, repo.index),
+        Ok(target) => {
+            eprintln!("{} is an account, not a repo", target.name());
+            std::process::exit(1)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1)
+        }
+    }
+}
+
+// Likes, unlikes, follows, unfollows, flags or unflags a repo by name from the default account.
+fn social_action(c: &Context, opcode: repoint::opcode::Opcode) {
+    let name = c.args.first().unwrap_or_else(|| {
+        eprintln!("give the name of a repo");
+        std::process::exit(1)
+    });
+    let (address, index, mut ledger) = account_context(c, &[]);
+    let repo = repo_named(&index, name);
+    let action = repoint::social::edge(&index, &address, opcode, &repo).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+
+    println!("{} ({}:{})", name, repo.address, repo.index);
+    println!("  {}", repoint::diff::describe(&action));
+    publish(c, &address, &mut ledger, &[action], None, None);
+}
+
+// Pays a repo's owner from the default account, with the tip op-return in the same transaction.
+fn tip_action(c: &Context) {
+    if c.args.len() < 2 {
+        eprintln!("give the name of a repo and an amount, like 1000 sats or 0.001 bsv");
+        std::process::exit(1);
+    }
+    let name = &c.args[0];
+    let amount = repoint::wallet::parse_amount(c.args[1..].join(" ")).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    let (address, index, mut ledger) = account_context(c, &[]);
+    let repo = repo_named(&index, name);
+
+    let (config, parent, utxos, fee_rate) = funding(&address, &ledger);
+    let max = config.max_tip.unwrap_or(repoint::social::DEFAULT_MAX_TIP);
+    let action = repoint::social::tip(&index, &address, &repo, amount, max).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+    let funded = repoint::fund_tip(&action, repo.address.as_str(), amount, parent, utxos, address.as_str(), fee_rate)
+        .unwrap_or_else(|e| {
+            eprintln!("can't fund a tip of {} sats from {}: {}", amount, address, e);
+            std::process::exit(1)
+        });
+
+    let summary = format!("{}; pay {} sats to {}", repoint::diff::describe(&action), amount, repo.address);
+    println!("{} ({}:{})", name, repo.address, repo.index);
+    println!("  {}", repoint::diff::describe(&action));
+    println!("  pay {} sats to {}", amount, repo.address);
+    println!("Fee: {} sats at {} sat/byte", funded.fee, fee_rate);
+    let prompt = format!("Tip {} sats to {}?", amount, name);
+    sign_and_record(c, &address, &mut ledger, vec![funded], vec![summary], &prompt, None);
+}
+
+// Points the default account, or one of its repos, at another one.
+fn redirect_action(c: &Context) {
+    let args: Vec<&str> = c.args.iter().map(|a| a.as_str()).collect();
+    let to = match args.as_slice() {
+        ["account", to] => to.to_string(),
+        ["repo", _, to, _] => to.to_string(),
+        _ => {
+            eprintln!("give either account TO-ADDR or repo NAME TO-ADDR TO-INDEX");
+            std::process::exit(1)
+        }
     };
+    let (address, index, mut ledger) = account_context(c, std::slice::from_ref(&to));
 
-    if let Some(opreturn_script_path) = c.string_flag("opreturn-script-path") {
-        println!("opreturn_script-path: {:?}", opreturn_script_path);
-        let output = repoint::init_sign(opreturn_script_path).expect("fail to get opreturn results");
-        println!("{}", String::from_utf8_lossy(&output.stdout));
+    let (action, header) = match args.as_slice() {
+        ["repo", name, _, to_index] => {
+            let to_index: u64 = to_index.parse().unwrap_or_else(|_| {
+                eprintln!("{} isn't a repo index", to_index);
+                std::process::exit(1)
+            });
+            let to = repoint::index::RepoRef::new(&to, to_index);
+            let action = repoint::redirect::repo(&index, &address, name, &to);
+            (action, format!("{} -> {}:{}", name, to.address, to.index))
+        }
+        _ => (repoint::redirect::account(&index, &address, &to), format!("{} -> {}", address, to)),
+    };
+    let action = action.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1)
+    });
+
+    println!("{}", header);
+    println!("  {}", repoint::diff::describe(&action));
+    publish(c, &address, &mut ledger, &[action], None, None);
+}
+
+// Asks a yes/no question on stdin; anything but y or yes is no.
+fn confirm(prompt: &str) -> bool {
+    print!("{} [y/N] ", prompt);
+    std::io::stdout().flush().expect("failed to flush stdout");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).expect("failed to read answer");
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// Validates repoint.toml without signing anything.
+fn check_action(c: &Context) {
+    let (manifest, _doc) = open_manifest(c.args.first());
+    println!("{} is valid", manifest.name);
+}
+
+// Opens `<dir>/repoint.toml`, printing every violation as `path:line:col: key: message` and exiting if there are any.
+fn open_manifest(dir: Option<&String>) -> (repoint::manifest::RepositoryManifest, repoint_file::Document) {
+    let mut pathbuf = match dir {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::new(),
+    };
+    pathbuf.push("repoint.toml");
+    let path = pathbuf.to_str().unwrap();
+
+    match repoint::manifest::open(path) {
+        Ok(opened) => opened,
+        Err(repoint::manifest::ManifestError::Invalid(violations)) => {
+            for v in violations.iter() {
+                eprintln!("{}:{}", path, v);
+            }
+            std::process::exit(1)
+        }
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1)
+        }
     }
 }
 
+// Instantiate's repoint: opreturns 0x7202 with app code with no other side-effects.
+fn init_action(_c: &Context) {
+    let tx_hex = repoint::init_sign().expect("fail to get opreturn results");
+    println!("{}", tx_hex);
+}
+
+#[allow(dead_code)]
 fn add_repo_action(c: &Context) {
     let mut args = c.args.iter();
     let mut path = "";
-    let mut pathbuf: PathBuf;
     let arg_count = args.clone().count();
-    match arg_count {
-       1 => {
-           path = args.next().unwrap();
-       },
-       _ => ()
-    };
+    if arg_count == 1 {
+        path = args.next().unwrap();
+    }
 
-    pathbuf = if path == "" {
+    let pathbuf = if path.is_empty() {
         PathBuf::from("repoint.toml")
     } else {
         let mut p = PathBuf::from(path);
@@ -174,11 +976,8 @@ fn add_repo_action(c: &Context) {
         p
     };
 
-    if let Some(opreturn_script_path) = c.string_flag("opreturn-script-path") {
-        println!("opreturn_script-path: {:?}", opreturn_script_path);
-        let output = repoint::sign(opreturn_script_path, String::from("0x7202"), String::from("hello from repoint")).expect("fail to get opreturn results");
-        println!("{}", String::from_utf8_lossy(&output.stdout));
-    }
+    let tx_hex = repoint::sign(&repoint::opcode::Action::instantiate());
+    println!("{}", tx_hex);
     println!("{:?}", pathbuf);
     File::create(&pathbuf).expect("Failed to create file.");
 
@@ -194,7 +993,7 @@ fn calc_action(c: &Context) {
         Some(op) => {
             let sum: i32 = match &*op {
                 "add" => c.args.iter().map(|n| n.parse::<i32>().unwrap()).sum(),
-                "sub" => c.args.iter().map(|n| n.parse::<i32>().unwrap() * -1).sum(),
+                "sub" => c.args.iter().map(|n| -n.parse::<i32>().unwrap()).sum(),
                 _ => panic!("undefined operator..."),
             };
 
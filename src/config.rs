@@ -0,0 +1,330 @@
+/*
+This module reads the global settings in `$HOME/.repoint/config.toml`. Every setting is optional, so a missing file is the same as an empty one.
+
+```ignore
+[keys]
+source = "env"
+
+[backend]
+kind = "rpc"
+url = "http://127.0.0.1:8332"
+cookie = "/home/me/.bitcoin/.cookie"
+
+[index]
+watch = ["1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt"]
+
+[fees]
+rate = 0.5
+
+[push]
+batch = 10
+
+[tips]
+max = "0.001 bsv"
+
+[moderation]
+trusted = ["1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt"]
+flagged = "hide"
+```
+*/
+extern crate toml;
+
+use crate::backend::{BackendConfig, BackendError};
+use crate::moderation::{FlagAction, Policy};
+use crate::provider::{KeySource, ProviderError};
+use crate::wallet;
+
+use std::fs::read_to_string;
+use std::path::Path;
+
+pub use err::{ConfigError, ErrorKind};
+use err::Error;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    /// `[keys] source`: where the signing key comes from.
+    pub key_source: KeySource,
+    /// `[backend]`: what to broadcast through and read the chain from.
+    pub backend: BackendConfig,
+    /// `[index] watch`: addresses to index from the backend, besides your own accounts.
+    pub watch: Vec<String>,
+    /// `[fees] rate`: satoshis per byte, over what the backend suggests.
+    pub fee_rate: Option<f64>,
+    /// `[push] batch`: the most actions to put in one transaction.
+    pub batch: Option<usize>,
+    /// `[tips] max`: the most satoshis `tip` sends, given in sats or as an amount like `"0.001 bsv"`.
+    pub max_tip: Option<u64>,
+    /// `[moderation]`: whose flags count, and what `query` does with flagged repos.
+    pub moderation: Policy,
+}
+
+impl Config {
+    pub fn parse<T: AsRef<str>>(data: T) -> Result<Config, ConfigError> {
+        let value: toml::Value = data.as_ref().parse()?;
+        let mut config = Config::default();
+
+        if let Some(source) = value.get("keys").and_then(|keys| keys.get("source")) {
+            let source = source.as_str().ok_or_else(|| invalid("keys.source must be a string"))?;
+            config.key_source = source.parse()?;
+        }
+
+        if let Some(backend) = value.get("backend") {
+            if let Some(kind) = backend.get("kind") {
+                let kind = kind.as_str().ok_or_else(|| invalid("backend.kind must be a string"))?;
+                config.backend.kind = kind.parse()?;
+            }
+            let field = |key: &str| -> Result<Option<String>, ConfigError> {
+                match backend.get(key) {
+                    Some(value) => match value.as_str() {
+                        Some(s) => Ok(Some(s.to_string())),
+                        None => Err(invalid(&format!("backend.{} must be a string", key))),
+                    },
+                    None => Ok(None),
+                }
+            };
+            config.backend.url = field("url")?;
+            config.backend.cookie = field("cookie")?;
+            config.backend.user = field("user")?;
+            config.backend.password = field("password")?;
+            config.backend.path = field("path")?;
+            config.backend.sender = field("sender")?;
+        }
+
+        if let Some(watch) = value.get("index").and_then(|index| index.get("watch")) {
+            let watch = watch.as_array().ok_or_else(|| invalid("index.watch must be an array of addresses"))?;
+            for address in watch.iter() {
+                let address = address.as_str().ok_or_else(|| invalid("index.watch must be an array of addresses"))?;
+                config.watch.push(address.to_string());
+            }
+        }
+
+        if let Some(rate) = value.get("fees").and_then(|fees| fees.get("rate")) {
+            let rate = match rate {
+                toml::Value::Float(f) => *f,
+                toml::Value::Integer(i) => *i as f64,
+                _ => return Err(invalid("fees.rate must be a number of sats per byte")),
+            };
+            if rate.is_nan() || rate <= 0.0 {
+                return Err(invalid("fees.rate must be more than 0"));
+            }
+            config.fee_rate = Some(rate);
+        }
+
+        if let Some(batch) = value.get("push").and_then(|push| push.get("batch")) {
+            match batch.as_integer() {
+                Some(batch) if batch > 0 => config.batch = Some(batch as usize),
+                _ => return Err(invalid("push.batch must be a number of actions, at least 1")),
+            }
+        }
+
+        if let Some(max) = value.get("tips").and_then(|tips| tips.get("max")) {
+            let max = match max {
+                toml::Value::Integer(sats) if *sats > 0 => Some(*sats as u64),
+                toml::Value::String(amount) => wallet::parse_amount(amount).ok(),
+                _ => None,
+            };
+            match max {
+                Some(max) => config.max_tip = Some(max),
+                None => return Err(invalid("tips.max must be an amount like 100000 or \"0.001 bsv\"")),
+            }
+        }
+
+        if let Some(moderation) = value.get("moderation") {
+            let trust = |key: &str, default: bool| -> Result<bool, ConfigError> {
+                match moderation.get(key) {
+                    Some(value) => value.as_bool().ok_or_else(|| invalid(&format!("moderation.{} must be true or false", key))),
+                    None => Ok(default),
+                }
+            };
+            config.moderation.trust_self = trust("trust_self", config.moderation.trust_self)?;
+            config.moderation.trust_followed = trust("trust_followed", config.moderation.trust_followed)?;
+            if let Some(trusted) = moderation.get("trusted") {
+                let trusted = trusted.as_array().ok_or_else(|| invalid("moderation.trusted must be an array of addresses"))?;
+                for address in trusted.iter() {
+                    let address = address.as_str().ok_or_else(|| invalid("moderation.trusted must be an array of addresses"))?;
+                    config.moderation.trusted.push(address.to_string());
+                }
+            }
+            if let Some(flagged) = moderation.get("flagged") {
+                config.moderation.flagged = match flagged.as_str() {
+                    Some("hide") => FlagAction::Hide,
+                    Some("annotate") => FlagAction::Annotate,
+                    _ => return Err(invalid("moderation.flagged must be hide or annotate")),
+                };
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+    if !path.as_ref().exists() {
+        return Ok(Config::default());
+    }
+
+    Config::parse(read_to_string(path)?)
+}
+
+fn invalid(msg: &str) -> ConfigError {
+    ConfigError::from(Error::new(msg, ErrorKind::InvalidValue))
+}
+
+mod err {
+    use super::{BackendError, ProviderError};
+    pub use toml::de::Error as TomlError;
+
+    #[derive(Debug)]
+    pub enum ConfigError {
+        IoError(std::io::Error),
+        TomlError(TomlError),
+        ProviderError(ProviderError),
+        BackendError(BackendError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        InvalidValue,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                ConfigError::IoError(e) => write!(f, "{}", e),
+                ConfigError::TomlError(e) => write!(f, "{}", e),
+                ConfigError::ProviderError(e) => write!(f, "{}", e),
+                ConfigError::BackendError(e) => write!(f, "{}", e),
+                ConfigError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for ConfigError {
+        fn from(error: std::io::Error) -> Self {
+            ConfigError::IoError(error)
+        }
+    }
+
+    impl From<TomlError> for ConfigError {
+        fn from(error: TomlError) -> Self {
+            ConfigError::TomlError(error)
+        }
+    }
+
+    impl From<ProviderError> for ConfigError {
+        fn from(error: ProviderError) -> Self {
+            ConfigError::ProviderError(error)
+        }
+    }
+
+    impl From<BackendError> for ConfigError {
+        fn from(error: BackendError) -> Self {
+            ConfigError::BackendError(error)
+        }
+    }
+
+    impl From<Error> for ConfigError {
+        fn from(error: Error) -> Self {
+            ConfigError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod settings {
+    use super::*;
+    use crate::backend::BackendKind;
+
+    #[test]
+    fn config_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert_eq!(load("/nonexistent/config.toml").unwrap().key_source, KeySource::Account);
+    }
+
+    #[test]
+    fn config_key_source() {
+        let config = Config::parse("[keys]\nsource = \"stdin\"\n").unwrap();
+
+        assert_eq!(config.key_source, KeySource::Stdin);
+        assert!(Config::parse("[keys]\nsource = \"ledger\"\n").is_err());
+        assert!(Config::parse("[keys]\nsource = 1\n").is_err());
+    }
+
+    #[test]
+    fn config_backend() {
+        let config = Config::parse("[backend]\nkind = \"whatsonchain\"\nurl = \"http://localhost:3000\"\n").unwrap();
+
+        assert_eq!(config.backend.url.as_deref(), Some("http://localhost:3000"));
+        assert_eq!(config.backend.kind, BackendKind::WhatsOnChain);
+        assert!(Config::parse("[backend]\nkind = \"mattercloud\"\n").is_err());
+    }
+
+    #[test]
+    fn config_rpc_backend() {
+        let toml = "[backend]\nkind = \"rpc\"\nuser = \"repoint\"\npassword = \"pw\"\n\n[index]\nwatch = [\"1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S\"]\n";
+        let config = Config::parse(toml).unwrap();
+
+        assert_eq!(config.backend.kind, BackendKind::Rpc);
+        assert_eq!(config.backend.user.as_deref(), Some("repoint"));
+        assert_eq!(config.watch, vec!["1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S"]);
+        assert!(Config::parse("[backend]\ncookie = 1\n").is_err());
+    }
+
+    #[test]
+    fn config_fee_rate() {
+        assert_eq!(Config::parse("[fees]\nrate = 0.25\n").unwrap().fee_rate, Some(0.25));
+        assert_eq!(Config::parse("[fees]\nrate = 1\n").unwrap().fee_rate, Some(1.0));
+        assert!(Config::parse("[fees]\nrate = 0\n").is_err());
+        assert!(Config::parse("[fees]\nrate = \"cheap\"\n").is_err());
+    }
+
+    #[test]
+    fn config_push_batch() {
+        assert_eq!(Config::parse("[push]\nbatch = 1\n").unwrap().batch, Some(1));
+        assert_eq!(Config::parse("").unwrap().batch, None);
+        assert!(Config::parse("[push]\nbatch = 0\n").is_err());
+    }
+
+    #[test]
+    fn config_tips_max() {
+        assert_eq!(Config::parse("[tips]\nmax = 5000\n").unwrap().max_tip, Some(5000));
+        assert_eq!(Config::parse("[tips]\nmax = \"0.001 bsv\"\n").unwrap().max_tip, Some(100_000));
+        assert!(Config::parse("[tips]\nmax = 0\n").is_err());
+        assert!(Config::parse("[tips]\nmax = \"lots\"\n").is_err());
+    }
+
+    #[test]
+    fn config_moderation() {
+        let config = Config::parse("[moderation]\ntrust_followed = false\ntrusted = [\"1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt\"]\nflagged = \"hide\"\n").unwrap();
+
+        assert!(config.moderation.trust_self);
+        assert!(!config.moderation.trust_followed);
+        assert_eq!(config.moderation.trusted, vec!["1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt"]);
+        assert_eq!(config.moderation.flagged, FlagAction::Hide);
+        assert_eq!(Config::parse("").unwrap().moderation, Policy::default());
+        assert!(Config::parse("[moderation]\nflagged = \"delete\"\n").is_err());
+        assert!(Config::parse("[moderation]\ntrust_self = \"yes\"\n").is_err());
+    }
+}
@@ -0,0 +1,162 @@
+/*
+This module reads repoint actions back out of raw transactions, the inverse of `sign`.
+*/
+use crate::opcode::{self, Action, OpcodeError};
+use crate::tx::{self, Transaction, TxError};
+
+pub use err::DecodeError;
+
+/// Decodes one output script. Scripts that aren't op-returns, or that
+/// carry another protocol's data, are `None` rather than an error.
+pub fn decode_script(script: &[u8]) -> Result<Option<Action>, DecodeError> {
+    let pushes = match tx::opreturn_data(script) {
+        Ok(Some(pushes)) => pushes,
+        Ok(None) => return Ok(None),
+        // Other protocols may script things we don't read; only ours has to parse.
+        Err(e) if opens_with_prefix(script) => return Err(DecodeError::from(e)),
+        Err(_) => return Ok(None),
+    };
+
+    match pushes.first() {
+        Some(first) if first.first() == Some(&opcode::PREFIX) => {
+            Ok(Some(Action::from_pushes(&pushes)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Whether the op-return's first push starts with the repoint opcode prefix.
+fn opens_with_prefix(script: &[u8]) -> bool {
+    let data = match script {
+        [tx::OP_FALSE, tx::OP_RETURN, data @ ..] | [tx::OP_RETURN, data @ ..] => data,
+        _ => return false,
+    };
+
+    matches!(data, [0x01..=0x4b, first, ..] if *first == opcode::PREFIX)
+}
+
+/// Every repoint action in `tx`, in output order. Fails if any of them is malformed.
+pub fn decode_tx(tx: &Transaction) -> Result<Vec<Action>, DecodeError> {
+    decode_outputs(tx).into_iter().collect()
+}
+
+/// Each repoint output of `tx` in order, decoded on its own, so one
+/// malformed output doesn't take the rest of a batch down with it.
+pub fn decode_outputs(tx: &Transaction) -> Vec<Result<Action, DecodeError>> {
+    tx.outputs
+        .iter()
+        .filter_map(|output| decode_script(&output.script_pubkey).transpose())
+        .collect()
+}
+
+/// Decodes raw transaction hex, such as the output of `sign`.
+pub fn decode_hex<T: AsRef<str>>(tx_hex: T) -> Result<Vec<Action>, DecodeError> {
+    let tx = Transaction::from_hex(tx_hex)?;
+    decode_tx(&tx)
+}
+
+mod err {
+    use super::{OpcodeError, TxError};
+
+    #[derive(Debug)]
+    pub enum DecodeError {
+        TxError(TxError),
+        OpcodeError(OpcodeError),
+    }
+
+    impl std::fmt::Display for DecodeError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                DecodeError::TxError(e) => write!(f, "{}", e),
+                DecodeError::OpcodeError(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<TxError> for DecodeError {
+        fn from(error: TxError) -> Self {
+            DecodeError::TxError(error)
+        }
+    }
+
+    impl From<OpcodeError> for DecodeError {
+        fn from(error: OpcodeError) -> Self {
+            DecodeError::OpcodeError(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod round_trip {
+    use super::*;
+    use crate::opcode::Opcode;
+    use crate::tx::TxOut;
+
+    #[test]
+    fn decode_create_account_hex() {
+        let actions = decode_hex("01000000000100000000000000000b006a02720305376462396100000000").unwrap();
+
+        assert_eq!(actions, vec![Action::create_account("7db9a").unwrap()]);
+    }
+
+    #[test]
+    fn decode_signed_actions() {
+        let actions = vec![
+            Action::instantiate(),
+            Action::create_repo("repoint").unwrap(),
+            Action::add_repo_url(0, "https://github.com/7db9a/repoint").unwrap(),
+        ];
+
+        for action in actions {
+            assert_eq!(decode_hex(crate::sign(&action)).unwrap(), vec![action]);
+        }
+    }
+
+    #[test]
+    fn decode_skips_foreign_outputs() {
+        let mut tx = Transaction::new();
+        tx.add_output(TxOut { value: 1000, script_pubkey: vec![0x76, 0xa9] });
+        tx.add_output(TxOut::opreturn(&[b"19HxigV4QyBv3tHpQVcUEQyq1pzZVdoAut".to_vec()]));
+        tx.add_output(TxOut::opreturn(&Action::create_repo("repoint").unwrap().pushes()));
+
+        let actions = decode_tx(&tx).unwrap();
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].opcode, Opcode::CreateRepo);
+    }
+
+    #[test]
+    fn decode_skips_foreign_scripts() {
+        let mut tx = Transaction::new();
+        tx.add_output(TxOut { value: 0, script_pubkey: vec![0x00, 0x6a, 0x51, 0x60, 0x01, 0x72] });
+        tx.add_output(TxOut { value: 0, script_pubkey: vec![0x00, 0x6a, 0x04, 0x61, 0x62, 0x63, 0x64, 0xac] });
+        tx.add_output(TxOut { value: 0, script_pubkey: vec![0x6a, 0x4f, 0x4c] });
+        tx.add_output(TxOut::opreturn(&Action::create_repo("repoint").unwrap().pushes()));
+
+        assert_eq!(decode_tx(&tx).unwrap(), vec![Action::create_repo("repoint").unwrap()]);
+
+        let mut broken = tx::opreturn_script(&Action::create_repo("repoint").unwrap().pushes());
+        broken.push(0xac);
+        assert!(decode_script(&broken).is_err());
+    }
+
+    #[test]
+    fn decode_rejects_malformed_repoint_data() {
+        let mut tx = Transaction::new();
+        tx.add_output(TxOut::opreturn(&[vec![0x72, 0x09], b"https://example.com".to_vec()]));
+
+        assert!(decode_tx(&tx).is_err());
+    }
+
+    #[test]
+    fn decode_outputs_one_at_a_time() {
+        let mut tx = Transaction::new();
+        tx.add_output(TxOut::opreturn(&Action::create_repo("repoint").unwrap().pushes()));
+        tx.add_output(TxOut::opreturn(&[vec![0x72, 0xff, 0x00], b"garbage".to_vec()]));
+        let outputs = decode_outputs(&tx);
+
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].as_ref().unwrap().opcode, Opcode::CreateRepo);
+        assert!(outputs[1].is_err());
+    }
+}
@@ -0,0 +1,372 @@
+/*
+This module compares a repo's repoint.toml with what's already on chain and works out the fewest actions that bring the chain up to date, and does the same for the profile in account.toml, so `push` never pays for or records redundant transactions.
+*/
+use crate::index::{Account, Index, Repo};
+use crate::manifest::RepositoryManifest;
+use crate::opcode::{Action, Opcode, OpcodeError};
+
+use err::Error;
+pub use err::{DiffError, ErrorKind};
+
+/// What `push` would publish, in the order it must land.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Plan {
+    pub address: String,
+    /// Index of the repo on chain, or the one it will get.
+    pub repo_index: u64,
+    pub actions: Vec<Action>,
+    /// Changes the protocol can't express, like removing a tag.
+    pub warnings: Vec<String>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// One line per action, `+` for additions, `~` for replacements and `-` for removals.
+    pub fn lines(&self) -> Vec<String> {
+        self.actions.iter().map(describe).collect()
+    }
+}
+
+/// Plans the actions that make `address`'s copy of the manifest's repo match it.
+/// `account_name` is used if the account itself still has to be created.
+pub fn plan<T: AsRef<str>>(
+    index: &Index,
+    address: T,
+    account_name: Option<T>,
+    manifest: &RepositoryManifest,
+) -> Result<Plan, DiffError> {
+    let address = address.as_ref();
+    let mut plan = Plan { address: address.to_string(), ..Plan::default() };
+
+    let account = index.account(address);
+    plan_account(index, &mut plan, account, account_name.as_ref().map(|n| n.as_ref()).or(manifest.account.as_deref()))?;
+
+    let repo = account.and_then(|a| a.repos.iter().find(|r| r.name.to_lowercase() == manifest.name.to_lowercase()));
+    plan.repo_index = match repo {
+        Some(repo) => repo.index,
+        None => {
+            plan.actions.push(Action::create_repo(&manifest.name)?);
+            account.map_or(0, |a| a.repos.len() as u64)
+        }
+    };
+    let empty = Repo { index: plan.repo_index, name: manifest.name.clone(), ..Repo::default() };
+    plan_repo(&mut plan, repo.unwrap_or(&empty), manifest)?;
+
+    Ok(plan)
+}
+
+/// Plans the actions that make `address`'s profile text and picture on chain match
+/// `profile` and `avatar`, creating the account as `account_name` first if need be.
+pub fn plan_profile<T: AsRef<str>>(
+    index: &Index,
+    address: T,
+    account_name: Option<T>,
+    profile: Option<&str>,
+    avatar: Option<&str>,
+) -> Result<Plan, DiffError> {
+    let address = address.as_ref();
+    let mut plan = Plan { address: address.to_string(), ..Plan::default() };
+
+    let account = index.account(address);
+    plan_account(index, &mut plan, account, account_name.as_ref().map(|n| n.as_ref()))?;
+
+    let fields = [
+        (Opcode::ProfileText, account.and_then(|a| a.profile.as_deref()), profile, "profile text"),
+        (Opcode::ProfilePic, account.and_then(|a| a.avatar.as_deref()), avatar, "profile pics"),
+    ];
+    for (opcode, current, wanted, field) in fields.iter() {
+        match (current, wanted) {
+            (current, Some(wanted)) if current != &Some(*wanted) => {
+                plan.actions.push(Action::builder(*opcode).message(wanted).build()?);
+            }
+            (Some(_), None) => plan.warnings.push(format!("{} can't be removed, only replaced", field)),
+            _ => (),
+        }
+    }
+
+    Ok(plan)
+}
+
+fn plan_account(index: &Index, plan: &mut Plan, account: Option<&Account>, name: Option<&str>) -> Result<(), DiffError> {
+    if index.disqualified(&plan.address) {
+        let msg = format!("{} used op-returns before instantiating repoint, so it can't be a repoint account", plan.address);
+        return Err(DiffError::from(Error::new(&msg, ErrorKind::Disqualified)));
+    }
+    if account.is_none() {
+        plan.actions.push(Action::instantiate());
+    }
+
+    match (account.and_then(|a| a.name.as_deref()), name) {
+        (None, Some(name)) => plan.actions.push(Action::create_account(name)?),
+        (None, None) => {
+            let msg = format!("{} has no account name yet, set account in repoint.toml", plan.address);
+            return Err(DiffError::from(Error::new(&msg, ErrorKind::NoName)));
+        }
+        (Some(current), Some(name)) if current != name => {
+            plan.warnings.push(format!("account is named {} on chain, names can't be changed", current))
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn plan_repo(plan: &mut Plan, repo: &Repo, manifest: &RepositoryManifest) -> Result<(), OpcodeError> {
+    let index = plan.repo_index;
+
+    match (&repo.description, &manifest.description) {
+        (current, Some(text)) if current.as_ref() != Some(text) => {
+            plan.actions.push(Action::builder(Opcode::RepoDescription).repo_index(index).message(text).build()?);
+        }
+        (Some(_), None) => plan.warnings.push("descriptions can't be removed, only replaced".to_string()),
+        _ => (),
+    }
+
+    for tag in unique(&manifest.tags).filter(|t| !repo.tags.contains(t)) {
+        plan.actions.push(Action::builder(Opcode::AddRepoTags).repo_index(index).message(tag).build()?);
+    }
+    for tag in repo.tags.iter().filter(|t| !manifest.tags.contains(t)) {
+        plan.warnings.push(format!("tag {} can't be removed on chain", tag));
+    }
+
+    for url in repo.urls.iter().filter(|u| !manifest.urls.contains(u)) {
+        plan.actions.push(Action::builder(Opcode::RemoveRepoUrl).repo_index(index).message(url).build()?);
+    }
+    for url in unique(&manifest.urls).filter(|u| !repo.urls.contains(u)) {
+        plan.actions.push(Action::add_repo_url(index, url)?);
+    }
+
+    Ok(())
+}
+
+// `items` without repeats, in order, so a tag listed twice isn't paid for twice.
+fn unique(items: &[String]) -> impl Iterator<Item = &String> {
+    items.iter().enumerate().filter(move |(i, item)| !items[..*i].contains(item)).map(|(_, item)| item)
+}
+
+/// One plan line, e.g. `+ 0x7206 create repo anvil` or `+ 0x7211 like repo 1Alice...:0`.
+pub fn describe(action: &Action) -> String {
+    let sign = match action.opcode {
+        Opcode::RepoDescription | Opcode::ProfileText | Opcode::ProfilePic => "~",
+        Opcode::RemoveRepoUrl => "-",
+        _ => "+",
+    };
+    let mut line = format!("{} {} {}", sign, action.opcode.to_hex(), action.opcode.name().to_lowercase());
+    match (&action.address, action.repo_index, action.target_index) {
+        (Some(address), Some(index), Some(target)) => line.push_str(&format!(" [{}] {}:{}", index, address, target)),
+        (Some(address), Some(index), None) => line.push_str(&format!(" {}:{}", address, index)),
+        (Some(address), None, _) => line.push_str(&format!(" {}", address)),
+        (None, Some(index), _) => line.push_str(&format!(" [{}]", index)),
+        _ => (),
+    }
+    if let Some(message) = &action.message {
+        line.push_str(&format!(" {}", message));
+    }
+
+    line
+}
+
+mod err {
+    use super::OpcodeError;
+
+    #[derive(Debug)]
+    pub enum DiffError {
+        OpcodeError(OpcodeError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        /// The address can never become a repoint account.
+        Disqualified,
+        /// The account isn't named on chain and nothing says what to name it.
+        NoName,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for DiffError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                DiffError::OpcodeError(e) => write!(f, "{}", e),
+                DiffError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<OpcodeError> for DiffError {
+        fn from(error: OpcodeError) -> Self {
+            DiffError::OpcodeError(error)
+        }
+    }
+
+    impl From<Error> for DiffError {
+        fn from(error: Error) -> Self {
+            DiffError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod planning {
+    use super::*;
+    use crate::index::testing::{self, ALICE};
+
+    fn setup_alice(actions: Vec<Action>) -> Index {
+        testing::setup_index(&[], actions.into_iter().map(|action| (ALICE, action)).collect())
+    }
+
+    fn setup_manifest() -> RepositoryManifest {
+        RepositoryManifest {
+            version: "0.1.0".to_string(),
+            name: "repoint".to_string(),
+            account: Some("7db9a".to_string()),
+            description: Some("Point to your repos.".to_string()),
+            tags: vec!["git".to_string(), "bitcoin".to_string()],
+            urls: vec!["https://github.com/7db9a/repoint".to_string()],
+            ..RepositoryManifest::default()
+        }
+    }
+
+    fn opcodes(plan: &Plan) -> Vec<Opcode> {
+        plan.actions.iter().map(|a| a.opcode).collect()
+    }
+
+    #[test]
+    fn plan_new_account_and_repo() {
+        let plan = plan(&Index::new(), ALICE, None, &setup_manifest()).unwrap();
+
+        assert_eq!(
+            opcodes(&plan),
+            vec![
+                Opcode::Instantiate,
+                Opcode::CreateAccount,
+                Opcode::CreateRepo,
+                Opcode::RepoDescription,
+                Opcode::AddRepoTags,
+                Opcode::AddRepoTags,
+                Opcode::AddRepoUrl,
+            ]
+        );
+        assert_eq!(plan.repo_index, 0);
+        assert_eq!(plan.lines()[6], "+ 0x7209 add repo url [0] https://github.com/7db9a/repoint");
+    }
+
+    #[test]
+    fn plan_only_changes() {
+        let index = setup_alice(vec![
+            Action::instantiate(),
+            Action::create_account("7db9a").unwrap(),
+            Action::create_repo("notes").unwrap(),
+            Action::create_repo("repoint").unwrap(),
+            Action::builder(Opcode::AddRepoTags).repo_index(1).message("git").build().unwrap(),
+            Action::builder(Opcode::AddRepoTags).repo_index(1).message("rust").build().unwrap(),
+            Action::add_repo_url(1, "https://gitlab.com/7db9a/repoint").unwrap(),
+        ]);
+        let plan = plan(&index, ALICE, None, &setup_manifest()).unwrap();
+
+        assert_eq!(
+            plan.lines(),
+            vec![
+                "~ 0x7207 new repo description [1] Point to your repos.",
+                "+ 0x7208 add repo tags [1] bitcoin",
+                "- 0x7210 remove repo url [1] https://gitlab.com/7db9a/repoint",
+                "+ 0x7209 add repo url [1] https://github.com/7db9a/repoint",
+            ]
+        );
+        assert_eq!(plan.warnings, vec!["tag rust can't be removed on chain"]);
+    }
+
+    #[test]
+    fn plan_up_to_date() {
+        let manifest = setup_manifest();
+        let mut actions = vec![Action::instantiate(), Action::create_account("7db9a").unwrap()];
+        actions.extend(plan(&Index::new(), ALICE, None, &manifest).unwrap().actions.into_iter().skip(2));
+        let index = setup_alice(actions);
+
+        assert!(plan(&index, ALICE, None, &manifest).unwrap().is_empty());
+    }
+
+    #[test]
+    fn plan_dedupes_manifest() {
+        let mut manifest = setup_manifest();
+        manifest.tags.push("git".to_string());
+        manifest.urls.push(manifest.urls[0].clone());
+        let plan = plan(&Index::new(), ALICE, None, &manifest).unwrap();
+
+        assert_eq!(opcodes(&plan).iter().filter(|o| **o == Opcode::AddRepoTags).count(), 2);
+        assert_eq!(opcodes(&plan).iter().filter(|o| **o == Opcode::AddRepoUrl).count(), 1);
+    }
+
+    #[test]
+    fn plan_refuses_disqualified_address() {
+        let index = setup_alice(vec![Action::create_account("7db9a").unwrap()]);
+
+        match plan(&index, ALICE, None, &setup_manifest()).unwrap_err() {
+            DiffError::Error(e) => assert_eq!(e.kind, ErrorKind::Disqualified),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn plan_refuses_unnamed_account() {
+        let mut manifest = setup_manifest();
+        manifest.account = None;
+
+        match plan(&Index::new(), ALICE, None, &manifest).unwrap_err() {
+            DiffError::Error(e) => assert_eq!(e.kind, ErrorKind::NoName),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn plan_profile_changes() {
+        let avatar = "https://example.com/7db9a.png";
+        let plan = plan_profile(&Index::new(), ALICE, Some("7db9a"), Some("Rust and BSV."), Some(avatar)).unwrap();
+
+        assert_eq!(
+            opcodes(&plan),
+            vec![Opcode::Instantiate, Opcode::CreateAccount, Opcode::ProfileText, Opcode::ProfilePic]
+        );
+
+        let index = setup_alice(plan.actions);
+        let unchanged = plan_profile(&index, ALICE, None, Some("Rust and BSV."), Some(avatar)).unwrap();
+        assert!(unchanged.is_empty());
+        assert!(unchanged.warnings.is_empty());
+    }
+
+    #[test]
+    fn plan_profile_cant_remove() {
+        let index = setup_alice(vec![
+            Action::instantiate(),
+            Action::create_account("7db9a").unwrap(),
+            Action::builder(Opcode::ProfileText).message("Rust and BSV.").build().unwrap(),
+        ]);
+        let plan = plan_profile(&index, ALICE, None, None, None).unwrap();
+
+        assert!(plan.is_empty());
+        assert_eq!(plan.warnings, vec!["profile text can't be removed, only replaced"]);
+    }
+}
@@ -0,0 +1,1031 @@
+/*
+This module replays repoint op-returns into account and repo state, following the protocol rules in the README.
+*/
+extern crate toml;
+
+use crate::backend::{ChainBackend, ChainTx};
+use crate::decode;
+use crate::key;
+use crate::opcode::{self, Action, Opcode};
+use crate::tx::{self, Transaction};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+use err::Error;
+pub use err::{ErrorKind, IndexError};
+
+/// A transaction as the indexer sees it: who sent it and its raw hex.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceTx {
+    pub txid: String,
+    /// The address that signed the transaction, i.e. the acting account.
+    pub address: String,
+    /// Block height, or `None` while unconfirmed.
+    pub height: Option<u64>,
+    pub hex: String,
+}
+
+/// Where the indexer gets its transactions from. Implementations must
+/// yield each address's transactions in the order they were made.
+pub trait TxSource {
+    fn transactions(&mut self) -> Result<Vec<SourceTx>, IndexError>;
+}
+
+impl TxSource for Vec<SourceTx> {
+    fn transactions(&mut self) -> Result<Vec<SourceTx>, IndexError> {
+        Ok(self.clone())
+    }
+}
+
+/// Reads transactions from a toml file of `[[tx]]` tables, for offline use and tests.
+///  ```ignore
+///  [[tx]]
+///  txid = "d3ff07f8..."
+///  address = "1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt"
+///  height = 650000
+///  hex = "0100000000..."
+///  ```
+pub struct FixtureSource {
+    path: PathBuf,
+}
+
+impl FixtureSource {
+    pub fn new<T: AsRef<str>>(path: T) -> FixtureSource {
+        FixtureSource {
+            path: PathBuf::from(path.as_ref()),
+        }
+    }
+}
+
+impl TxSource for FixtureSource {
+    fn transactions(&mut self) -> Result<Vec<SourceTx>, IndexError> {
+        let data = read_to_string(&self.path)?;
+        parse_fixture(&data)
+    }
+}
+
+/// Reads the repoint transactions of `addresses` from a chain backend.
+pub struct ChainSource<'a> {
+    pub backend: &'a dyn ChainBackend,
+    pub addresses: Vec<String>,
+    /// Transactions fetched so far by txid. Histories share them, and each one's parent is
+    /// usually the one before it, so each is only fetched once for the life of the source.
+    fetched: BTreeMap<String, ChainTx>,
+}
+
+impl<'a> TxSource for ChainSource<'a> {
+    fn transactions(&mut self) -> Result<Vec<SourceTx>, IndexError> {
+        let mut txs = vec![];
+        for address in self.addresses.clone().iter() {
+            for tx_ref in self.backend.address_history(address)?.iter() {
+                let tx = self.get_tx(&tx_ref.txid)?;
+                // Histories include payments to the address; only what it provably signed is its own.
+                if self.signed_by(&tx.hex, address)? {
+                    txs.push(SourceTx {
+                        txid: tx.txid,
+                        address: address.clone(),
+                        height: tx.height,
+                        hex: tx.hex,
+                    });
+                }
+            }
+        }
+
+        Ok(txs)
+    }
+}
+
+impl<'a> ChainSource<'a> {
+    pub fn new(backend: &'a dyn ChainBackend, addresses: Vec<String>) -> ChainSource<'a> {
+        ChainSource {
+            backend,
+            addresses,
+            fetched: BTreeMap::new(),
+        }
+    }
+
+    fn get_tx(&mut self, txid: &str) -> Result<ChainTx, IndexError> {
+        if let Some(tx) = self.fetched.get(txid) {
+            return Ok(tx.clone());
+        }
+        let tx = self.backend.get_tx(txid)?;
+        self.fetched.insert(txid.to_string(), tx.clone());
+
+        Ok(tx)
+    }
+
+    /// Whether the first input of `hex` is a P2PKH spend of `address`'s coins with a valid signature.
+    /// Anything else, like an empty scriptSig spending an anyone-can-spend output, doesn't count.
+    fn signed_by(&mut self, hex: &str, address: &str) -> Result<bool, IndexError> {
+        let tx = match Transaction::from_hex(hex) {
+            Ok(tx) if !tx.inputs.is_empty() => tx,
+            _ => return Ok(false),
+        };
+        let input = &tx.inputs[0];
+        let (sig, pubkey) = match tx::script_pushes(&input.script_sig).as_deref() {
+            Ok([sig, pubkey]) if !sig.is_empty() => (sig.clone(), pubkey.clone()),
+            _ => return Ok(false),
+        };
+        let pubkey_hash = key::hash160(&pubkey);
+        if !key::address_hash(address).is_ok_and(|(_, hash)| hash == pubkey_hash) {
+            return Ok(false);
+        }
+
+        let parent = self.get_tx(&tx::display_txid(&input.prev_txid))?;
+        let spent = match Transaction::from_hex(&parent.hex).map(|p| p.outputs.get(input.prev_vout as usize).cloned()) {
+            Ok(Some(spent)) if tx::p2pkh_hash(&spent.script_pubkey) == Some(&pubkey_hash[..]) => spent,
+            _ => return Ok(false),
+        };
+        let (der, sighash_type) = sig.split_at(sig.len() - 1);
+        let hash = tx.sighash(0, &spent.script_pubkey, spent.value, sighash_type[0] as u32);
+
+        Ok(key::verify(&pubkey, &hash, der))
+    }
+}
+
+/// Reorders `txs` so none comes before a transaction whose output it spends, and otherwise
+/// keeps them as given. The spending chain is the canonical order of an address's actions:
+/// chained transactions mined in the same block, or still in the mempool, can be listed in any order.
+pub fn spend_order(txs: Vec<SourceTx>) -> Vec<SourceTx> {
+    let position: BTreeMap<String, usize> = txs.iter().enumerate().map(|(i, tx)| (tx.txid.clone(), i)).collect();
+    let mut children = vec![vec![]; txs.len()];
+    let mut waiting = vec![0; txs.len()];
+    for (i, tx) in txs.iter().enumerate() {
+        let inputs = Transaction::from_hex(&tx.hex).map(|decoded| decoded.inputs).unwrap_or_default();
+        let parents: BTreeSet<usize> = inputs
+            .iter()
+            .filter_map(|input| position.get(&tx::display_txid(&input.prev_txid)).copied())
+            .filter(|parent| *parent != i)
+            .collect();
+        for parent in parents.iter() {
+            children[*parent].push(i);
+        }
+        waiting[i] = parents.len();
+    }
+
+    let mut ready: BTreeSet<usize> = (0..txs.len()).filter(|i| waiting[*i] == 0).collect();
+    let mut order = vec![];
+    while let Some(i) = ready.iter().next().copied() {
+        ready.remove(&i);
+        order.push(i);
+        for child in children[i].iter() {
+            waiting[*child] -= 1;
+            if waiting[*child] == 0 {
+                ready.insert(*child);
+            }
+        }
+    }
+
+    let mut txs: Vec<Option<SourceTx>> = txs.into_iter().map(Some).collect();
+    let mut ordered: Vec<SourceTx> = order.into_iter().filter_map(|i| txs[i].take()).collect();
+    // Only made up txids can spend each other in a cycle. Those keep their place at the end.
+    ordered.extend(txs.into_iter().flatten());
+    ordered
+}
+
+pub fn parse_fixture<T: AsRef<str>>(data: T) -> Result<Vec<SourceTx>, IndexError> {
+    let value = data.as_ref().parse::<toml::Value>()?;
+    let empty = vec![];
+    let entries = match value.get("tx") {
+        Some(toml::Value::Array(entries)) => entries,
+        None => &empty,
+        Some(_) => {
+            let err = Error::new("`tx` must be an array of tables", ErrorKind::InvalidFixture);
+            return Err(IndexError::from(err));
+        }
+    };
+
+    let mut txs = vec![];
+    for entry in entries.iter() {
+        let field = |key: &str| -> Result<String, IndexError> {
+            match entry.get(key).and_then(|v| v.as_str()) {
+                Some(s) => Ok(s.to_string()),
+                None => {
+                    let err = Error::new(&format!("tx entry missing `{}`", key), ErrorKind::InvalidFixture);
+                    Err(IndexError::from(err))
+                }
+            }
+        };
+        let height = entry.get("height").and_then(|h| h.as_integer()).map(|h| h as u64);
+
+        txs.push(SourceTx {
+            txid: field("txid")?,
+            address: field("address")?,
+            height,
+            hex: field("hex")?,
+        });
+    }
+
+    Ok(txs)
+}
+
+/// Points at a repo: the owning account's address and the repo's index in it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RepoRef {
+    pub address: String,
+    pub index: u64,
+}
+
+impl RepoRef {
+    pub fn new<T: AsRef<str>>(address: T, index: u64) -> RepoRef {
+        RepoRef {
+            address: address.as_ref().to_string(),
+            index,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Account {
+    pub address: String,
+    /// Set once by `Create account`; `None` until then.
+    pub name: Option<String>,
+    pub profile: Option<String>,
+    pub avatar: Option<String>,
+    pub repos: Vec<Repo>,
+    pub redirect: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Repo {
+    pub index: u64,
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub urls: Vec<String>,
+    pub redirect: Option<RepoRef>,
+}
+
+/// An op-return that was on chain but broke a protocol rule, so it has no effect.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    pub txid: String,
+    pub address: String,
+    pub reason: String,
+}
+
+/// Materialized repoint state.
+#[derive(Clone, Debug, Default)]
+pub struct Index {
+    /// Every address seen, and whether its first op-return instantiated repoint.
+    instantiated: BTreeMap<String, bool>,
+    accounts: BTreeMap<String, Account>,
+    /// Lowercased names to what carries them, for exact and prefix lookups.
+    account_names: BTreeMap<String, BTreeSet<String>>,
+    repo_names: BTreeMap<String, BTreeSet<RepoRef>>,
+    likes: BTreeMap<RepoRef, BTreeSet<String>>,
+    follows: BTreeMap<RepoRef, BTreeSet<String>>,
+    flags: BTreeMap<RepoRef, BTreeSet<String>>,
+    /// Satoshis paid to each repo's owner, per tipping address.
+    tips: BTreeMap<RepoRef, BTreeMap<String, u64>>,
+    rejected: Vec<Rejection>,
+    txids: BTreeSet<String>,
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index::default()
+    }
+
+    /// Replays `source` block by block, unconfirmed last, so an edge to another account's
+    /// repo comes after the repo was created. Within a block, spends come after what they spend.
+    pub fn build<S: TxSource>(source: &mut S) -> Result<Index, IndexError> {
+        let mut index = Index::new();
+        let mut txs = source.transactions()?;
+        txs.sort_by_key(|tx| tx.height.unwrap_or(u64::MAX));
+        for tx in spend_order(txs).iter() {
+            index.apply_tx(tx);
+        }
+
+        Ok(index)
+    }
+
+    /// Builds an index from `actions`, each in a confirmed transaction of its own
+    /// from the address beside it, in order.
+    #[cfg(test)]
+    pub fn from_actions(actions: &[(&str, Action)]) -> Index {
+        let mut txs: Vec<SourceTx> = actions
+            .iter()
+            .enumerate()
+            .map(|(i, (address, action))| SourceTx {
+                txid: i.to_string(),
+                address: address.to_string(),
+                height: Some(1),
+                hex: crate::sign(action),
+            })
+            .collect();
+
+        Index::build(&mut txs).unwrap()
+    }
+
+    /// Decodes `tx` and applies its actions in output order. Undecodable
+    /// repoint data is recorded as a rejection, output by output, rather
+    /// than failing the build or the rest of the transaction.
+    pub fn apply_tx(&mut self, tx: &SourceTx) {
+        self.txids.insert(tx.txid.clone());
+        let transaction = match Transaction::from_hex(&tx.hex) {
+            Ok(transaction) => transaction,
+            Err(e) => return self.reject(tx, format!("undecodable: {}", e)),
+        };
+
+        let mut paid = BTreeSet::new();
+        for decoded in decode::decode_outputs(&transaction) {
+            let applied = decoded
+                .map_err(|e| format!("undecodable: {}", e))
+                .and_then(|action| {
+                    self.apply(&tx.address, &action)?;
+                    match action.opcode {
+                        Opcode::TipRepo => self.tip(&tx.address, &action, &transaction, &mut paid),
+                        _ => Ok(()),
+                    }
+                });
+            if let Err(reason) = applied {
+                self.reject(tx, reason);
+            }
+        }
+    }
+
+    /// Credits a tip with what `transaction` pays the repo's owner. An owner's
+    /// payment only counts towards the first tip in the transaction naming them.
+    fn tip(&mut self, from: &str, action: &Action, transaction: &Transaction, paid: &mut BTreeSet<String>) -> Result<(), String> {
+        let repo = RepoRef::new(action.address.clone().unwrap_or_default(), action.repo_index.unwrap_or_default());
+        let (_, owner_hash) = key::address_hash(&repo.address).map_err(|e| format!("can't pay {}: {}", repo.address, e))?;
+        if !paid.insert(repo.address.clone()) {
+            return Err(format!("{} was already tipped by this transaction", repo.address));
+        }
+        let amount: u64 = transaction
+            .outputs
+            .iter()
+            .filter(|output| tx::p2pkh_hash(&output.script_pubkey) == Some(owner_hash.as_slice()))
+            .map(|output| output.value)
+            .sum();
+        if amount == 0 {
+            return Err("tip doesn't pay the repo's owner".to_string());
+        }
+
+        *self.tips.entry(repo).or_default().entry(from.to_string()).or_default() += amount;
+        Ok(())
+    }
+
+    fn reject(&mut self, tx: &SourceTx, reason: String) {
+        self.rejected.push(Rejection {
+            txid: tx.txid.clone(),
+            address: tx.address.clone(),
+            reason,
+        });
+    }
+
+    /// Applies one action from `address`. 'Create' actions happen once,
+    /// 'new' actions overwrite and 'add' actions accumulate.
+    pub fn apply<T: AsRef<str>>(&mut self, address: T, action: &Action) -> Result<(), String> {
+        let address = address.as_ref();
+
+        match self.instantiated.get(address) {
+            None => {
+                let is_app = action.opcode == Opcode::Instantiate
+                    && action.app_id.as_deref() == Some(opcode::APP_ID);
+                self.instantiated.insert(address.to_string(), is_app);
+                if !is_app {
+                    return Err("address didn't instantiate repoint first".to_string());
+                }
+                self.accounts.insert(
+                    address.to_string(),
+                    Account {
+                        address: address.to_string(),
+                        name: None,
+                        profile: None,
+                        avatar: None,
+                        repos: vec![],
+                        redirect: None,
+                    },
+                );
+                return Ok(());
+            }
+            Some(false) => return Err("address isn't a repoint account".to_string()),
+            Some(true) => (),
+        }
+
+        let account = self.accounts.get_mut(address).expect("instantiated address has an account");
+        if action.opcode == Opcode::Instantiate {
+            return Err("repoint is already instantiated".to_string());
+        }
+        if action.opcode == Opcode::CreateAccount {
+            if account.name.is_some() {
+                return Err("account names can't be changed".to_string());
+            }
+            account.name = action.message.clone();
+            let key = account.name.clone().unwrap_or_default().to_lowercase();
+            self.account_names.entry(key).or_default().insert(address.to_string());
+            return Ok(());
+        }
+        if account.name.is_none() {
+            return Err("account hasn't been created".to_string());
+        }
+
+        let target = || RepoRef::new(action.address.clone().unwrap_or_default(), action.repo_index.unwrap_or_default());
+        match action.opcode {
+            Opcode::ProfileText => account.profile = action.message.clone(),
+            Opcode::ProfilePic => account.avatar = action.message.clone(),
+            Opcode::CreateRepo => {
+                let name = action.message.clone().unwrap_or_default();
+                if account.repos.iter().any(|r| r.name.to_lowercase() == name.to_lowercase()) {
+                    return Err(format!("repo {} already exists", name));
+                }
+                let index = account.repos.len() as u64;
+                self.repo_names
+                    .entry(name.to_lowercase())
+                    .or_default()
+                    .insert(RepoRef::new(address, index));
+                account.repos.push(Repo {
+                    index,
+                    name,
+                    description: None,
+                    tags: vec![],
+                    urls: vec![],
+                    redirect: None,
+                });
+            }
+            Opcode::RepoDescription
+            | Opcode::AddRepoTags
+            | Opcode::AddRepoUrl
+            | Opcode::RemoveRepoUrl => {
+                let index = action.repo_index.unwrap_or_default();
+                let repo = match account.repos.get_mut(index as usize) {
+                    Some(repo) => repo,
+                    None => return Err(format!("no repo at index {}", index)),
+                };
+                let message = action.message.clone().unwrap_or_default();
+                match action.opcode {
+                    Opcode::RepoDescription => repo.description = Some(message),
+                    Opcode::AddRepoTags => add_unique(&mut repo.tags, message),
+                    Opcode::AddRepoUrl => add_unique(&mut repo.urls, message),
+                    _ => {
+                        let before = repo.urls.len();
+                        repo.urls.retain(|u| *u != message);
+                        if repo.urls.len() == before {
+                            return Err(format!("repo has no url {}", message));
+                        }
+                    }
+                }
+            }
+            Opcode::RedirectAccount => account.redirect = action.address.clone(),
+            Opcode::RedirectRepo => {
+                let index = action.repo_index.unwrap_or_default();
+                let to = RepoRef::new(action.address.clone().unwrap_or_default(), action.target_index.unwrap_or_default());
+                match account.repos.get_mut(index as usize) {
+                    Some(repo) => repo.redirect = Some(to),
+                    None => return Err(format!("no repo at index {}", index)),
+                }
+            }
+            Opcode::LikeRepo
+            | Opcode::UnlikeRepo
+            | Opcode::FollowRepo
+            | Opcode::UnfollowRepo
+            | Opcode::FlagRepo
+            | Opcode::UnflagRepo => {
+                let repo = self.known(target())?;
+                match action.opcode {
+                    Opcode::LikeRepo => add_edge(&mut self.likes, repo, address)?,
+                    Opcode::UnlikeRepo => remove_edge(&mut self.likes, repo, address)?,
+                    Opcode::FollowRepo => add_edge(&mut self.follows, repo, address)?,
+                    Opcode::UnfollowRepo => remove_edge(&mut self.follows, repo, address)?,
+                    Opcode::FlagRepo => add_edge(&mut self.flags, repo, address)?,
+                    _ => remove_edge(&mut self.flags, repo, address)?,
+                }
+            }
+            // What a tip is worth is credited by `apply_tx`, which sees the payment.
+            Opcode::TipRepo => {
+                if self.known(target())?.address == address {
+                    return Err("can't tip your own repo".to_string());
+                }
+            }
+            Opcode::Instantiate | Opcode::CreateAccount => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    // `repo`, unless its owner is indexed and never created it. Owners outside the index,
+    // like those of followed repos before they're watched, can't be checked.
+    fn known(&self, repo: RepoRef) -> Result<RepoRef, String> {
+        if self.account(&repo.address).is_some() && self.repo(&repo).is_none() {
+            return Err(format!("no repo {}:{}", repo.address, repo.index));
+        }
+
+        Ok(repo)
+    }
+
+    pub fn account<T: AsRef<str>>(&self, address: T) -> Option<&Account> {
+        self.accounts.get(address.as_ref())
+    }
+
+    /// Whether `address` can never be a repoint account, because its first
+    /// repoint op-return wasn't a valid instantiation.
+    pub fn disqualified<T: AsRef<str>>(&self, address: T) -> bool {
+        self.instantiated.get(address.as_ref()) == Some(&false)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
+    }
+
+    pub fn repo(&self, repo: &RepoRef) -> Option<&Repo> {
+        self.account(&repo.address)
+            .and_then(|a| a.repos.get(repo.index as usize))
+    }
+
+    /// Every repo, in name order.
+    pub fn all_repos(&self) -> Vec<&RepoRef> {
+        self.repo_names.values().flat_map(|refs| refs.iter()).collect()
+    }
+
+    /// Addresses of accounts named `name`, ignoring case.
+    pub fn accounts_named<T: AsRef<str>>(&self, name: T) -> Vec<&String> {
+        self.account_names
+            .get(&name.as_ref().to_lowercase())
+            .map(|s| s.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Repos named exactly `name`, ignoring case.
+    pub fn repos_named<T: AsRef<str>>(&self, name: T) -> Vec<&RepoRef> {
+        self.repo_names
+            .get(&name.as_ref().to_lowercase())
+            .map(|s| s.iter().collect())
+            .unwrap_or_default()
+    }
+
+    /// Repos whose name starts with `prefix`, ignoring case, in name order.
+    pub fn repos_with_prefix<T: AsRef<str>>(&self, prefix: T) -> Vec<&RepoRef> {
+        let prefix = prefix.as_ref().to_lowercase();
+        self.repo_names
+            .range(prefix.clone()..)
+            .take_while(|(name, _)| name.starts_with(&prefix))
+            .flat_map(|(_, refs)| refs.iter())
+            .collect()
+    }
+
+    /// Addresses that currently like `repo`.
+    pub fn likes(&self, repo: &RepoRef) -> Vec<&String> {
+        self.likes.get(repo).map(|s| s.iter().collect()).unwrap_or_default()
+    }
+
+    /// How many addresses currently like `repo`.
+    pub fn like_count(&self, repo: &RepoRef) -> usize {
+        self.likes.get(repo).map(|s| s.len()).unwrap_or_default()
+    }
+
+    /// Addresses that currently follow `repo`.
+    pub fn followers(&self, repo: &RepoRef) -> Vec<&String> {
+        self.follows.get(repo).map(|s| s.iter().collect()).unwrap_or_default()
+    }
+
+    /// Repos `address` currently follows.
+    pub fn following<T: AsRef<str>>(&self, address: T) -> Vec<&RepoRef> {
+        self.follows
+            .iter()
+            .filter(|(_, from)| from.contains(address.as_ref()))
+            .map(|(repo, _)| repo)
+            .collect()
+    }
+
+    /// Addresses that currently flag `repo`.
+    pub fn flags(&self, repo: &RepoRef) -> Vec<&String> {
+        self.flags.get(repo).map(|s| s.iter().collect()).unwrap_or_default()
+    }
+
+    /// Who has tipped `repo` and how many satoshis each, in address order.
+    pub fn tips(&self, repo: &RepoRef) -> Vec<(&String, u64)> {
+        self.tips.get(repo).map(|t| t.iter().map(|(from, sats)| (from, *sats)).collect()).unwrap_or_default()
+    }
+
+    /// Satoshis tipped to `repo` altogether.
+    pub fn tip_total(&self, repo: &RepoRef) -> u64 {
+        self.tips.get(repo).map(|t| t.values().sum()).unwrap_or_default()
+    }
+
+    /// Repos `address` has tipped and how many satoshis each.
+    pub fn tipped<T: AsRef<str>>(&self, address: T) -> Vec<(&RepoRef, u64)> {
+        self.tips
+            .iter()
+            .filter_map(|(repo, t)| t.get(address.as_ref()).map(|sats| (repo, *sats)))
+            .collect()
+    }
+
+    /// Whether `txid` has been applied, accepted or not.
+    pub fn has_tx<T: AsRef<str>>(&self, txid: T) -> bool {
+        self.txids.contains(txid.as_ref())
+    }
+
+    pub fn rejected(&self) -> &[Rejection] {
+        &self.rejected
+    }
+}
+
+/// Addresses and an index factory shared by the tests of every module that reads an index.
+#[cfg(test)]
+pub mod testing {
+    use super::Index;
+    use crate::opcode::Action;
+
+    /// Alice's key, the uncompressed WIF example from the bitcoin wiki.
+    pub const PRIVKEY: &str = "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ";
+    pub const ALICE: &str = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S";
+    pub const BOB: &str = "19HxigV4QyBv3tHpQVcUEQyq1pzZVdoAut";
+    pub const CAROL: &str = "1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt";
+
+    /// Creates each `(address, account name, repo names)` account with its repos, in order,
+    /// then applies `extra` on top.
+    pub fn setup_index(accounts: &[(&str, &str, &[&str])], extra: Vec<(&str, Action)>) -> Index {
+        let mut actions = vec![];
+        for (address, name, repos) in accounts.iter() {
+            actions.push((*address, Action::instantiate()));
+            actions.push((*address, Action::create_account(name).unwrap()));
+            for repo in repos.iter() {
+                actions.push((*address, Action::create_repo(repo).unwrap()));
+            }
+        }
+        actions.extend(extra);
+
+        Index::from_actions(&actions)
+    }
+}
+
+fn add_unique(list: &mut Vec<String>, item: String) {
+    if !list.contains(&item) {
+        list.push(item);
+    }
+}
+
+fn add_edge(edges: &mut BTreeMap<RepoRef, BTreeSet<String>>, repo: RepoRef, from: &str) -> Result<(), String> {
+    if edges.entry(repo).or_default().insert(from.to_string()) {
+        Ok(())
+    } else {
+        Err("edge already exists".to_string())
+    }
+}
+
+fn remove_edge(edges: &mut BTreeMap<RepoRef, BTreeSet<String>>, repo: RepoRef, from: &str) -> Result<(), String> {
+    let removed = match edges.get_mut(&repo) {
+        Some(set) => set.remove(from),
+        None => false,
+    };
+    if removed {
+        Ok(())
+    } else {
+        Err("edge doesn't exist".to_string())
+    }
+}
+
+mod err {
+    pub use crate::backend::BackendError;
+    pub use toml::de::Error as TomlError;
+
+    #[derive(Debug)]
+    pub enum IndexError {
+        IoError(std::io::Error),
+        TomlError(TomlError),
+        BackendError(BackendError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        InvalidFixture,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for IndexError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                IndexError::IoError(e) => write!(f, "{}", e),
+                IndexError::TomlError(e) => write!(f, "{}", e),
+                IndexError::BackendError(e) => write!(f, "{}", e),
+                IndexError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for IndexError {
+        fn from(error: std::io::Error) -> Self {
+            IndexError::IoError(error)
+        }
+    }
+
+    impl From<TomlError> for IndexError {
+        fn from(error: TomlError) -> Self {
+            IndexError::TomlError(error)
+        }
+    }
+
+    impl From<BackendError> for IndexError {
+        fn from(error: BackendError) -> Self {
+            IndexError::BackendError(error)
+        }
+    }
+
+    impl From<Error> for IndexError {
+        fn from(error: Error) -> Self {
+            IndexError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod replay {
+    use super::*;
+    use super::testing::{ALICE, BOB, CAROL, PRIVKEY};
+    use crate::mock_chain::MockChain;
+    use crate::sign;
+
+    fn tx(txid: &str, address: &str, action: Action) -> SourceTx {
+        SourceTx {
+            txid: txid.to_string(),
+            address: address.to_string(),
+            height: Some(1),
+            hex: sign(&action),
+        }
+    }
+
+    fn alice_with_repo() -> Vec<SourceTx> {
+        vec![
+            tx("a0", ALICE, Action::instantiate()),
+            tx("a1", ALICE, Action::create_account("7db9a").unwrap()),
+            tx("a2", ALICE, Action::create_repo("repoint").unwrap()),
+            tx("a3", ALICE, Action::add_repo_url(0, "https://github.com/7db9a/repoint").unwrap()),
+        ]
+    }
+
+    #[test]
+    fn replay_account_and_repo() {
+        let mut txs = alice_with_repo();
+        txs.push(tx("a4", ALICE, Action::builder(Opcode::RepoDescription).repo_index(0).message("old").build().unwrap()));
+        txs.push(tx("a5", ALICE, Action::builder(Opcode::RepoDescription).repo_index(0).message("new").build().unwrap()));
+        txs.push(tx("a6", ALICE, Action::create_repo("repoint-demo").unwrap()));
+
+        let index = Index::build(&mut txs).unwrap();
+        let account = index.account(ALICE).unwrap();
+
+        assert_eq!(account.name, Some("7db9a".to_string()));
+        assert_eq!(account.repos.len(), 2);
+        assert_eq!(account.repos[0].urls, vec!["https://github.com/7db9a/repoint"]);
+        assert_eq!(account.repos[0].description, Some("new".to_string()));
+        assert_eq!(account.repos[1].index, 1);
+        assert!(index.rejected().is_empty());
+        assert_eq!(index.accounts_named("7DB9A"), vec![ALICE]);
+        assert_eq!(index.repos_named("repoint"), vec![&RepoRef::new(ALICE, 0)]);
+        assert_eq!(index.repos_with_prefix("repoint").len(), 2);
+    }
+
+    #[test]
+    fn replay_enforces_protocol_rules() {
+        let mut txs = alice_with_repo();
+        txs.push(tx("a4", ALICE, Action::create_account("renamed").unwrap()));
+        txs.push(tx("a5", ALICE, Action::create_repo("Repoint").unwrap()));
+        txs.push(tx("a6", ALICE, Action::add_repo_url(7, "https://example.com").unwrap()));
+        // Bob never instantiated, so he isn't an account.
+        txs.push(tx("b0", BOB, Action::create_account("bob").unwrap()));
+        txs.push(tx("b1", BOB, Action::instantiate()));
+
+        let index = Index::build(&mut txs).unwrap();
+        let rejected: Vec<&str> = index.rejected().iter().map(|r| r.txid.as_str()).collect();
+
+        assert_eq!(index.account(ALICE).unwrap().name, Some("7db9a".to_string()));
+        assert_eq!(index.account(ALICE).unwrap().repos.len(), 1);
+        assert!(index.account(BOB).is_none());
+        assert_eq!(rejected, vec!["a4", "a5", "a6", "b0", "b1"]);
+    }
+
+    #[test]
+    fn replay_social_edges() {
+        let mut txs = alice_with_repo();
+        txs.push(tx("b0", BOB, Action::instantiate()));
+        txs.push(tx("b1", BOB, Action::create_account("bob").unwrap()));
+        let like = Action::builder(Opcode::LikeRepo).address(ALICE).repo_index(0).build().unwrap();
+        let unlike = Action::builder(Opcode::UnlikeRepo).address(ALICE).repo_index(0).build().unwrap();
+        let follow = Action::builder(Opcode::FollowRepo).address(ALICE).repo_index(0).build().unwrap();
+        txs.push(tx("b2", BOB, like.clone()));
+        txs.push(tx("b3", BOB, like));
+        txs.push(tx("b4", BOB, follow));
+        txs.push(tx("b5", BOB, unlike));
+        let missing = Action::builder(Opcode::FollowRepo).address(ALICE).repo_index(7).build().unwrap();
+        txs.push(tx("b6", BOB, missing));
+        let flag = Action::builder(Opcode::FlagRepo).address(ALICE).repo_index(9).build().unwrap();
+        txs.push(tx("b7", BOB, flag));
+        // Carol isn't indexed, so her repo can't be checked.
+        let unindexed = Action::builder(Opcode::LikeRepo).address(CAROL).repo_index(0).build().unwrap();
+        txs.push(tx("b8", BOB, unindexed));
+
+        let index = Index::build(&mut txs).unwrap();
+        let repo = RepoRef::new(ALICE, 0);
+
+        assert!(index.likes(&repo).is_empty());
+        assert_eq!(index.like_count(&repo), 0);
+        assert_eq!(index.followers(&repo), vec![BOB]);
+        assert_eq!(index.following(BOB), vec![&repo]);
+        assert_eq!(index.rejected().len(), 3);
+        assert_eq!(index.rejected()[1].reason, format!("no repo {}:7", ALICE));
+        assert_eq!(index.rejected()[2].reason, format!("no repo {}:9", ALICE));
+        assert!(index.flags(&RepoRef::new(ALICE, 9)).is_empty());
+        assert_eq!(index.likes(&RepoRef::new(CAROL, 0)), vec![BOB]);
+    }
+
+    #[test]
+    fn replay_tips() {
+        let mut txs = alice_with_repo();
+        txs.push(tx("b0", BOB, Action::instantiate()));
+        txs.push(tx("b1", BOB, Action::create_account("bob").unwrap()));
+        let tip = Action::builder(Opcode::TipRepo).address(ALICE).repo_index(0).build().unwrap();
+        let (_, alice_hash) = key::address_hash(ALICE).unwrap();
+        for (txid, sats) in [("b2", 1000), ("b3", 500)].iter() {
+            let mut paid = crate::opreturn_tx(std::slice::from_ref(&tip));
+            paid.add_output(tx::TxOut::p2pkh(*sats, &alice_hash));
+            txs.push(SourceTx { hex: paid.to_hex(), ..tx(txid, BOB, tip.clone()) });
+        }
+        // Without a payment, or to your own repo, a tip counts for nothing.
+        txs.push(tx("b4", BOB, tip.clone()));
+        txs.push(tx("a4", ALICE, tip));
+        // Nor does paying for a repo that doesn't exist.
+        let missing = Action::builder(Opcode::TipRepo).address(ALICE).repo_index(7).build().unwrap();
+        let mut paid = crate::opreturn_tx(std::slice::from_ref(&missing));
+        paid.add_output(tx::TxOut::p2pkh(1000, &alice_hash));
+        txs.push(SourceTx { hex: paid.to_hex(), ..tx("b5", BOB, missing) });
+
+        let index = Index::build(&mut txs).unwrap();
+        let repo = RepoRef::new(ALICE, 0);
+
+        assert_eq!(index.tips(&repo), vec![(&BOB.to_string(), 1500)]);
+        assert_eq!(index.tip_total(&repo), 1500);
+        assert_eq!(index.tipped(BOB), vec![(&repo, 1500)]);
+        assert_eq!(index.rejected().len(), 3);
+        assert_eq!(index.rejected()[2].reason, format!("no repo {}:7", ALICE));
+    }
+
+    #[test]
+    fn replay_skips_bad_outputs() {
+        let mut txs = alice_with_repo();
+        let mut batch = crate::opreturn_tx(&[Action::create_repo("notes").unwrap()]);
+        batch.add_output(tx::TxOut::opreturn(&[vec![0x72, 0xff, 0x00], b"garbage".to_vec()]));
+        txs.push(SourceTx { hex: batch.to_hex(), ..tx("a4", ALICE, Action::instantiate()) });
+
+        let index = Index::build(&mut txs).unwrap();
+
+        assert_eq!(index.account(ALICE).unwrap().repos[1].name, "notes");
+        assert_eq!(index.rejected().len(), 1);
+        assert!(index.rejected()[0].reason.starts_with("undecodable"));
+    }
+
+    // Counts the transactions a source fetches from the chain it wraps.
+    struct Counting {
+        chain: MockChain,
+        fetches: std::cell::Cell<usize>,
+    }
+
+    impl ChainBackend for Counting {
+        fn broadcast(&self, hex: &str) -> Result<String, crate::backend::BackendError> {
+            self.chain.broadcast(hex)
+        }
+
+        fn get_tx(&self, txid: &str) -> Result<ChainTx, crate::backend::BackendError> {
+            self.fetches.set(self.fetches.get() + 1);
+            self.chain.get_tx(txid)
+        }
+
+        fn address_history(&self, address: &str) -> Result<Vec<crate::backend::TxRef>, crate::backend::BackendError> {
+            self.chain.address_history(address)
+        }
+
+        fn utxos(&self, address: &str) -> Result<Vec<crate::backend::Utxo>, crate::backend::BackendError> {
+            self.chain.utxos(address)
+        }
+    }
+
+    #[test]
+    fn chain_source_fetches_each_tx_once() {
+        let backend = Counting { chain: MockChain::new(), fetches: std::cell::Cell::new(0) };
+        let key = key::PrivateKey::from_wif(PRIVKEY).unwrap();
+        let alice = key.address();
+        backend.chain.publish(&key, &[Action::instantiate(), Action::create_account("7db9a").unwrap()]).unwrap();
+        backend.chain.publish(&key, &[Action::create_repo("repoint").unwrap()]).unwrap();
+        backend.chain.publish(&key, &[Action::create_repo("notes").unwrap()]).unwrap();
+        let history = backend.chain.address_history(&alice).unwrap().len();
+
+        let mut source = ChainSource::new(&backend, vec![alice.clone(), alice.clone()]);
+        let index = Index::build(&mut source).unwrap();
+        Index::build(&mut source).unwrap();
+
+        assert_eq!(index.account(&alice).unwrap().repos.len(), 2);
+        assert_eq!(backend.fetches.get(), history);
+    }
+
+    #[test]
+    fn chain_source_needs_a_signature() {
+        let chain = MockChain::new();
+        let key = key::PrivateKey::from_wif(PRIVKEY).unwrap();
+        let alice = key.address();
+        chain.publish(&key, &[Action::instantiate(), Action::create_account("7db9a").unwrap()]).unwrap();
+
+        // Someone else spends a coin with an empty scriptSig, and one of Alice's coins with her
+        // public key but a made up signature, paying her so both show up in her history.
+        let forged = [vec![0x01, 0x41, 0x41], key.public_key()].concat();
+        for (owner, script_sig) in [(BOB.to_string(), vec![]), (alice.clone(), forged)].iter() {
+            let coin = chain.fund(owner, 10_000).unwrap();
+            let mut injected = crate::opreturn_tx(&[Action::create_repo("spam").unwrap()]);
+            injected.add_input(tx::TxIn {
+                prev_txid: tx::txid_bytes(&coin).unwrap(),
+                prev_vout: 0,
+                script_sig: script_sig.clone(),
+                sequence: u32::MAX,
+            });
+            injected.add_output(tx::TxOut::p2pkh(1000, &key::hash160(&key.public_key())));
+            chain.broadcast(&injected.to_hex()).unwrap();
+        }
+
+        let mut source = ChainSource::new(&chain, vec![alice.clone()]);
+        let index = Index::build(&mut source).unwrap();
+
+        assert_eq!(index.account(&alice).unwrap().name.as_deref(), Some("7db9a"));
+        assert!(index.account(&alice).unwrap().repos.is_empty());
+        assert!(index.rejected().is_empty());
+    }
+
+    #[test]
+    fn fixture_source() {
+        let fixture = format!(
+            r#"[[tx]]
+txid = "a0"
+address = "{}"
+height = 1
+hex = "{}"
+
+[[tx]]
+txid = "a1"
+address = "{}"
+hex = "{}""#,
+            ALICE,
+            sign(&Action::instantiate()),
+            ALICE,
+            sign(&Action::create_account("7db9a").unwrap()),
+        );
+
+        let txs = parse_fixture(fixture).unwrap();
+
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[1].height, None);
+        let index = Index::build(&mut txs.clone()).unwrap();
+        assert_eq!(index.account(ALICE).unwrap().name, Some("7db9a".to_string()));
+        assert!(parse_fixture("[[tx]]\ntxid = \"a0\"").is_err());
+    }
+
+    #[test]
+    fn replay_follows_spend_chain() {
+        let key = crate::key::PrivateKey::from_wif(PRIVKEY).unwrap();
+        let coin = crate::backend::Utxo {
+            txid: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string(),
+            vout: 0,
+            value: 10_000,
+            height: Some(1),
+        };
+        let actions = [Action::instantiate(), Action::create_account("7db9a").unwrap(), Action::create_repo("repoint").unwrap()];
+        let mut chain = crate::fund_actions(&actions, None, vec![coin], ALICE, 0.5, 1).unwrap();
+        crate::wallet::sign_chain(&mut chain, &key).unwrap();
+
+        // Listed newest first, as a mempool might.
+        let mut txs: Vec<SourceTx> = chain
+            .iter()
+            .rev()
+            .map(|funded| SourceTx {
+                txid: funded.tx.txid(),
+                address: ALICE.to_string(),
+                height: None,
+                hex: funded.tx.to_hex(),
+            })
+            .collect();
+        let index = Index::build(&mut txs).unwrap();
+
+        assert!(index.rejected().is_empty());
+        assert_eq!(index.account(ALICE).unwrap().repos[0].name, "repoint");
+    }
+}
@@ -0,0 +1,337 @@
+/*
+This module reads bitcoin private keys in wallet import format (WIF) and derives their P2PKH address.
+*/
+extern crate bs58;
+extern crate ripemd160;
+extern crate secp256k1;
+extern crate sha2;
+
+use ripemd160::Ripemd160;
+use secp256k1::{Message, PublicKey, Secp256k1, SecretKey, Signature};
+use sha2::{Digest, Sha256};
+
+use err::Error;
+pub use err::{ErrorKind, KeyError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+impl Network {
+    /// Version byte of WIF private keys.
+    pub fn wif_version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x80,
+            Network::Testnet => 0xef,
+        }
+    }
+
+    /// Version byte of P2PKH addresses.
+    pub fn p2pkh_version(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x00,
+            Network::Testnet => 0x6f,
+        }
+    }
+
+    fn from_wif_version(version: u8) -> Option<Network> {
+        [Network::Mainnet, Network::Testnet].iter().copied().find(|n| n.wif_version() == version)
+    }
+
+    fn from_p2pkh_version(version: u8) -> Option<Network> {
+        [Network::Mainnet, Network::Testnet].iter().copied().find(|n| n.p2pkh_version() == version)
+    }
+}
+
+/// A decoded private key. WIF records the network and whether the address uses
+/// the compressed or uncompressed public key, so we keep those with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrivateKey {
+    pub secret: SecretKey,
+    pub network: Network,
+    pub compressed: bool,
+}
+
+impl PrivateKey {
+    pub fn from_wif<T: AsRef<str>>(wif: T) -> Result<PrivateKey, KeyError> {
+        let payload = base58check_decode(wif.as_ref().trim())?;
+        let (version, body) = match payload.split_first() {
+            Some(split) => split,
+            None => return Err(invalid_wif("key is empty")),
+        };
+        let network = match Network::from_wif_version(*version) {
+            Some(network) => network,
+            None => return Err(invalid_wif(&format!("unknown wif version {:#04x}", version))),
+        };
+
+        let (secret, compressed) = match body.len() {
+            32 => (body, false),
+            33 if body[32] == 0x01 => (&body[..32], true),
+            _ => return Err(invalid_wif("wif must hold a 32 byte key")),
+        };
+
+        Ok(PrivateKey {
+            secret: SecretKey::from_slice(secret)?,
+            network,
+            compressed,
+        })
+    }
+
+    pub fn public_key(&self) -> Vec<u8> {
+        let secp = Secp256k1::signing_only();
+        let pubkey = PublicKey::from_secret_key(&secp, &self.secret);
+        if self.compressed {
+            pubkey.serialize().to_vec()
+        } else {
+            pubkey.serialize_uncompressed().to_vec()
+        }
+    }
+
+    /// The P2PKH address for this key, the `pubaddr` of account.toml.
+    pub fn address(&self) -> String {
+        p2pkh_address(&self.public_key(), self.network)
+    }
+
+    /// DER encoded signature of a 32 byte hash, deterministic (RFC6979) and low-S.
+    pub fn sign(&self, hash: &[u8]) -> Result<Vec<u8>, KeyError> {
+        let secp = Secp256k1::signing_only();
+        let message = Message::from_slice(hash)?;
+
+        Ok(secp.sign(&message, &self.secret).serialize_der().to_vec())
+    }
+}
+
+/// Whether `der_sig` is a valid signature of the 32 byte `hash` by `public_key`.
+pub fn verify(public_key: &[u8], hash: &[u8], der_sig: &[u8]) -> bool {
+    let secp = Secp256k1::verification_only();
+    match (PublicKey::from_slice(public_key), Message::from_slice(hash), Signature::from_der(der_sig)) {
+        (Ok(public_key), Ok(message), Ok(mut sig)) => {
+            sig.normalize_s();
+            secp.verify(&message, &sig, &public_key).is_ok()
+        }
+        _ => false,
+    }
+}
+
+/// The P2PKH address of a serialized public key.
+pub fn p2pkh_address(public_key: &[u8], network: Network) -> String {
+    hash_address(&hash160(public_key), network)
+}
+
+/// The P2PKH address paying to a 20 byte public key hash.
+pub fn hash_address(pubkey_hash: &[u8], network: Network) -> String {
+    let mut payload = vec![network.p2pkh_version()];
+    payload.extend_from_slice(pubkey_hash);
+
+    base58check_encode(&payload)
+}
+
+/// Checks `address` is a well formed P2PKH address and returns its network.
+pub fn validate_address<T: AsRef<str>>(address: T) -> Result<Network, KeyError> {
+    Ok(address_hash(address)?.0)
+}
+
+/// The network and public key hash of a P2PKH address.
+pub fn address_hash<T: AsRef<str>>(address: T) -> Result<(Network, Vec<u8>), KeyError> {
+    let payload = base58check_decode(address.as_ref().trim()).map_err(|_| invalid_address(address.as_ref()))?;
+    if payload.len() != 21 {
+        return Err(invalid_address(address.as_ref()));
+    }
+    let network = Network::from_p2pkh_version(payload[0]).ok_or_else(|| invalid_address(address.as_ref()))?;
+
+    Ok((network, payload[1..].to_vec()))
+}
+
+/// Decodes `wif` and checks it's the key for `address`.
+pub fn check_address<T: AsRef<str>>(wif: T, address: T) -> Result<PrivateKey, KeyError> {
+    validate_address(address.as_ref())?;
+    let key = PrivateKey::from_wif(wif)?;
+    if key.address() != address.as_ref().trim() {
+        let err = Error::new(
+            &format!("private key doesn't belong to {}", address.as_ref()),
+            ErrorKind::AddressMismatch,
+        );
+        return Err(KeyError::from(err));
+    }
+
+    Ok(key)
+}
+
+pub fn sha256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+pub fn sha256d(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(&Sha256::digest(data)).to_vec()
+}
+
+pub fn hash160(data: &[u8]) -> Vec<u8> {
+    Ripemd160::digest(&Sha256::digest(data)).to_vec()
+}
+
+pub fn base58check_encode(payload: &[u8]) -> String {
+    let mut data = payload.to_vec();
+    data.extend_from_slice(&sha256d(payload)[..4]);
+
+    bs58::encode(data).into_string()
+}
+
+/// Decodes base58 and strips the 4 byte checksum after verifying it.
+pub fn base58check_decode<T: AsRef<str>>(encoded: T) -> Result<Vec<u8>, KeyError> {
+    let data = bs58::decode(encoded.as_ref()).into_vec()?;
+    if data.len() < 4 {
+        let err = Error::new("base58check data is too short", ErrorKind::InvalidChecksum);
+        return Err(KeyError::from(err));
+    }
+
+    let (payload, checksum) = data.split_at(data.len() - 4);
+    if sha256d(payload)[..4] != *checksum {
+        let err = Error::new("base58check checksum doesn't match", ErrorKind::InvalidChecksum);
+        return Err(KeyError::from(err));
+    }
+
+    Ok(payload.to_vec())
+}
+
+fn invalid_wif(msg: &str) -> KeyError {
+    KeyError::from(Error::new(msg, ErrorKind::InvalidWif))
+}
+
+fn invalid_address(address: &str) -> KeyError {
+    KeyError::from(Error::new(&format!("{} isn't a valid address", address), ErrorKind::InvalidAddress))
+}
+
+mod err {
+    pub use bs58::decode::Error as Base58Error;
+    pub use secp256k1::Error as Secp256k1Error;
+
+    #[derive(Debug)]
+    pub enum KeyError {
+        Base58Error(Base58Error),
+        Secp256k1Error(Secp256k1Error),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        InvalidChecksum,
+        InvalidWif,
+        InvalidAddress,
+        AddressMismatch,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for KeyError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                KeyError::Base58Error(e) => write!(f, "invalid base58: {:?}", e),
+                KeyError::Secp256k1Error(e) => write!(f, "{}", e),
+                KeyError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<Base58Error> for KeyError {
+        fn from(error: Base58Error) -> Self {
+            KeyError::Base58Error(error)
+        }
+    }
+
+    impl From<Secp256k1Error> for KeyError {
+        fn from(error: Secp256k1Error) -> Self {
+            KeyError::Secp256k1Error(error)
+        }
+    }
+
+    impl From<Error> for KeyError {
+        fn from(error: Error) -> Self {
+            KeyError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod wif {
+    use super::*;
+
+    // The uncompressed WIF example from the bitcoin wiki.
+    const PRIVKEY: &str = "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ";
+    const ADDRESS: &str = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S";
+
+    #[test]
+    fn derive_address_from_wif() {
+        let key = PrivateKey::from_wif(PRIVKEY).unwrap();
+
+        assert!(!key.compressed);
+        assert_eq!(key.network, Network::Mainnet);
+        assert_eq!(key.address(), ADDRESS);
+    }
+
+    #[test]
+    fn derive_address_from_compressed_and_testnet_wif() {
+        // The same secret as PRIVKEY, re-encoded.
+        let compressed = PrivateKey::from_wif("KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617").unwrap();
+        let testnet = PrivateKey::from_wif("91gGn1HgSap6CbU12F6z3pJri26xzp7Ay1VW6NHCoEayNXwRpu2").unwrap();
+
+        assert!(compressed.compressed);
+        assert_eq!(compressed.address(), "1LoVGDgRs9hTfTNJNuXKSpywcbdvwRXpmK");
+        assert_eq!(testnet.network, Network::Testnet);
+        assert_eq!(testnet.address(), "mvgbzkCSgKbYgaeG38auUzR7otscEGi8U7");
+    }
+
+    #[test]
+    fn validate_addresses() {
+        assert_eq!(validate_address(ADDRESS).unwrap(), Network::Mainnet);
+        assert_eq!(validate_address("mvgbzkCSgKbYgaeG38auUzR7otscEGi8U7").unwrap(), Network::Testnet);
+        assert!(validate_address("1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2T").is_err());
+        assert!(validate_address("7db9a").is_err());
+    }
+
+    #[test]
+    fn check_address_mismatch() {
+        match check_address(PRIVKEY, "19HxigV4QyBv3tHpQVcUEQyq1pzZVdoAut").unwrap_err() {
+            KeyError::Error(e) => assert_eq!(e.kind, ErrorKind::AddressMismatch),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn wif_bad_checksum() {
+        let typo = PRIVKEY.replacen("5Hue", "5Huf", 1);
+
+        assert!(PrivateKey::from_wif(typo).is_err());
+    }
+
+    #[test]
+    fn verify_signatures() {
+        let key = PrivateKey::from_wif(PRIVKEY).unwrap();
+        let hash = sha256d(b"repoint");
+        let sig = key.sign(&hash).unwrap();
+
+        assert!(verify(&key.public_key(), &hash, &sig));
+        assert!(!verify(&key.public_key(), &sha256d(b"other"), &sig));
+        assert!(!verify(&key.public_key(), &hash, &[0x30, 0x00]));
+    }
+}
@@ -0,0 +1,226 @@
+/*
+This module encrypts private keys at rest: scrypt derives a key from the passphrase, and chacha20poly1305 seals the private key with it.
+*/
+extern crate chacha20poly1305;
+extern crate rand;
+extern crate rpassword;
+extern crate scrypt;
+
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use scrypt::ScryptParams;
+use std::convert::TryInto;
+
+use err::Error;
+pub use err::{ErrorKind, KeystoreError};
+
+/// The only kdf/cipher pairing written so far, stored alongside the key so it can change later.
+pub const KDF: &str = "scrypt-chacha20poly1305";
+
+/// scrypt cost as log2(N). 2^15 with r = 8 is the usual interactive setting.
+pub const DEFAULT_LOG_N: u8 = 15;
+
+/// Set to skip the passphrase prompt, e.g. on CI.
+pub const PASSPHRASE_VAR: &str = "REPOINT_PASSPHRASE";
+
+/// A sealed private key. Every byte field is hex, as it's stored in account.toml.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncryptedKey {
+    pub kdf: String,
+    pub log_n: u8,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+pub fn encrypt<T: AsRef<str>>(privkey: T, passphrase: T) -> Result<EncryptedKey, KeystoreError> {
+    encrypt_with_cost(privkey, passphrase, DEFAULT_LOG_N)
+}
+
+pub fn encrypt_with_cost<T: AsRef<str>>(privkey: T, passphrase: T, log_n: u8) -> Result<EncryptedKey, KeystoreError> {
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_key(passphrase.as_ref(), &salt, log_n)?;
+    let cipher = ChaCha20Poly1305::new(&Key::from(key));
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), privkey.as_ref().as_bytes())
+        .map_err(|_| KeystoreError::from(Error::new("failed to encrypt key", ErrorKind::Crypto)))?;
+
+    Ok(EncryptedKey {
+        kdf: KDF.to_string(),
+        log_n,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })
+}
+
+/// Fails with `WrongPassphrase` if the passphrase doesn't open the key.
+pub fn decrypt<T: AsRef<str>>(key: &EncryptedKey, passphrase: T) -> Result<String, KeystoreError> {
+    if key.kdf != KDF {
+        let err = Error::new(&format!("unsupported kdf {}", key.kdf), ErrorKind::InvalidKeyFile);
+        return Err(KeystoreError::from(err));
+    }
+    let salt = hex::decode(&key.salt)?;
+    let nonce: [u8; 12] = hex::decode(&key.nonce)?
+        .as_slice()
+        .try_into()
+        .map_err(|_| KeystoreError::from(Error::new("nonce must be 12 bytes", ErrorKind::InvalidKeyFile)))?;
+    let ciphertext = hex::decode(&key.ciphertext)?;
+
+    let derived = derive_key(passphrase.as_ref(), &salt, key.log_n)?;
+    let cipher = ChaCha20Poly1305::new(&Key::from(derived));
+    let plaintext = cipher
+        .decrypt(&Nonce::from(nonce), ciphertext.as_ref())
+        .map_err(|_| KeystoreError::from(Error::new("wrong passphrase", ErrorKind::WrongPassphrase)))?;
+
+    String::from_utf8(plaintext)
+        .map_err(|_| KeystoreError::from(Error::new("decrypted key isn't utf-8", ErrorKind::InvalidKeyFile)))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8) -> Result<[u8; 32], KeystoreError> {
+    let params = ScryptParams::new(log_n, 8, 1)
+        .map_err(|_| KeystoreError::from(Error::new("invalid scrypt cost", ErrorKind::InvalidKeyFile)))?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| KeystoreError::from(Error::new("failed to derive key", ErrorKind::Crypto)))?;
+
+    Ok(key)
+}
+
+/// The passphrase from `REPOINT_PASSPHRASE`, or else prompted for on the tty.
+pub fn read_passphrase<T: AsRef<str>>(prompt: T) -> Result<String, KeystoreError> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+
+    Ok(rpassword::read_password_from_tty(Some(prompt.as_ref()))?)
+}
+
+/// Prompts twice when choosing a new passphrase.
+pub fn read_new_passphrase() -> Result<String, KeystoreError> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+
+    let first = rpassword::read_password_from_tty(Some("New passphrase: "))?;
+    let second = rpassword::read_password_from_tty(Some("Repeat passphrase: "))?;
+    if first != second {
+        let err = Error::new("passphrases don't match", ErrorKind::WrongPassphrase);
+        return Err(KeystoreError::from(err));
+    }
+    if first.is_empty() {
+        let err = Error::new("passphrase can't be empty", ErrorKind::WrongPassphrase);
+        return Err(KeystoreError::from(err));
+    }
+
+    Ok(first)
+}
+
+mod err {
+    pub use hex::FromHexError;
+
+    #[derive(Debug)]
+    pub enum KeystoreError {
+        IoError(std::io::Error),
+        HexError(FromHexError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        WrongPassphrase,
+        InvalidKeyFile,
+        Crypto,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for KeystoreError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                KeystoreError::IoError(e) => write!(f, "{}", e),
+                KeystoreError::HexError(e) => write!(f, "{}", e),
+                KeystoreError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for KeystoreError {
+        fn from(error: std::io::Error) -> Self {
+            KeystoreError::IoError(error)
+        }
+    }
+
+    impl From<FromHexError> for KeystoreError {
+        fn from(error: FromHexError) -> Self {
+            KeystoreError::HexError(error)
+        }
+    }
+
+    impl From<Error> for KeystoreError {
+        fn from(error: Error) -> Self {
+            KeystoreError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod seal {
+    use super::*;
+
+    // Cheap scrypt cost so tests stay fast.
+    const TEST_LOG_N: u8 = 4;
+    const PRIVKEY: &str = "5JZ4RXH4MoXpaUQMcJHo8DxhZtkf5U5VnYd9zZH8BRKZuAbxZEw";
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let key = encrypt_with_cost(PRIVKEY, "hunter2", TEST_LOG_N).unwrap();
+
+        assert!(!key.ciphertext.contains(&hex::encode(PRIVKEY)));
+        assert_eq!(decrypt(&key, "hunter2").unwrap(), PRIVKEY);
+    }
+
+    #[test]
+    fn decrypt_wrong_passphrase() {
+        let key = encrypt_with_cost(PRIVKEY, "hunter2", TEST_LOG_N).unwrap();
+
+        match decrypt(&key, "hunter3").unwrap_err() {
+            KeystoreError::Error(e) => assert_eq!(e.kind, ErrorKind::WrongPassphrase),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn decrypt_detects_tampering() {
+        let mut key = encrypt_with_cost(PRIVKEY, "hunter2", TEST_LOG_N).unwrap();
+        let flipped = if key.ciphertext.starts_with('0') { "1" } else { "0" };
+        key.ciphertext.replace_range(..1, flipped);
+
+        assert!(decrypt(&key, "hunter2").is_err());
+    }
+}
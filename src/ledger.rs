@@ -0,0 +1,544 @@
+/*
+This module keeps a durable record of everything an account has published, in `$HOME/.repoint/ledger/<address>.toml`. It's the history behind `repoint history` and what makes `push` idempotent: actions already sent but not yet indexed aren't planned twice.
+
+Entries are chained, each spending the change of the one before (its `parent`), so they're broadcast oldest first. If broadcasting stops halfway, the next `broadcast` carries on where it stopped; if one is rejected, everything chained after it fails with it.
+*/
+extern crate toml;
+
+use crate::backend::{BackendError, ChainBackend, ErrorKind as BackendErrorKind, Utxo};
+use crate::index::{Index, SourceTx};
+use crate::key;
+use crate::tx::{self, Transaction};
+
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use err::Error;
+pub use err::{ErrorKind, LedgerError};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    /// Built and signed, but not known to be broadcast.
+    Signed,
+    Broadcast,
+    Confirmed,
+    Failed,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Signed => "signed",
+            Status::Broadcast => "broadcast",
+            Status::Confirmed => "confirmed",
+            Status::Failed => "failed",
+        }
+    }
+
+    /// Sent, or about to be, but not in the index yet.
+    pub fn is_outstanding(&self) -> bool {
+        matches!(self, Status::Signed | Status::Broadcast)
+    }
+}
+
+impl FromStr for Status {
+    type Err = LedgerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "signed" => Ok(Status::Signed),
+            "broadcast" => Ok(Status::Broadcast),
+            "confirmed" => Ok(Status::Confirmed),
+            "failed" => Ok(Status::Failed),
+            _ => Err(invalid(&format!("unknown status {}", s))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub txid: String,
+    pub hex: String,
+    /// Unix time in seconds the entry was recorded.
+    pub timestamp: u64,
+    pub status: Status,
+    pub height: Option<u64>,
+    /// Hash of the repoint.toml the transaction was planned from, see `manifest_hash`.
+    pub manifest: Option<String>,
+    /// What the transaction does, e.g. a plan line.
+    pub summary: String,
+    /// The txid of the entry whose change it spends.
+    pub parent: Option<String>,
+}
+
+impl Entry {
+    pub fn new<T: AsRef<str>>(txid: T, hex: T, summary: T, manifest: Option<String>) -> Entry {
+        Entry {
+            txid: txid.as_ref().to_string(),
+            hex: hex.as_ref().to_string(),
+            timestamp: now(),
+            status: Status::Signed,
+            height: None,
+            manifest,
+            summary: summary.as_ref().to_string(),
+            parent: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ledger {
+    pub path: PathBuf,
+    /// Oldest first.
+    pub entries: Vec<Entry>,
+}
+
+impl Ledger {
+    /// `<home>/ledger/<address>.toml`.
+    pub fn path_for<T: AsRef<str>>(home: &Path, address: T) -> PathBuf {
+        home.join("ledger").join(format!("{}.toml", address.as_ref()))
+    }
+
+    /// Opens the ledger at `path`. A missing file is an empty ledger.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Ledger, LedgerError> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(Ledger { path, entries: vec![] });
+        }
+
+        let entries = parse(read_to_string(&path)?)?;
+        Ok(Ledger { path, entries })
+    }
+
+    pub fn save(&self) -> Result<(), LedgerError> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        write(&self.path, self.to_toml())?;
+
+        Ok(())
+    }
+
+    /// Records `entry`. Recording a txid twice keeps the first entry.
+    pub fn record(&mut self, entry: Entry) -> bool {
+        if self.get(&entry.txid).is_some() {
+            return false;
+        }
+        self.entries.push(entry);
+        true
+    }
+
+    pub fn get<T: AsRef<str>>(&self, txid: T) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.txid == txid.as_ref())
+    }
+
+    pub fn set_status<T: AsRef<str>>(&mut self, txid: T, status: Status, height: Option<u64>) -> Result<(), LedgerError> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|e| e.txid == txid.as_ref())
+            .ok_or_else(|| LedgerError::from(Error::new(&format!("no entry for {}", txid.as_ref()), ErrorKind::UnknownTx)))?;
+        entry.status = status;
+        entry.height = height.or(entry.height);
+
+        Ok(())
+    }
+
+    pub fn outstanding(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(|e| e.status.is_outstanding())
+    }
+
+    /// The outputs outstanding entries spend, as `(txid, vout)`. The backend may
+    /// still count them as unspent until those entries are broadcast.
+    pub fn spends(&self) -> Vec<(String, u32)> {
+        self.outstanding()
+            .filter_map(|e| Transaction::from_hex(&e.hex).ok())
+            .flat_map(|tx| tx.inputs.into_iter().map(|i| (tx::display_txid(&i.prev_txid), i.prev_vout)))
+            .collect()
+    }
+
+    /// The change of the newest outstanding entry, unless something spends it already:
+    /// what the next transaction from `address` should chain from.
+    pub fn chain_tip<T: AsRef<str>>(&self, address: T) -> Option<Utxo> {
+        let (_, hash) = key::address_hash(address.as_ref()).ok()?;
+        let entry = self.outstanding().last()?;
+        let tx = Transaction::from_hex(&entry.hex).ok()?;
+        let spends = self.spends();
+        let vout = (0..tx.outputs.len()).rev().find(|vout| {
+            tx::p2pkh_hash(&tx.outputs[*vout].script_pubkey) == Some(&hash[..])
+                && !spends.contains(&(entry.txid.clone(), *vout as u32))
+        })?;
+
+        Some(Utxo {
+            txid: entry.txid.clone(),
+            vout: vout as u32,
+            value: tx.outputs[vout].value,
+            height: entry.height,
+        })
+    }
+
+    /// Applies outstanding entries to `index` as if they were already on chain,
+    /// so planning against it doesn't repeat them.
+    pub fn overlay<T: AsRef<str>>(&self, index: &mut Index, address: T) {
+        for entry in self.outstanding() {
+            if index.has_tx(&entry.txid) {
+                continue;
+            }
+            index.apply_tx(&SourceTx {
+                txid: entry.txid.clone(),
+                address: address.as_ref().to_string(),
+                height: None,
+                hex: entry.hex.clone(),
+            });
+        }
+    }
+
+    /// Broadcasts signed entries oldest first and returns their txids. Ones the network
+    /// rejects are marked failed, and so is whatever is chained after them; any other
+    /// error stops, leaving the rest signed.
+    pub fn broadcast(&mut self, backend: &dyn ChainBackend) -> Result<Vec<String>, BackendError> {
+        let mut failed: Vec<String> =
+            self.entries.iter().filter(|e| e.status == Status::Failed).map(|e| e.txid.clone()).collect();
+        let mut sent = vec![];
+        for entry in self.entries.iter_mut().filter(|e| e.status == Status::Signed) {
+            if matches!(&entry.parent, Some(parent) if failed.contains(parent)) {
+                entry.status = Status::Failed;
+                failed.push(entry.txid.clone());
+                continue;
+            }
+            match backend.broadcast(&entry.hex) {
+                Ok(_) => {
+                    entry.status = Status::Broadcast;
+                    sent.push(entry.txid.clone());
+                }
+                Err(BackendError::Error(e)) if e.kind == BackendErrorKind::Rejected => {
+                    entry.status = Status::Failed;
+                    failed.push(entry.txid.clone());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// Marks broadcast entries that made it into a block as confirmed. One the backend
+    /// no longer knows, after a reorg or a mempool eviction, is sent again; if that's
+    /// rejected it's marked failed along with whatever is chained after it, so its
+    /// actions get planned again.
+    pub fn refresh(&mut self, backend: &dyn ChainBackend) -> Result<(), BackendError> {
+        let mut failed: Vec<String> =
+            self.entries.iter().filter(|e| e.status == Status::Failed).map(|e| e.txid.clone()).collect();
+        for entry in self.entries.iter_mut().filter(|e| e.status.is_outstanding()) {
+            if matches!(&entry.parent, Some(parent) if failed.contains(parent)) {
+                entry.status = Status::Failed;
+                failed.push(entry.txid.clone());
+                continue;
+            }
+            if entry.status != Status::Broadcast {
+                continue;
+            }
+            match backend.get_tx(&entry.txid) {
+                Ok(tx) if tx.height.is_some() => {
+                    entry.status = Status::Confirmed;
+                    entry.height = tx.height;
+                }
+                Ok(_) => (),
+                Err(BackendError::Error(e)) if e.kind == BackendErrorKind::NotFound => match backend.broadcast(&entry.hex) {
+                    Ok(_) => (),
+                    Err(BackendError::Error(e)) if e.kind == BackendErrorKind::Rejected => {
+                        entry.status = Status::Failed;
+                        failed.push(entry.txid.clone());
+                    }
+                    Err(e) => return Err(e),
+                },
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        for entry in self.entries.iter() {
+            toml.push_str("[[entry]]\n");
+            toml.push_str(&format!("txid = {}\n", quote(&entry.txid)));
+            toml.push_str(&format!("timestamp = {}\n", entry.timestamp));
+            toml.push_str(&format!("status = {}\n", quote(entry.status.as_str())));
+            if let Some(height) = entry.height {
+                toml.push_str(&format!("height = {}\n", height));
+            }
+            if let Some(manifest) = &entry.manifest {
+                toml.push_str(&format!("manifest = {}\n", quote(manifest)));
+            }
+            if let Some(parent) = &entry.parent {
+                toml.push_str(&format!("parent = {}\n", quote(parent)));
+            }
+            toml.push_str(&format!("summary = {}\n", quote(&entry.summary)));
+            toml.push_str(&format!("hex = {}\n\n", quote(&entry.hex)));
+        }
+
+        toml
+    }
+}
+
+/// Sha256 of repoint.toml's contents, as hex.
+pub fn manifest_hash<T: AsRef<str>>(source: T) -> String {
+    hex::encode(key::sha256(source.as_ref().as_bytes()))
+}
+
+fn parse<T: AsRef<str>>(data: T) -> Result<Vec<Entry>, LedgerError> {
+    let value: toml::Value = data.as_ref().parse()?;
+    let tables = match value.get("entry") {
+        Some(entries) => entries.as_array().ok_or_else(|| invalid("entry must be an array of tables"))?.clone(),
+        None => vec![],
+    };
+
+    let mut entries = vec![];
+    for (i, table) in tables.iter().enumerate() {
+        let field = |key: &str| table.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+        let number = |key: &str| table.get(key).and_then(|v| v.as_integer()).map(|n| n as u64);
+        let (txid, hex, status) = match (field("txid"), field("hex"), field("status")) {
+            (Some(txid), Some(hex), Some(status)) => (txid, hex, status),
+            _ => return Err(invalid(&format!("entry {} needs txid, hex and status", i + 1))),
+        };
+
+        entries.push(Entry {
+            txid,
+            hex,
+            timestamp: number("timestamp").unwrap_or(0),
+            status: status.parse()?,
+            height: number("height"),
+            manifest: field("manifest"),
+            summary: field("summary").unwrap_or_default(),
+            parent: field("parent"),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+fn invalid(msg: &str) -> LedgerError {
+    LedgerError::from(Error::new(msg, ErrorKind::InvalidLedger))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+mod err {
+    pub use toml::de::Error as TomlError;
+
+    #[derive(Debug)]
+    pub enum LedgerError {
+        IoError(std::io::Error),
+        TomlError(TomlError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        InvalidLedger,
+        UnknownTx,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for LedgerError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                LedgerError::IoError(e) => write!(f, "{}", e),
+                LedgerError::TomlError(e) => write!(f, "{}", e),
+                LedgerError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for LedgerError {
+        fn from(error: std::io::Error) -> Self {
+            LedgerError::IoError(error)
+        }
+    }
+
+    impl From<TomlError> for LedgerError {
+        fn from(error: TomlError) -> Self {
+            LedgerError::TomlError(error)
+        }
+    }
+
+    impl From<Error> for LedgerError {
+        fn from(error: Error) -> Self {
+            LedgerError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod history {
+    use super::*;
+    use crate::index::testing::{ALICE, PRIVKEY};
+    use crate::key::PrivateKey;
+    use crate::mock_chain::MockChain;
+    use crate::opcode::Action;
+    use crate::replay::ReplayServer;
+    use crate::tx::Transaction;
+    use crate::wallet::sign_chain;
+    use crate::whatsonchain::WhatsOnChain;
+    use crate::{fund_actions, sign};
+
+    fn setup_entry(action: &Action) -> Entry {
+        let hex = sign(action);
+        let txid = Transaction::from_hex(&hex).unwrap().txid();
+        Entry::new(txid, hex, action.opcode.name().to_string(), Some(manifest_hash("name = \"repoint\"")))
+    }
+
+    #[test]
+    fn ledger_save_and_reopen() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("repoint-ledger-{}", std::process::id()));
+        let mut ledger = Ledger::open(Ledger::path_for(&path, ALICE)).unwrap();
+        let entry = setup_entry(&Action::create_repo("repoint").unwrap());
+
+        assert!(ledger.record(entry.clone()));
+        assert!(!ledger.record(entry.clone()));
+        ledger.set_status(&entry.txid, Status::Confirmed, Some(650_000)).unwrap();
+        ledger.save().unwrap();
+
+        let reopened = Ledger::open(Ledger::path_for(&path, ALICE)).unwrap();
+        assert_eq!(reopened, ledger);
+        assert_eq!(reopened.entries[0].height, Some(650_000));
+        assert_eq!(reopened.entries[0].manifest, Some(manifest_hash("name = \"repoint\"")));
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn ledger_overlay_outstanding() {
+        let mut ledger = Ledger::open("/nonexistent/ledger.toml").unwrap();
+        for action in [Action::instantiate(), Action::create_account("7db9a").unwrap(), Action::create_repo("repoint").unwrap()].iter() {
+            ledger.record(setup_entry(action));
+        }
+        let failed = ledger.entries[2].txid.clone();
+        ledger.set_status(&failed, Status::Failed, None).unwrap();
+
+        let mut index = Index::new();
+        ledger.overlay(&mut index, ALICE);
+
+        let account = index.account(ALICE).unwrap();
+        assert_eq!(account.name.as_deref(), Some("7db9a"));
+        assert!(account.repos.is_empty());
+    }
+
+    #[test]
+    fn ledger_broadcast_and_refresh() {
+        let mut ledger = Ledger::open("/nonexistent/ledger.toml").unwrap();
+        let rejected = setup_entry(&Action::instantiate());
+        let sent = setup_entry(&Action::create_account("7db9a").unwrap());
+        let rejecting = ReplayServer::start(vec![("POST", "/tx/raw".to_string(), 400, "\"bad-txns\"".to_string())]);
+        let server = ReplayServer::start(vec![
+            ("POST", "/tx/raw".to_string(), 200, format!("\"{}\"", sent.txid)),
+            ("GET", format!("/tx/hash/{}", sent.txid), 200, "{\"blockheight\":650000}".to_string()),
+            ("GET", format!("/tx/{}/hex", sent.txid), 200, sent.hex.clone()),
+        ]);
+
+        ledger.record(rejected.clone());
+        assert!(ledger.broadcast(&WhatsOnChain::new(&rejecting.url)).unwrap().is_empty());
+        ledger.record(sent.clone());
+        assert_eq!(ledger.broadcast(&WhatsOnChain::new(&server.url)).unwrap(), vec![sent.txid.clone()]);
+        ledger.refresh(&WhatsOnChain::new(&server.url)).unwrap();
+
+        assert_eq!(ledger.get(&rejected.txid).unwrap().status, Status::Failed);
+        assert_eq!(ledger.get(&sent.txid).unwrap().status, Status::Confirmed);
+        assert_eq!(ledger.get(&sent.txid).unwrap().height, Some(650_000));
+    }
+
+    #[test]
+    fn ledger_chain_fails_together() {
+        let chain = MockChain::new();
+        let coin = Utxo { txid: chain.fund(ALICE, 10_000).unwrap(), vout: 0, value: 10_000, height: None };
+        let missing = Utxo { vout: 1, ..coin.clone() };
+        let key = PrivateKey::from_wif(PRIVKEY).unwrap();
+        let actions = [Action::create_repo("repoint").unwrap(), Action::create_repo("anvil").unwrap()];
+
+        let mut ledger = Ledger::open("/nonexistent/ledger.toml").unwrap();
+        for utxo in [coin, missing].iter() {
+            let mut funded = fund_actions(&actions, None, vec![utxo.clone()], ALICE, 0.5, 1).unwrap();
+            sign_chain(&mut funded, &key).unwrap();
+            for f in funded.iter() {
+                let mut entry = Entry::new(f.tx.txid(), f.tx.to_hex(), "create repo".to_string(), None);
+                entry.parent = f.parent.clone();
+                ledger.record(entry);
+            }
+        }
+        assert_eq!(ledger.entries[1].parent.as_ref(), Some(&ledger.entries[0].txid));
+        assert_eq!(ledger.chain_tip(ALICE).unwrap().txid, ledger.entries[3].txid);
+
+        // The second chain spends an output that doesn't exist, so its child is never sent.
+        let sent = ledger.broadcast(&chain).unwrap();
+        assert_eq!(sent, vec![ledger.entries[0].txid.clone(), ledger.entries[1].txid.clone()]);
+        assert_eq!(ledger.entries[3].status, Status::Failed);
+        assert_eq!(chain.txs().len(), 3);
+        assert_eq!(ledger.chain_tip(ALICE).unwrap().txid, ledger.entries[1].txid);
+    }
+
+    #[test]
+    fn ledger_refresh_resends_vanished() {
+        let chain = MockChain::new();
+        let coin = Utxo { txid: chain.fund(ALICE, 10_000).unwrap(), vout: 0, value: 10_000, height: None };
+        let missing = Utxo { vout: 1, ..coin.clone() };
+        let key = PrivateKey::from_wif(PRIVKEY).unwrap();
+        let actions = [Action::create_repo("repoint").unwrap(), Action::create_repo("anvil").unwrap()];
+
+        // Both chains were sent once, then dropped out of the mempool.
+        let mut ledger = Ledger::open("/nonexistent/ledger.toml").unwrap();
+        for utxo in [coin, missing].iter() {
+            let mut funded = fund_actions(&actions, None, vec![utxo.clone()], ALICE, 0.5, 1).unwrap();
+            sign_chain(&mut funded, &key).unwrap();
+            for f in funded.iter() {
+                let mut entry = Entry::new(f.tx.txid(), f.tx.to_hex(), "create repo".to_string(), None);
+                entry.parent = f.parent.clone();
+                entry.status = Status::Broadcast;
+                ledger.record(entry);
+            }
+        }
+
+        ledger.refresh(&chain).unwrap();
+        let statuses: Vec<Status> = ledger.entries.iter().map(|e| e.status).collect();
+        assert_eq!(statuses, vec![Status::Broadcast, Status::Broadcast, Status::Failed, Status::Failed]);
+        assert_eq!(chain.txs().len(), 3);
+
+        let mut index = Index::new();
+        ledger.overlay(&mut index, ALICE);
+        assert!(index.has_tx(&ledger.entries[1].txid));
+        assert!(!index.has_tx(&ledger.entries[2].txid));
+    }
+}
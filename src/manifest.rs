@@ -0,0 +1,477 @@
+/*
+This module is the typed model of a repo's repoint.toml. Everything in it ends up on chain for good, so parsing reports every mistake, with its line and column, before anything is signed.
+
+```ignore
+['repository']
+version = "0.1.0"
+name = "repoint"
+account = "7db9a"
+address = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S"
+description = "Point to your repos from the blockchain."
+tags = ["bitcoin", "git"]
+urls = ["https://github.com/7db9a/repoint"]
+```
+*/
+use crate::key;
+use crate::repoint_file::{self, value, Document};
+
+use std::collections::BTreeMap;
+
+use toml::Spanned;
+use toml_edit::{Item, Value};
+
+pub use err::{ManifestError, Violation};
+
+/// The table repoint.toml keeps the repo in.
+pub const TABLE: &str = "repository";
+
+/// Longest text we put in one op-return message, in bytes. Nodes relay bigger
+/// ones since Genesis, but everything published is paid for and indexed forever.
+pub const MAX_TEXT_LEN: usize = 512;
+
+const KEYS: [&str; 7] = ["version", "name", "account", "address", "description", "tags", "urls"];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RepositoryManifest {
+    pub version: String,
+    pub name: String,
+    /// Name of the owning account.
+    pub account: Option<String>,
+    /// Address of the owning account.
+    pub address: Option<String>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub urls: Vec<String>,
+}
+
+impl RepositoryManifest {
+    /// Parses and validates repoint.toml. Fails with every violation found, not just the first.
+    pub fn parse<T: AsRef<str>>(source: T) -> Result<RepositoryManifest, ManifestError> {
+        let source = source.as_ref();
+        let doc = source.parse::<Document>()?;
+        let mut violations = vec![];
+        let spans = Spans::parse(source);
+        let at = |key: &str, needle: Option<&str>, message: String| {
+            let (line, col) = spans.locate(source, key, needle);
+            Violation { key: key.to_string(), line, col, message }
+        };
+
+        let table = match doc[TABLE].as_table() {
+            Some(table) => table,
+            None => {
+                let err = Violation { key: TABLE.to_string(), line: 1, col: 1, message: "missing [repository] table".to_string() };
+                return Err(ManifestError::Invalid(vec![err]));
+            }
+        };
+        for (key, _) in table.iter() {
+            if !KEYS.contains(&key) {
+                violations.push(at(key, None, format!("unknown key, expected one of {}", KEYS.join(", "))));
+            }
+        }
+
+        let mut string = |key: &str, required: bool| match &table.get(key) {
+            None => {
+                if required {
+                    violations.push(at(key, None, format!("missing {}", key)));
+                }
+                None
+            }
+            Some(item) => match item.as_str() {
+                Some(s) => Some(s.to_string()),
+                None => {
+                    violations.push(at(key, None, "must be a string".to_string()));
+                    None
+                }
+            },
+        };
+        let version = string("version", true);
+        let name = string("name", true);
+        let account = string("account", false);
+        let address = string("address", false);
+        let description = string("description", false);
+
+        let mut list = |key: &str| -> Vec<String> {
+            let item = match table.get(key) {
+                Some(item) => item,
+                None => return vec![],
+            };
+            match item.as_array() {
+                Some(array) if array.iter().all(|v| v.as_str().is_some()) => {
+                    array.iter().filter_map(|v| v.as_str()).map(|s| s.to_string()).collect()
+                }
+                _ => {
+                    violations.push(at(key, None, "must be an array of strings".to_string()));
+                    vec![]
+                }
+            }
+        };
+        let tags = list("tags");
+        let urls = list("urls");
+
+        let manifest = RepositoryManifest {
+            version: version.unwrap_or_default(),
+            name: name.unwrap_or_default(),
+            account,
+            address,
+            description,
+            tags,
+            urls,
+        };
+        for (key, needle, message) in manifest.check() {
+            if !violations.iter().any(|v| v.key == key) {
+                violations.push(at(key, needle.as_deref(), message));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(manifest)
+        } else {
+            violations.sort_by_key(|v| (v.line, v.col));
+            Err(ManifestError::Invalid(violations))
+        }
+    }
+
+    /// Writes the manifest into `doc`. Only fields that changed are touched,
+    /// so comments and formatting elsewhere in the file survive.
+    pub fn apply(&self, doc: &Document) -> Document {
+        let mut doc = doc.clone();
+        let current = |doc: &Document, key: &str| doc[TABLE][key].as_str().map(|s| s.to_string());
+
+        let strings = [
+            ("version", Some(self.version.clone())),
+            ("name", Some(self.name.clone())),
+            ("account", self.account.clone()),
+            ("address", self.address.clone()),
+            ("description", self.description.clone()),
+        ];
+        for (key, want) in strings.iter() {
+            if current(&doc, key) == *want {
+                continue;
+            }
+            match want {
+                Some(s) => doc[TABLE][*key] = value(s.as_str()),
+                None => remove(&mut doc, key),
+            }
+        }
+
+        for (key, want) in [("tags", &self.tags), ("urls", &self.urls)].iter() {
+            let have: Option<Vec<&str>> = doc[TABLE][*key].as_array().map(|a| a.iter().filter_map(|v| v.as_str()).collect());
+            let unchanged = match &have {
+                Some(have) => have.len() == want.len() && have.iter().zip(want.iter()).all(|(h, w)| h == w),
+                None => want.is_empty(),
+            };
+            if unchanged {
+                continue;
+            }
+            if want.is_empty() {
+                remove(&mut doc, key);
+            } else {
+                let array: Value = want.iter().map(|s| s.as_str()).collect();
+                doc[TABLE][*key] = Item::Value(array);
+            }
+        }
+
+        doc
+    }
+
+    /// Value rules, as `(key, offending value, message)`, without positions.
+    fn check(&self) -> Vec<(&'static str, Option<String>, String)> {
+        let mut problems = vec![];
+
+        if self.version.trim().is_empty() {
+            problems.push(("version", None, "can't be empty".to_string()));
+        }
+        if let Err(msg) = check_name(&self.name) {
+            problems.push(("name", None, msg));
+        }
+        if let Some(account) = &self.account {
+            if let Err(msg) = check_name(account) {
+                problems.push(("account", None, msg));
+            }
+        }
+        if let Some(address) = &self.address {
+            if key::validate_address(address).is_err() {
+                problems.push(("address", None, "isn't a valid bitcoin address".to_string()));
+            }
+        }
+        if let Some(description) = &self.description {
+            if let Err(msg) = check_text(description) {
+                problems.push(("description", None, msg));
+            }
+        }
+        for tag in self.tags.iter() {
+            if let Err(msg) = check_text(tag) {
+                problems.push(("tags", Some(tag.clone()), format!("{:?} {}", tag, msg)));
+            }
+        }
+        for url in self.urls.iter() {
+            if !is_uri(url) {
+                problems.push(("urls", Some(url.clone()), format!("{:?} isn't a uri like https://host/path", url)));
+            } else if let Err(msg) = check_text(url) {
+                problems.push(("urls", Some(url.clone()), format!("{:?} {}", url, msg)));
+            }
+        }
+
+        problems
+    }
+}
+
+/// Reads and validates `<dir>/repoint.toml`, also returning the document for `apply`.
+pub fn open<T: AsRef<str>>(path: T) -> Result<(RepositoryManifest, Document), ManifestError> {
+    let source = repoint_file::read_to_string(path.as_ref())?;
+    let manifest = RepositoryManifest::parse(&source)?;
+
+    Ok((manifest, source.parse::<Document>()?))
+}
+
+/// `scheme://rest`, where the scheme is a letter followed by letters, digits, `+`, `-` or `.`.
+pub fn is_uri<T: AsRef<str>>(uri: T) -> bool {
+    let uri = uri.as_ref();
+    let (scheme, rest) = match uri.find("://") {
+        Some(pos) => (&uri[..pos], &uri[pos + 3..]),
+        None => return false,
+    };
+
+    scheme.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        && !rest.is_empty()
+        && !uri.chars().any(char::is_whitespace)
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        Err("can't be empty".to_string())
+    } else if name.len() > MAX_TEXT_LEN {
+        Err(format!("is longer than {} bytes", MAX_TEXT_LEN))
+    } else if name.contains('/') || name.chars().any(char::is_whitespace) {
+        Err("can't contain '/' or whitespace".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether `text` can go in an op-return message: not blank, and at most `MAX_TEXT_LEN` bytes.
+pub fn check_text(text: &str) -> Result<(), String> {
+    if text.trim().is_empty() {
+        Err("can't be empty".to_string())
+    } else if text.len() > MAX_TEXT_LEN {
+        Err(format!("is longer than {} bytes", MAX_TEXT_LEN))
+    } else {
+        Ok(())
+    }
+}
+
+fn remove(doc: &mut Document, key: &str) {
+    if let Some(table) = doc[TABLE].as_table_mut() {
+        table.remove(key);
+    }
+}
+
+/// Byte offsets of the line with the repository table's header and of each of its
+/// keys, with the span of the key's value, as the toml parser saw them.
+#[derive(Default)]
+struct Spans {
+    header: Option<usize>,
+    keys: BTreeMap<String, (usize, (usize, usize))>,
+}
+
+impl Spans {
+    /// Empty if `source` has anything but tables at the top level.
+    fn parse(source: &str) -> Spans {
+        type Tables = BTreeMap<Spanned<String>, BTreeMap<Spanned<String>, Spanned<toml::Value>>>;
+        let tables: Tables = match toml::from_str(source) {
+            Ok(tables) => tables,
+            Err(_) => return Spans::default(),
+        };
+
+        match tables.iter().find(|(name, _)| name.get_ref() == TABLE) {
+            Some((name, table)) => Spans {
+                header: Some(source[..name.start()].rfind('\n').map(|i| i + 1).unwrap_or(0)),
+                keys: table.iter().map(|(key, value)| (key.get_ref().clone(), (key.start(), value.span()))).collect(),
+            },
+            None => Spans::default(),
+        }
+    }
+
+    /// 1-based line and column of `key` in the repository table, or of `needle`
+    /// (an array element) in its value. Falls back to the table header.
+    fn locate(&self, source: &str, key: &str, needle: Option<&str>) -> (usize, usize) {
+        let offset = match self.keys.get(key) {
+            Some((key_start, (start, end))) => needle
+                .and_then(|needle| {
+                    ["\"", "'"].iter().find_map(|quote| source[*start..*end].find(&format!("{0}{1}{0}", quote, needle)))
+                })
+                .map(|found| start + found)
+                .unwrap_or(*key_start),
+            None => self.header.unwrap_or(0),
+        };
+
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+    }
+}
+
+mod err {
+    pub use toml_edit::TomlError;
+
+    /// One problem in repoint.toml.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Violation {
+        pub key: String,
+        pub line: usize,
+        pub col: usize,
+        pub message: String,
+    }
+
+    impl std::fmt::Display for Violation {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}:{}: {}: {}", self.line, self.col, self.key, self.message)
+        }
+    }
+
+    #[derive(Debug)]
+    pub enum ManifestError {
+        IoError(std::io::Error),
+        TomlError(TomlError),
+        Invalid(Vec<Violation>),
+    }
+
+    impl std::fmt::Display for ManifestError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                ManifestError::IoError(e) => write!(f, "{}", e),
+                ManifestError::TomlError(e) => write!(f, "{}", e),
+                ManifestError::Invalid(violations) => {
+                    let lines: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+                    write!(f, "{}", lines.join("\n"))
+                }
+            }
+        }
+    }
+
+    impl From<std::io::Error> for ManifestError {
+        fn from(error: std::io::Error) -> Self {
+            ManifestError::IoError(error)
+        }
+    }
+
+    impl From<TomlError> for ManifestError {
+        fn from(error: TomlError) -> Self {
+            ManifestError::TomlError(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod schema {
+    use super::*;
+
+    const MANIFEST: &str = r#"# Published with repoint.
+['repository']
+version = "0.1.0"
+name = "repoint"
+account = "7db9a"
+description = "Point to your repos from the blockchain." # shown by query
+tags = ["bitcoin", "git"]
+urls = [
+    "https://github.com/7db9a/repoint", # mirror
+]
+"#;
+
+    fn violations(source: &str) -> Vec<Violation> {
+        match RepositoryManifest::parse(source).unwrap_err() {
+            ManifestError::Invalid(violations) => violations,
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn manifest_parse() {
+        let manifest = RepositoryManifest::parse(MANIFEST).unwrap();
+
+        assert_eq!(manifest.name, "repoint");
+        assert_eq!(manifest.account.as_deref(), Some("7db9a"));
+        assert_eq!(manifest.address, None);
+        assert_eq!(manifest.tags, vec!["bitcoin", "git"]);
+        assert_eq!(manifest.urls, vec!["https://github.com/7db9a/repoint"]);
+    }
+
+    #[test]
+    fn manifest_reports_every_violation_with_position() {
+        let source = r#"['repository']
+version = "0.1.0"
+name = "my repo"
+address = "1NotAnAddress"
+tags = "git"
+urls = ["https://github.com/7db9a/repoint", "github.com/7db9a/repoint"]
+licence = "MIT"
+"#;
+        let found: Vec<(String, usize, usize)> = violations(source).into_iter().map(|v| (v.key, v.line, v.col)).collect();
+
+        assert_eq!(
+            found,
+            vec![
+                ("name".to_string(), 3, 1),
+                ("address".to_string(), 4, 1),
+                ("tags".to_string(), 5, 1),
+                ("urls".to_string(), 6, 45),
+                ("licence".to_string(), 7, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn manifest_positions_follow_the_parser() {
+        let source = r#"[other]
+name = "not checked"
+tags = ["my tag"]
+
+[repository]
+version = "0.1.0"
+name = "repoint"
+urls = [
+    "https://github.com/7db9a/repoint",
+    'github.com/7db9a/repoint',
+]
+meta = { name = "x" }
+"#;
+        let found: Vec<(String, usize, usize)> = violations(source).into_iter().map(|v| (v.key, v.line, v.col)).collect();
+        assert_eq!(found, vec![("urls".to_string(), 10, 5), ("meta".to_string(), 12, 1)]);
+
+        let dotted = violations("[repository]\nversion = \"0.1.0\"\nname = \"repoint\"\n\n[repository.links]\nname = \"x\"\n");
+        assert_eq!((dotted[0].key.as_str(), dotted[0].line, dotted[0].col), ("links", 5, 13));
+    }
+
+    #[test]
+    fn manifest_missing_fields() {
+        let found = violations("['repository']\nversion = \"0.1.0\"\n");
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].message, "missing name");
+        assert_eq!((found[0].line, found[0].col), (1, 1));
+    }
+
+    #[test]
+    fn manifest_apply_keeps_comments() {
+        let doc = MANIFEST.parse::<Document>().unwrap();
+        let mut manifest = RepositoryManifest::parse(MANIFEST).unwrap();
+        manifest.tags.push("cli".to_string());
+
+        let toml = manifest.apply(&doc).to_string();
+
+        assert!(toml.starts_with("# Published with repoint.\n"));
+        assert!(toml.contains("# shown by query"));
+        assert!(toml.contains("# mirror"));
+        assert_eq!(RepositoryManifest::parse(&toml).unwrap(), manifest);
+    }
+
+    #[test]
+    fn uri_forms() {
+        assert!(is_uri("https://github.com/7db9a/repoint"));
+        assert!(is_uri("git+ssh://git@github.com/7db9a/repoint"));
+        assert!(!is_uri("github.com/7db9a/repoint"));
+        assert!(!is_uri("https://"));
+        assert!(!is_uri("https://github.com/7db9a/my repo"));
+    }
+}
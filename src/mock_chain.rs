@@ -0,0 +1,495 @@
+/*
+This module is a whole chain in memory, for tests and offline demos. Broadcasts wait in a mempool until `mine` puts them in a block, and `reorg` rolls blocks back. With a path, the chain is kept in a toml file between runs: the tip height and `[[tx]]` tables like the fixtures `FixtureSource` reads, which it can also be seeded from.
+
+```ignore
+[backend]
+kind = "mock"
+path = "/tmp/repoint/mock.toml"
+```
+*/
+extern crate toml;
+
+use crate::backend::{BackendError, ChainBackend, ChainTx, Error, ErrorKind, TxRef, Utxo};
+use crate::index::{self, SourceTx};
+use crate::key::{self, Network, PrivateKey};
+use crate::opcode::Action;
+use crate::tx::{self, Transaction, TxIn, TxOut};
+use crate::wallet;
+
+use std::cell::RefCell;
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockTx {
+    pub txid: String,
+    pub hex: String,
+    /// Who signed it, if known.
+    pub sender: Option<String>,
+    /// `None` while in the mempool.
+    pub height: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct State {
+    tip: u64,
+    /// In the order they were accepted.
+    txs: Vec<MockTx>,
+}
+
+#[derive(Debug)]
+pub struct MockChain {
+    pub network: Network,
+    /// Where the chain is saved after every change, if anywhere.
+    pub path: Option<PathBuf>,
+    /// Credited with broadcasts that have no inputs, since nothing in them says who sent them.
+    pub sender: Option<String>,
+    state: RefCell<State>,
+}
+
+impl MockChain {
+    pub fn new() -> MockChain {
+        MockChain {
+            network: Network::Mainnet,
+            path: None,
+            sender: None,
+            state: RefCell::new(State::default()),
+        }
+    }
+
+    /// The chain saved at `path`, or an empty one that will be saved there.
+    pub fn open<T: Into<PathBuf>>(path: T) -> Result<MockChain, BackendError> {
+        let path = path.into();
+        let state = if path.exists() { parse(read_to_string(&path)?)? } else { State::default() };
+
+        Ok(MockChain {
+            path: Some(path),
+            state: RefCell::new(state),
+            ..MockChain::new()
+        })
+    }
+
+    pub fn tip(&self) -> u64 {
+        self.state.borrow().tip
+    }
+
+    pub fn txs(&self) -> Vec<MockTx> {
+        self.state.borrow().txs.clone()
+    }
+
+    /// Adds transactions as they are, keeping their txids and heights. The tip
+    /// moves up to the highest of them.
+    pub fn seed(&self, txs: Vec<SourceTx>) -> Result<(), BackendError> {
+        {
+            let mut state = self.state.borrow_mut();
+            for tx in txs.into_iter() {
+                state.tip = state.tip.max(tx.height.unwrap_or(0));
+                state.txs.push(MockTx {
+                    txid: tx.txid,
+                    hex: tx.hex,
+                    sender: Some(tx.address),
+                    height: tx.height,
+                });
+            }
+        }
+
+        self.save()
+    }
+
+    /// Seeds the chain from a fixture file, see `index::FixtureSource`.
+    pub fn seed_fixture<T: AsRef<str>>(&self, path: T) -> Result<(), BackendError> {
+        let data = read_to_string(path.as_ref())?;
+        let txs = index::parse_fixture(data).map_err(|e| invalid(&e.to_string()))?;
+
+        self.seed(txs)
+    }
+
+    /// Puts `value` satoshis in the mempool for `address`, as if mined out of thin air.
+    pub fn fund<T: AsRef<str>>(&self, address: T, value: u64) -> Result<String, BackendError> {
+        let (_, hash) = key::address_hash(address.as_ref()).map_err(|e| invalid(&e.to_string()))?;
+        let mut coinbase = Transaction::new();
+        coinbase.add_input(TxIn {
+            prev_txid: [0; 32],
+            prev_vout: u32::MAX,
+            script_sig: (self.state.borrow().txs.len() as u32).to_le_bytes().to_vec(),
+            sequence: u32::MAX,
+        });
+        coinbase.add_output(TxOut::p2pkh(value, &hash));
+
+        let txid = coinbase.txid();
+        self.state.borrow_mut().txs.push(MockTx {
+            txid: txid.clone(),
+            hex: coinbase.to_hex(),
+            sender: None,
+            height: None,
+        });
+        self.save()?;
+
+        Ok(txid)
+    }
+
+    /// Funds and signs a transaction carrying `actions` from `key`'s address, like a
+    /// wallet would, and broadcasts it. For putting other people's accounts on the chain.
+    pub fn publish(&self, key: &PrivateKey, actions: &[Action]) -> Result<String, BackendError> {
+        let address = key.address();
+        let (_, hash) = key::address_hash(&address).map_err(|e| invalid(&e.to_string()))?;
+        self.fund(&address, 10_000)?;
+        let utxos = self.utxos(&address)?;
+
+        let funded = wallet::fund(crate::opreturn_tx(actions), None, &utxos, &hash, wallet::DEFAULT_FEE_RATE, false)
+            .and_then(|mut funded| funded.sign(key).map(|_| funded))
+            .map_err(|e| invalid(&e.to_string()))?;
+        self.broadcast(&funded.tx.to_hex())
+    }
+
+    /// Mines `blocks` blocks, the first holding everything in the mempool, and returns the new tip.
+    pub fn mine(&self, blocks: u64) -> Result<u64, BackendError> {
+        {
+            let mut state = self.state.borrow_mut();
+            if blocks > 0 {
+                let height = state.tip + 1;
+                for tx in state.txs.iter_mut().filter(|tx| tx.height.is_none()) {
+                    tx.height = Some(height);
+                }
+            }
+            state.tip += blocks;
+        }
+        self.save()?;
+
+        Ok(self.tip())
+    }
+
+    /// Undoes the top `depth` blocks. Their transactions go back to the mempool,
+    /// and their txids are returned.
+    pub fn reorg(&self, depth: u64) -> Result<Vec<String>, BackendError> {
+        let mut returned = vec![];
+        {
+            let mut state = self.state.borrow_mut();
+            let tip = state.tip.saturating_sub(depth);
+            for tx in state.txs.iter_mut().filter(|tx| matches!(tx.height, Some(h) if h > tip)) {
+                tx.height = None;
+                returned.push(tx.txid.clone());
+            }
+            state.tip = tip;
+        }
+        self.save()?;
+
+        Ok(returned)
+    }
+
+    /// Drops an unconfirmed transaction, as if it fell out of every mempool.
+    pub fn evict<T: AsRef<str>>(&self, txid: T) -> Result<(), BackendError> {
+        self.state
+            .borrow_mut()
+            .txs
+            .retain(|tx| tx.txid != txid.as_ref() || tx.height.is_some());
+
+        self.save()
+    }
+
+    /// 1 for a transaction in the tip block, `None` in the mempool or if unknown.
+    pub fn confirmations<T: AsRef<str>>(&self, txid: T) -> Option<u64> {
+        let state = self.state.borrow();
+        let height = state.txs.iter().find(|tx| tx.txid == txid.as_ref())?.height?;
+
+        Some(state.tip + 1 - height)
+    }
+
+    pub fn to_toml(&self) -> String {
+        let state = self.state.borrow();
+        let mut toml = format!("tip = {}\n", state.tip);
+        for tx in state.txs.iter() {
+            toml.push_str("\n[[tx]]\n");
+            toml.push_str(&format!("txid = {}\n", quote(&tx.txid)));
+            if let Some(sender) = &tx.sender {
+                toml.push_str(&format!("address = {}\n", quote(sender)));
+            }
+            if let Some(height) = tx.height {
+                toml.push_str(&format!("height = {}\n", height));
+            }
+            toml.push_str(&format!("hex = {}\n", quote(&tx.hex)));
+        }
+
+        toml
+    }
+
+    fn save(&self) -> Result<(), BackendError> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)?;
+            }
+            write(path, self.to_toml())?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `tx` pays `hash`, or was sent by `address`.
+    fn touches(&self, tx: &MockTx, address: &str, hash: &[u8]) -> bool {
+        if tx.sender.as_deref() == Some(address) {
+            return true;
+        }
+        match Transaction::from_hex(&tx.hex) {
+            Ok(decoded) => decoded.outputs.iter().any(|o| tx::p2pkh_hash(&o.script_pubkey) == Some(hash)),
+            Err(_) => false,
+        }
+    }
+}
+
+impl Default for MockChain {
+    fn default() -> Self {
+        MockChain::new()
+    }
+}
+
+impl ChainBackend for MockChain {
+    /// Accepts any well formed transaction whose inputs exist and are unspent.
+    /// Broadcasting a known transaction again just returns its txid.
+    fn broadcast(&self, hex: &str) -> Result<String, BackendError> {
+        let tx = Transaction::from_hex(hex).map_err(|e| rejected(&e.to_string()))?;
+        let txid = tx.txid();
+        if self.state.borrow().txs.iter().any(|known| known.txid == txid) {
+            return Ok(txid);
+        }
+
+        let outpoints = outpoints(&self.state.borrow().txs);
+        for input in tx.inputs.iter() {
+            let outpoint = (tx::display_txid(&input.prev_txid), input.prev_vout);
+            match outpoints.iter().find(|(o, _)| *o == outpoint) {
+                None => return Err(rejected(&format!("missing input {}:{}", outpoint.0, outpoint.1))),
+                Some((_, true)) => return Err(rejected(&format!("{}:{} is already spent", outpoint.0, outpoint.1))),
+                Some((_, false)) => (),
+            }
+        }
+
+        let sender = match signer(&tx, self.network) {
+            Some(signer) => Some(signer),
+            None if tx.inputs.is_empty() => self.sender.clone(),
+            None => None,
+        };
+        self.state.borrow_mut().txs.push(MockTx {
+            txid: txid.clone(),
+            hex: hex.to_string(),
+            sender,
+            height: None,
+        });
+        self.save()?;
+
+        Ok(txid)
+    }
+
+    fn get_tx(&self, txid: &str) -> Result<ChainTx, BackendError> {
+        let state = self.state.borrow();
+        let tx = state
+            .txs
+            .iter()
+            .find(|tx| tx.txid == txid)
+            .ok_or_else(|| BackendError::from(Error::new(&format!("no transaction {}", txid), ErrorKind::NotFound)))?;
+
+        Ok(ChainTx {
+            txid: tx.txid.clone(),
+            hex: tx.hex.clone(),
+            height: tx.height,
+        })
+    }
+
+    fn address_history(&self, address: &str) -> Result<Vec<TxRef>, BackendError> {
+        let (_, hash) = key::address_hash(address).map_err(|e| invalid(&e.to_string()))?;
+        let mut refs: Vec<TxRef> = self
+            .state
+            .borrow()
+            .txs
+            .iter()
+            .filter(|tx| self.touches(tx, address, &hash))
+            .map(|tx| TxRef {
+                txid: tx.txid.clone(),
+                height: tx.height,
+            })
+            .collect();
+        refs.sort_by_key(|r| r.height.unwrap_or(u64::MAX));
+
+        Ok(refs)
+    }
+
+    fn utxos(&self, address: &str) -> Result<Vec<Utxo>, BackendError> {
+        let (_, hash) = key::address_hash(address).map_err(|e| invalid(&e.to_string()))?;
+        let state = self.state.borrow();
+        let spent: Vec<(String, u32)> = outpoints(&state.txs)
+            .into_iter()
+            .filter(|(_, spent)| *spent)
+            .map(|(outpoint, _)| outpoint)
+            .collect();
+
+        let mut utxos = vec![];
+        for mock in state.txs.iter() {
+            let tx = match Transaction::from_hex(&mock.hex) {
+                Ok(tx) => tx,
+                Err(_) => continue,
+            };
+            for (vout, output) in tx.outputs.iter().enumerate() {
+                let outpoint = (mock.txid.clone(), vout as u32);
+                if tx::p2pkh_hash(&output.script_pubkey) == Some(&hash[..]) && !spent.contains(&outpoint) {
+                    utxos.push(Utxo {
+                        txid: mock.txid.clone(),
+                        vout: vout as u32,
+                        value: output.value,
+                        height: mock.height,
+                    });
+                }
+            }
+        }
+
+        Ok(utxos)
+    }
+}
+
+/// Every output on the chain, and whether something on the chain spends it.
+fn outpoints(txs: &[MockTx]) -> Vec<((String, u32), bool)> {
+    let decoded: Vec<(String, Transaction)> = txs
+        .iter()
+        .filter_map(|mock| Transaction::from_hex(&mock.hex).ok().map(|tx| (mock.txid.clone(), tx)))
+        .collect();
+    let spends: Vec<(String, u32)> = decoded
+        .iter()
+        .flat_map(|(_, tx)| tx.inputs.iter().map(|i| (tx::display_txid(&i.prev_txid), i.prev_vout)))
+        .collect();
+
+    let mut outpoints = vec![];
+    for (txid, tx) in decoded.iter() {
+        for vout in 0..tx.outputs.len() as u32 {
+            let outpoint = (txid.clone(), vout);
+            let spent = spends.contains(&outpoint);
+            outpoints.push((outpoint, spent));
+        }
+    }
+
+    outpoints
+}
+
+/// The address whose public key ends the first input's script, like a P2PKH spend.
+fn signer(tx: &Transaction, network: Network) -> Option<String> {
+    let pushes = tx::script_pushes(&tx.inputs.first()?.script_sig).ok()?;
+    let pubkey = pushes.last().filter(|p| p.len() == 33 || p.len() == 65)?;
+
+    Some(key::p2pkh_address(pubkey, network))
+}
+
+/// Txids are stored byte-reversed in inputs.
+fn parse<T: AsRef<str>>(data: T) -> Result<State, BackendError> {
+    let value: toml::Value = data.as_ref().parse().map_err(|e: toml::de::Error| invalid(&e.to_string()))?;
+    let mut state = State {
+        tip: value.get("tip").and_then(|t| t.as_integer()).unwrap_or(0) as u64,
+        txs: vec![],
+    };
+
+    let empty = vec![];
+    let tables = value.get("tx").and_then(|t| t.as_array()).unwrap_or(&empty);
+    for table in tables.iter() {
+        let field = |key: &str| table.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+        let (txid, hex) = match (field("txid"), field("hex")) {
+            (Some(txid), Some(hex)) => (txid, hex),
+            _ => return Err(invalid("every [[tx]] needs a txid and hex")),
+        };
+        state.txs.push(MockTx {
+            txid,
+            hex,
+            sender: field("address"),
+            height: table.get("height").and_then(|h| h.as_integer()).map(|h| h as u64),
+        });
+    }
+
+    Ok(state)
+}
+
+fn quote(s: &str) -> String {
+    toml::Value::String(s.to_string()).to_string()
+}
+
+fn rejected(msg: &str) -> BackendError {
+    BackendError::from(Error::new(msg, ErrorKind::Rejected))
+}
+
+fn invalid(msg: &str) -> BackendError {
+    BackendError::from(Error::new(msg, ErrorKind::InvalidResponse))
+}
+
+#[cfg(test)]
+mod chain {
+    use super::*;
+    use crate::index::testing::{ALICE, BOB, PRIVKEY};
+    use crate::index::{ChainSource, Index};
+    use crate::sign;
+
+    #[test]
+    fn mock_broadcast_mine_reorg() {
+        let chain = MockChain { sender: Some(ALICE.to_string()), ..MockChain::new() };
+        let txid = chain.broadcast(&sign(&Action::instantiate())).unwrap();
+
+        assert_eq!(chain.broadcast(&sign(&Action::instantiate())).unwrap(), txid);
+        assert_eq!(chain.get_tx(&txid).unwrap().height, None);
+        assert!(chain.broadcast("00").is_err());
+
+        chain.mine(3).unwrap();
+        assert_eq!(chain.get_tx(&txid).unwrap().height, Some(1));
+        assert_eq!(chain.confirmations(&txid), Some(3));
+
+        assert!(chain.reorg(2).unwrap().is_empty());
+        assert_eq!(chain.reorg(1).unwrap(), vec![txid.clone()]);
+        assert_eq!(chain.confirmations(&txid), None);
+        assert_eq!(chain.address_history(ALICE).unwrap(), vec![TxRef { txid: txid.clone(), height: None }]);
+
+        chain.evict(&txid).unwrap();
+        assert!(chain.get_tx(&txid).is_err());
+    }
+
+    #[test]
+    fn mock_utxos_and_double_spends() {
+        let chain = MockChain::new();
+        let funding = chain.fund(ALICE, 100_000).unwrap();
+        chain.mine(1).unwrap();
+
+        let utxos = chain.utxos(ALICE).unwrap();
+        assert_eq!(utxos, vec![Utxo { txid: funding.clone(), vout: 0, value: 100_000, height: Some(1) }]);
+
+        let mut prev_txid = [0u8; 32];
+        prev_txid.copy_from_slice(&hex::decode(&funding).unwrap());
+        prev_txid.reverse();
+        let (_, bob) = key::address_hash(BOB).unwrap();
+        let mut spend = Transaction::new();
+        spend.add_input(TxIn { prev_txid, prev_vout: 0, script_sig: vec![], sequence: u32::MAX });
+        spend.add_output(TxOut::p2pkh(90_000, &bob));
+        chain.broadcast(&spend.to_hex()).unwrap();
+
+        spend.outputs[0].value = 80_000;
+        assert!(chain.broadcast(&spend.to_hex()).is_err());
+        assert!(chain.utxos(ALICE).unwrap().is_empty());
+        assert_eq!(chain.utxos(BOB).unwrap()[0].value, 90_000);
+    }
+
+    #[test]
+    fn mock_seeded_chain_indexes() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("repoint-mock-chain-{}.toml", std::process::id()));
+        let chain = MockChain::open(&path).unwrap();
+        let key = PrivateKey::from_wif(PRIVKEY).unwrap();
+        chain.publish(&key, &[Action::instantiate(), Action::create_account("7db9a").unwrap()]).unwrap();
+        // Seeded as Alice's, but nothing signs it, so it isn't hers.
+        let unsigned = SourceTx {
+            txid: "a2".to_string(),
+            address: ALICE.to_string(),
+            height: Some(650_000),
+            hex: sign(&Action::create_repo("repoint").unwrap()),
+        };
+        chain.seed(vec![unsigned]).unwrap();
+
+        let reopened = MockChain::open(&path).unwrap();
+        assert_eq!(reopened.tip(), 650_000);
+        let mut source = ChainSource::new(&reopened, vec![ALICE.to_string()]);
+        let index = Index::build(&mut source).unwrap();
+
+        assert_eq!(index.account(ALICE).unwrap().name.as_deref(), Some("7db9a"));
+        assert!(index.account(ALICE).unwrap().repos.is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,154 +1,274 @@
 pub mod repoint_file;
-use std::process::Command;
+pub mod tx;
+pub mod opcode;
+pub mod decode;
+pub mod index;
+pub mod query;
+pub mod resolve;
+pub mod keystore;
+pub mod key;
+pub mod session;
+pub mod provider;
+pub mod config;
+pub mod manifest;
+pub mod account_file;
+pub mod diff;
+pub mod ledger;
+pub mod backend;
+pub mod whatsonchain;
+pub mod rpc;
+pub mod mock_chain;
+pub mod wallet;
+pub mod social;
+pub mod redirect;
+pub mod moderation;
+#[cfg(test)]
+pub mod replay;
 use std::path::PathBuf;
-use cmd_lib::run_fun;
+use opcode::{Action, OpcodeError};
+use tx::{Transaction, TxOut};
+use backend::{ChainBackend, Utxo};
+use config::{Config, ConfigError};
+use provider::KeyProvider;
 
-pub fn init_sign(cmd: String) -> Result<std::process::Output, std::io::Error> {
-    sign(
-        cmd,
-        String::from("0x7202"),
-        String::from("c2859d6ace2072662e22bd2e197c790fffca56ac6030800139800a3d1f87866f")
-    )
+pub fn init_sign() -> Result<String, OpcodeError> {
+    Ok(sign(&Action::instantiate()))
 }
 
-pub fn create_account_sign(cmd: String, account_name: String) -> Result<std::process::Output, std::io::Error> {
-    sign(
-        cmd,
-        String::from("0x7203"),
-        account_name
-    )
+pub fn create_account_sign(account_name: String) -> Result<String, OpcodeError> {
+    Ok(sign(&Action::create_account(account_name)?))
 }
 
-pub fn create_repo_sign(cmd: String, repo_name: String) -> Result<std::process::Output, std::io::Error> {
-    sign(
-        cmd,
-        String::from("0x7206"),
-        repo_name
-    )
+pub fn create_repo_sign(repo_name: String) -> Result<String, OpcodeError> {
+    Ok(sign(&Action::create_repo(repo_name)?))
 }
 
-pub fn add_url_sign(cmd: String, url: String) -> Result<std::process::Output, std::io::Error> {
-    sign(
-        cmd,
-        String::from("0x7209"),
-        url
-    )
+pub fn add_url_sign(repo_index: u64, url: String) -> Result<String, OpcodeError> {
+    Ok(sign(&Action::add_repo_url(repo_index, url)?))
 }
 
-pub fn sign(cmd: String, opcode: String, msg: String) -> Result<std::process::Output, std::io::Error> {
-     Command::new("sh")
-        .arg(cmd)
-        .arg(
-            get_privkey()// get from account.toml
-        )
-        .arg(opcode)  // hard-code
-        .arg(msg)     // hard-code (app-ID)
-        //.arg(fee)     // cli arg
-        //.arg(rpc_url) // get from env var
-        //.arg(safe)
-        .output()
+/// `$HOME/.repoint`
+pub fn home_dir() -> PathBuf {
+    let mut pathbuf = dirs::home_dir().unwrap();
+    pathbuf.push(".repoint");
+    pathbuf
 }
 
-pub fn get_privkey() -> String {
-     let mut pathbuf = dirs::home_dir().unwrap();
-     pathbuf.push(".repoint");
-     pathbuf.push("account.toml");
-
-     let path = pathbuf.to_str().expect("fail to convert pathbuf into string");
+/// `$HOME/.repoint/<file>`
+pub fn home_path<T: AsRef<str>>(file: T) -> PathBuf {
+    home_dir().join(file.as_ref())
+}
 
-    let toml_doc = repoint_file::open(path).expect("failed to open toml file");
-    let privkey = toml_doc["account"]["xpriv"]
-        .as_str()
-        .expect("failed to parse privkey from account.toml")
-        .to_string();
+/// Builds the index from a toml fixture of transactions, by default `$HOME/.repoint/chain.toml`.
+/// Without a fixture, it reads `addresses` from the chain backend instead.
+pub fn load_index(
+    source: Option<String>,
+    backend: &dyn backend::ChainBackend,
+    addresses: &[String],
+) -> Result<index::Index, index::IndexError> {
+    let path = match source {
+        Some(path) => path,
+        None => {
+            let path = home_path("chain.toml");
+            if !path.exists() {
+                let mut chain = index::ChainSource::new(backend, addresses.to_vec());
+                return index::Index::build(&mut chain);
+            }
+            path.to_str().unwrap().to_string()
+        }
+    };
 
-    assert_eq!(privkey, "5JZ4RXH4MoXpaUQMcJHo8DxhZtkf5U5VnYd9zZH8BRKZuAbxZEw");
+    index::Index::build(&mut index::FixtureSource::new(path))
+}
 
-    privkey
+/// Builds the op-return transaction for `action`, returned as raw hex.
+pub fn sign(action: &Action) -> String {
+    opreturn_tx(std::slice::from_ref(action)).to_hex()
 }
 
-pub fn send_opreturn(test: bool) {
-    if test {
+/// The unfunded transaction carrying `actions`, an op-return output each. The
+/// indexer applies them in output order, so they stay in the order given.
+pub fn opreturn_tx(actions: &[Action]) -> Transaction {
+    let mut tx = Transaction::new();
+    for action in actions.iter() {
+        tx.add_output(TxOut::opreturn(&action.pushes()));
+    }
+
+    tx
+}
 
+/// How many actions go in one transaction unless config.toml says otherwise.
+pub const DEFAULT_BATCH: usize = 25;
 
+/// Funds a transaction per `batch` actions from `utxos`, chained so each spends the
+/// change of the one before, the first spending `parent` if there is one. Change goes
+/// back to `address`. Sign them with `wallet::sign_chain`.
+pub fn fund_actions<T: AsRef<str>>(
+    actions: &[Action],
+    parent: Option<Utxo>,
+    mut utxos: Vec<Utxo>,
+    address: T,
+    fee_rate: f64,
+    batch: usize,
+) -> Result<Vec<wallet::Funded>, wallet::WalletError> {
+    let (_, change_hash) = key::address_hash(address.as_ref())?;
+    let chunks: Vec<&[Action]> = actions.chunks(batch.max(1)).collect();
+    let mut parent = parent;
+    let mut funded = vec![];
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = i + 1 < chunks.len();
+        let tx = wallet::fund(opreturn_tx(chunk), parent.as_ref(), &utxos, &change_hash, fee_rate, more)?;
+        utxos.retain(|utxo| !tx.spent.contains(utxo));
+        parent = tx.change_utxo();
+        funded.push(tx);
     }
+
+    Ok(funded)
 }
 
-pub fn exists_opreturn(test: bool) {
-    if test {
-    }
+/// Funds the transaction that pays `payee` `amount` satoshis alongside the tip `action`,
+/// spending `parent` first if there is one. Change goes back to `address`.
+pub fn fund_tip<T: AsRef<str>>(
+    action: &Action,
+    payee: T,
+    amount: u64,
+    parent: Option<Utxo>,
+    utxos: Vec<Utxo>,
+    address: T,
+    fee_rate: f64,
+) -> Result<wallet::Funded, wallet::WalletError> {
+    let (_, payee_hash) = key::address_hash(payee.as_ref())?;
+    let (_, change_hash) = key::address_hash(address.as_ref())?;
+    let mut tx = opreturn_tx(std::slice::from_ref(action));
+    tx.add_output(TxOut::p2pkh(amount, &payee_hash));
+
+    wallet::fund(tx, parent.as_ref(), &utxos, &change_hash, fee_rate, false)
+}
+
+/// Satoshis per byte: `[fees] rate` from config.toml, else what the backend suggests, else `wallet::DEFAULT_FEE_RATE`.
+pub fn fee_rate(config: &Config, backend: &dyn ChainBackend) -> f64 {
+    config
+        .fee_rate
+        .or_else(|| backend.fee_rate().ok().flatten())
+        .unwrap_or(wallet::DEFAULT_FEE_RATE)
+}
+
+/// The ledger of what `address` has published, `$HOME/.repoint/ledger/<address>.toml`.
+pub fn open_ledger<T: AsRef<str>>(address: T) -> Result<ledger::Ledger, ledger::LedgerError> {
+    ledger::Ledger::open(ledger::Ledger::path_for(&home_dir(), address))
+}
+
+/// The chain backend set in config.toml, WhatsOnChain mainnet by default.
+pub fn chain_backend() -> Result<Box<dyn backend::ChainBackend>, ConfigError> {
+    Ok(config::load(home_path("config.toml"))?.backend.backend()?)
+}
+
+/// The key provider named by `source`, e.g. from `--key-source`, or else the one set in config.toml.
+pub fn key_provider(source: Option<String>) -> Result<Box<dyn KeyProvider>, ConfigError> {
+    let source = match source {
+        Some(source) => source.parse()?,
+        None => config::load(home_path("config.toml"))?.key_source,
+    };
+
+    Ok(source.provider(home_dir()))
+}
+
+pub fn get_privkey() -> String {
+    let provider = key_provider(None).expect("failed to read config.toml");
+    provider.privkey().expect("failed to get private key")
 }
 
 // test get_privkey
 #[cfg(test)]
 mod account_toml {
-    use std::path::PathBuf;
     use super::*;
+    use opcode::Opcode;
 
+    // The account provider get_privkey uses by default, over a scratch home instead of $HOME.
     #[test]
     fn test_get_privkey() {
+         let privkey = "5JZ4RXH4MoXpaUQMcJHo8DxhZtkf5U5VnYd9zZH8BRKZuAbxZEw";
+         let address = key::PrivateKey::from_wif(privkey).unwrap().address();
+         let mut home = std::env::temp_dir();
+         home.push(format!("repoint-get-privkey-{}", std::process::id()));
+         std::fs::create_dir_all(&home).unwrap();
+         let account = format!("default = \"7db9a\"\n\n[accounts.7db9a]\npubaddr = \"{}\"\nxpriv = \"{}\"\n", address, privkey);
+         std::fs::write(home.join("account.toml"), account).unwrap();
 
          assert_eq!(
-             get_privkey(),
-             "5JZ4RXH4MoXpaUQMcJHo8DxhZtkf5U5VnYd9zZH8BRKZuAbxZEw")
+             provider::KeySource::Account.provider(home.clone()).privkey().unwrap(),
+             privkey);
+
+         std::fs::remove_dir_all(home).unwrap();
     }
 
     #[test]
     fn test_init_sign() {
-         let output = init_sign(
-             "opreturn.sh".to_string(),
-         ).expect("opreturn shell call failed");
-
-         let stdout = String::from_utf8_lossy(&output.stdout);
+         let stdout = init_sign().expect("failed to build opreturn tx");
 
          assert_eq!(
              stdout,
-             "010000000001000000000000000046006a027202406332383539643661636532303732363632653232626432653139376337393066666663613536616336303330383030313339383030613364316638373836366600000000\n"
+             "010000000001000000000000000046006a027202406332383539643661636532303732363632653232626432653139376337393066666663613536616336303330383030313339383030613364316638373836366600000000"
         )
     }
 
     #[test]
     fn test_create_account_sign() {
-         let output = create_account_sign(
-             "opreturn.sh".to_string(),
+         let stdout = create_account_sign(
              "7db9a".to_string(),
-         ).expect("opreturn shell call failed");
-
-         let stdout = String::from_utf8_lossy(&output.stdout);
+         ).expect("failed to build opreturn tx");
 
          assert_eq!(
              stdout,
-             "01000000000100000000000000000b006a02720305376462396100000000\n",
+             "01000000000100000000000000000b006a02720305376462396100000000",
         )
     }
 
     #[test]
     fn test_create_repo_sign() {
-         let output = create_repo_sign(
-             "opreturn.sh".to_string(),
+         let stdout = create_repo_sign(
              "repoint".to_string(),
-         ).expect("opreturn shell call failed");
-
-         let stdout = String::from_utf8_lossy(&output.stdout);
+         ).expect("failed to build opreturn tx");
 
          assert_eq!(
              stdout,
-             "01000000000100000000000000000d006a027206077265706f696e7400000000\n"
+             "01000000000100000000000000000d006a027206077265706f696e7400000000"
         )
     }
 
     #[test]
     fn test_add_url_sign() {
-         let output = create_repo_sign(
-             "opreturn.sh".to_string(),
+         let stdout = create_repo_sign(
              "https://github.com/7db9a/repoint".to_string(),
-         ).expect("opreturn shell call failed");
-
-         let stdout = String::from_utf8_lossy(&output.stdout);
+         ).expect("failed to build opreturn tx");
 
          assert_eq!(
              stdout,
-             "010000000001000000000000000026006a0272062068747470733a2f2f6769746875622e636f6d2f37646239612f7265706f696e7400000000\n"
+             "010000000001000000000000000026006a0272062068747470733a2f2f6769746875622e636f6d2f37646239612f7265706f696e7400000000"
         )
     }
+
+    #[test]
+    fn test_fund_actions_in_chained_batches() {
+        let actions = vec![
+            Action::create_repo("repoint").unwrap(),
+            Action::builder(Opcode::RepoDescription).repo_index(0).message("Point to your repos.").build().unwrap(),
+            Action::add_repo_url(0, "https://github.com/7db9a/repoint").unwrap(),
+        ];
+        let utxos: Vec<Utxo> = (0..2)
+            .map(|vout| Utxo {
+                txid: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string(),
+                vout,
+                value: 10_000,
+                height: Some(1),
+            })
+            .collect();
+        let funded = fund_actions(&actions, None, utxos, index::testing::ALICE, 0.5, 2).unwrap();
+
+        assert_eq!(funded.len(), 2);
+        assert_eq!(decode::decode_tx(&funded[0].tx).unwrap(), actions[..2].to_vec());
+        assert_eq!(decode::decode_tx(&funded[1].tx).unwrap(), actions[2..].to_vec());
+        // The second spends the first's change rather than the other coin.
+        assert_eq!(funded[1].spent, vec![funded[0].change_utxo().unwrap()]);
+    }
 }
@@ -0,0 +1,152 @@
+/*
+This module decides which flags count. Anyone can flag any repo, so flags only mean something coming from someone you trust: yourself, the owners of repos you follow, and the addresses you list in config.toml. Repos flagged by them are hidden from `query` or marked, as configured.
+
+```ignore
+[moderation]
+trust_self = true
+trust_followed = true
+trusted = ["1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt"]
+flagged = "hide"
+```
+*/
+use crate::index::{Index, RepoRef};
+use crate::query::RepoMatch;
+
+use std::collections::BTreeSet;
+
+/// What `query` does with repos that someone trusted has flagged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FlagAction {
+    /// Leave them out.
+    Hide,
+    /// List them, marked with who flagged them.
+    Annotate,
+}
+
+/// Whose flags count, from config.toml's `[moderation]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    /// Your own accounts.
+    pub trust_self: bool,
+    /// The owners of repos your accounts follow.
+    pub trust_followed: bool,
+    /// Other addresses whose flags count.
+    pub trusted: Vec<String>,
+    pub flagged: FlagAction,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            trust_self: true,
+            trust_followed: true,
+            trusted: vec![],
+            flagged: FlagAction::Annotate,
+        }
+    }
+}
+
+impl Policy {
+    /// The addresses whose flags count for someone with accounts at `own`.
+    pub fn trusted_addresses(&self, index: &Index, own: &[String]) -> BTreeSet<String> {
+        let mut trusted: BTreeSet<String> = self.trusted.iter().cloned().collect();
+        if self.trust_self {
+            trusted.extend(own.iter().cloned());
+        }
+        if self.trust_followed {
+            for address in own.iter() {
+                trusted.extend(index.following(address).into_iter().map(|repo| repo.address.clone()));
+            }
+        }
+
+        trusted
+    }
+}
+
+/// Who among `trusted` flags `repo`.
+pub fn flagged_by<'a>(index: &'a Index, repo: &RepoRef, trusted: &BTreeSet<String>) -> Vec<&'a String> {
+    index.flags(repo).into_iter().filter(|address| trusted.contains(*address)).collect()
+}
+
+/// Hides or marks the matches flagged by someone in `trusted`.
+pub fn moderate<'a>(
+    index: &'a Index,
+    matches: Vec<RepoMatch<'a>>,
+    trusted: &BTreeSet<String>,
+    action: FlagAction,
+) -> Vec<RepoMatch<'a>> {
+    matches
+        .into_iter()
+        .filter_map(|mut m| {
+            let repo = RepoRef::new(&m.account.address, m.repo.index);
+            m.flagged_by = flagged_by(index, &repo, trusted)
+                .into_iter()
+                .map(|address| index.account(address).and_then(|a| a.name.clone()).unwrap_or_else(|| address.clone()))
+                .collect();
+            match action {
+                FlagAction::Hide if !m.flagged_by.is_empty() => None,
+                _ => Some(m),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod policy {
+    use super::*;
+    use crate::opcode::{Action, Opcode};
+    use crate::index::testing::{self, ALICE, BOB, CAROL};
+    use crate::query::{query, Query};
+
+    fn edge(opcode: Opcode, address: &str, index: u64) -> Action {
+        Action::builder(opcode).address(address).repo_index(index).build().unwrap()
+    }
+
+    // Bob and Carol each have a repo; Carol flags Bob's, and Alice follows Carol's.
+    fn setup_flagged(extra: Vec<(&str, Action)>) -> Index {
+        let accounts: &[(&str, &str, &[&str])] =
+            &[(ALICE, "7db9a", &[]), (BOB, "bob", &["spam"]), (CAROL, "carol", &["notes"])];
+        let mut actions = vec![(CAROL, edge(Opcode::FlagRepo, BOB, 0))];
+        actions.extend(extra);
+
+        testing::setup_index(accounts, actions)
+    }
+
+    fn shown(index: &Index, policy: &Policy) -> Vec<String> {
+        let trusted = policy.trusted_addresses(index, &[ALICE.to_string()]);
+        moderate(index, query(index, &Query::default()), &trusted, policy.flagged)
+            .iter()
+            .map(|m| m.summary())
+            .collect()
+    }
+
+    #[test]
+    fn untrusted_flags_dont_count() {
+        let index = setup_flagged(vec![]);
+        let hide = Policy { flagged: FlagAction::Hide, ..Policy::default() };
+
+        assert_eq!(shown(&index, &hide).len(), 2);
+        assert!(flagged_by(&index, &RepoRef::new(BOB, 0), &BTreeSet::new()).is_empty());
+    }
+
+    #[test]
+    fn followed_flags_hide_or_annotate() {
+        let index = setup_flagged(vec![(ALICE, edge(Opcode::FollowRepo, CAROL, 0))]);
+        let hide = Policy { flagged: FlagAction::Hide, ..Policy::default() };
+
+        assert_eq!(shown(&index, &hide), vec![format!("carol/notes\t{}:0\t", CAROL)]);
+        assert!(shown(&index, &Policy::default()).contains(&format!("bob/spam\t{}:0\t\tflagged by carol", BOB)));
+        let ignore_follows = Policy { trust_followed: false, ..hide };
+        assert_eq!(shown(&index, &ignore_follows).len(), 2);
+    }
+
+    #[test]
+    fn allowlist_and_self() {
+        let index = setup_flagged(vec![(ALICE, edge(Opcode::FlagRepo, CAROL, 0))]);
+        let hide = Policy { flagged: FlagAction::Hide, trusted: vec![CAROL.to_string()], ..Policy::default() };
+
+        assert!(shown(&index, &hide).is_empty());
+        let nobody = Policy { trust_self: false, trusted: vec![], ..hide };
+        assert_eq!(shown(&index, &nobody).len(), 2);
+    }
+}
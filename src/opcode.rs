@@ -0,0 +1,541 @@
+/*
+This module is the single source of truth for the repoint protocol table in the README.
+*/
+use crate::tx;
+
+use err::Error;
+pub use err::{ErrorKind, OpcodeError};
+
+/// The app-ID pushed by `Instantiate repoint`. Marks the protocol version.
+pub const APP_ID: &str = "c2859d6ace2072662e22bd2e197c790fffca56ac6030800139800a3d1f87866f";
+
+/// Every op-return starts with this byte. It's 'r', for repoint.
+pub const PREFIX: u8 = 0x72;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Instantiate,
+    CreateAccount,
+    ProfileText,
+    ProfilePic,
+    CreateRepo,
+    RepoDescription,
+    AddRepoTags,
+    AddRepoUrl,
+    RemoveRepoUrl,
+    LikeRepo,
+    UnlikeRepo,
+    FlagRepo,
+    UnflagRepo,
+    TipRepo,
+    FollowRepo,
+    UnfollowRepo,
+    RedirectAccount,
+    RedirectRepo,
+}
+
+pub const OPCODES: [Opcode; 18] = [
+    Opcode::Instantiate,
+    Opcode::CreateAccount,
+    Opcode::ProfileText,
+    Opcode::ProfilePic,
+    Opcode::CreateRepo,
+    Opcode::RepoDescription,
+    Opcode::AddRepoTags,
+    Opcode::AddRepoUrl,
+    Opcode::RemoveRepoUrl,
+    Opcode::LikeRepo,
+    Opcode::UnlikeRepo,
+    Opcode::FlagRepo,
+    Opcode::UnflagRepo,
+    Opcode::TipRepo,
+    Opcode::FollowRepo,
+    Opcode::UnfollowRepo,
+    Opcode::RedirectAccount,
+    Opcode::RedirectRepo,
+];
+
+/// What an opcode expects between itself and the message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Appendix {
+    None,
+    AppId,
+    RepoIndex,
+    Account,
+    AccountRepo,
+    /// The README's repo-index driven form: the redirected repo, then its `$account-address $repo-index`.
+    RepoRedirect,
+}
+
+/// What an opcode expects as its message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Message {
+    None,
+    Name,
+    Text,
+    Uri,
+}
+
+impl Opcode {
+    /// The opcode as it's written in the README, e.g. `0x7202`.
+    pub fn code(&self) -> u16 {
+        match self {
+            Opcode::Instantiate => 0x7202,
+            Opcode::CreateAccount => 0x7203,
+            Opcode::ProfileText => 0x7204,
+            Opcode::ProfilePic => 0x7205,
+            Opcode::CreateRepo => 0x7206,
+            Opcode::RepoDescription => 0x7207,
+            Opcode::AddRepoTags => 0x7208,
+            Opcode::AddRepoUrl => 0x7209,
+            Opcode::RemoveRepoUrl => 0x7210,
+            Opcode::LikeRepo => 0x7211,
+            Opcode::UnlikeRepo => 0x7212,
+            Opcode::FlagRepo => 0x7213,
+            Opcode::UnflagRepo => 0x7214,
+            Opcode::TipRepo => 0x7215,
+            Opcode::FollowRepo => 0x7216,
+            Opcode::UnfollowRepo => 0x7217,
+            Opcode::RedirectAccount => 0x7218,
+            Opcode::RedirectRepo => 0x7219,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Opcode::Instantiate => "Instantiate repoint",
+            Opcode::CreateAccount => "Create account",
+            Opcode::ProfileText => "Update profile text",
+            Opcode::ProfilePic => "New profile pic",
+            Opcode::CreateRepo => "Create repo",
+            Opcode::RepoDescription => "New repo description",
+            Opcode::AddRepoTags => "Add repo tags",
+            Opcode::AddRepoUrl => "Add repo url",
+            Opcode::RemoveRepoUrl => "Remove repo url",
+            Opcode::LikeRepo => "Like repo",
+            Opcode::UnlikeRepo => "Unlike repo",
+            Opcode::FlagRepo => "Flag repo",
+            Opcode::UnflagRepo => "Unflag repo",
+            Opcode::TipRepo => "Tip repo",
+            Opcode::FollowRepo => "Follow repo",
+            Opcode::UnfollowRepo => "Unfollow repo",
+            Opcode::RedirectAccount => "Redirect account to account",
+            Opcode::RedirectRepo => "Redirect repo to repo",
+        }
+    }
+
+    /// Tip repo identifies the repo like the other social opcodes, as the README's tip note says.
+    /// A repo redirect is repo-index driven, so the index of the repo it moves goes first.
+    pub fn appendix(&self) -> Appendix {
+        match self {
+            Opcode::Instantiate => Appendix::AppId,
+            Opcode::CreateAccount
+            | Opcode::ProfileText
+            | Opcode::ProfilePic
+            | Opcode::CreateRepo => Appendix::None,
+            Opcode::RepoDescription
+            | Opcode::AddRepoTags
+            | Opcode::AddRepoUrl
+            | Opcode::RemoveRepoUrl => Appendix::RepoIndex,
+            Opcode::RedirectAccount => Appendix::Account,
+            Opcode::LikeRepo
+            | Opcode::UnlikeRepo
+            | Opcode::FlagRepo
+            | Opcode::UnflagRepo
+            | Opcode::TipRepo
+            | Opcode::FollowRepo
+            | Opcode::UnfollowRepo => Appendix::AccountRepo,
+            Opcode::RedirectRepo => Appendix::RepoRedirect,
+        }
+    }
+
+    pub fn message(&self) -> Message {
+        match self {
+            Opcode::CreateAccount | Opcode::CreateRepo => Message::Name,
+            Opcode::ProfileText | Opcode::RepoDescription | Opcode::AddRepoTags => Message::Text,
+            Opcode::ProfilePic | Opcode::AddRepoUrl | Opcode::RemoveRepoUrl => Message::Uri,
+            _ => Message::None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 2] {
+        self.code().to_be_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Opcode, OpcodeError> {
+        if bytes.len() == 2 {
+            let code = u16::from_be_bytes([bytes[0], bytes[1]]);
+            if let Some(opcode) = OPCODES.iter().find(|op| op.code() == code) {
+                return Ok(*opcode);
+            }
+        }
+
+        let err = Error::new("unknown repoint opcode", ErrorKind::UnknownOpcode);
+        Err(OpcodeError::from(err))
+    }
+
+    /// Formats as `0x7202`.
+    pub fn to_hex(&self) -> String {
+        format!("0x{:04x}", self.code())
+    }
+
+    /// Parses `0x7202`.
+    pub fn from_hex<T: AsRef<str>>(opcode: T) -> Result<Opcode, OpcodeError> {
+        match tx::opcode_bytes(opcode) {
+            Ok(bytes) => Opcode::from_bytes(&bytes),
+            Err(_) => {
+                let err = Error::new("opcode isn't valid hex", ErrorKind::UnknownOpcode);
+                Err(OpcodeError::from(err))
+            }
+        }
+    }
+}
+
+/// One repoint op-return: `op-code [op-code-appendix] [msg]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Action {
+    pub opcode: Opcode,
+    pub app_id: Option<String>,
+    pub address: Option<String>,
+    pub repo_index: Option<u64>,
+    /// Only for repo redirects: the index of the repo being pointed to.
+    pub target_index: Option<u64>,
+    pub message: Option<String>,
+}
+
+impl Action {
+    pub fn builder(opcode: Opcode) -> ActionBuilder {
+        ActionBuilder {
+            action: Action {
+                opcode,
+                app_id: None,
+                address: None,
+                repo_index: None,
+                target_index: None,
+                message: None,
+            },
+        }
+    }
+
+    pub fn instantiate() -> Action {
+        Action::builder(Opcode::Instantiate)
+            .app_id(APP_ID)
+            .build()
+            .expect("app-ID action is always valid")
+    }
+
+    pub fn create_account<T: AsRef<str>>(name: T) -> Result<Action, OpcodeError> {
+        Action::builder(Opcode::CreateAccount).message(name).build()
+    }
+
+    pub fn create_repo<T: AsRef<str>>(name: T) -> Result<Action, OpcodeError> {
+        Action::builder(Opcode::CreateRepo).message(name).build()
+    }
+
+    pub fn add_repo_url<T: AsRef<str>>(repo_index: u64, uri: T) -> Result<Action, OpcodeError> {
+        Action::builder(Opcode::AddRepoUrl)
+            .repo_index(repo_index)
+            .message(uri)
+            .build()
+    }
+
+    /// The op-return pushes, in protocol order: opcode, appendix, message.
+    pub fn pushes(&self) -> Vec<Vec<u8>> {
+        let mut pushes = vec![self.opcode.to_bytes().to_vec()];
+        let index = |i: Option<u64>| i.map(|i| i.to_string().into_bytes());
+        let address = self.address.as_ref().map(|a| a.as_bytes().to_vec());
+        let appendix = match self.opcode.appendix() {
+            Appendix::None => vec![],
+            Appendix::AppId => vec![self.app_id.as_ref().map(|a| a.as_bytes().to_vec())],
+            Appendix::RepoIndex => vec![index(self.repo_index)],
+            Appendix::Account => vec![address],
+            Appendix::AccountRepo => vec![address, index(self.repo_index)],
+            Appendix::RepoRedirect => vec![index(self.repo_index), address, index(self.target_index)],
+        };
+        pushes.extend(appendix.into_iter().flatten());
+        if let Some(message) = &self.message {
+            pushes.push(message.as_bytes().to_vec());
+        }
+
+        pushes
+    }
+
+    /// Reads an action back from its op-return pushes, following the opcode's schema.
+    pub fn from_pushes(pushes: &[Vec<u8>]) -> Result<Action, OpcodeError> {
+        let (first, rest) = match pushes.split_first() {
+            Some(split) => split,
+            None => {
+                let err = Error::new("op-return has no opcode", ErrorKind::UnknownOpcode);
+                return Err(OpcodeError::from(err));
+            }
+        };
+        let opcode = Opcode::from_bytes(first)?;
+        let mut args = rest.iter();
+        let mut builder = Action::builder(opcode);
+
+        match opcode.appendix() {
+            Appendix::None => (),
+            Appendix::AppId => builder = builder.app_id(next_str(&mut args, "app-ID")?),
+            Appendix::RepoIndex => builder = builder.repo_index(next_index(&mut args)?),
+            Appendix::Account => builder = builder.address(next_str(&mut args, "account address")?),
+            Appendix::AccountRepo => {
+                builder = builder.address(next_str(&mut args, "account address")?);
+                builder = builder.repo_index(next_index(&mut args)?);
+            }
+            Appendix::RepoRedirect => {
+                builder = builder.repo_index(next_index(&mut args)?);
+                builder = builder.address(next_str(&mut args, "account address")?);
+                builder = builder.target_index(next_index(&mut args)?);
+            }
+        }
+        if opcode.message() != Message::None {
+            builder = builder.message(next_str(&mut args, "message")?);
+        }
+
+        if args.next().is_some() {
+            let err = Error::new(
+                &format!("too many pushes for {}", opcode.to_hex()),
+                ErrorKind::UnexpectedArgument,
+            );
+            return Err(OpcodeError::from(err));
+        }
+
+        builder.build()
+    }
+
+    /// Checks the arguments against the opcode's schema.
+    pub fn validate(&self) -> Result<(), OpcodeError> {
+        let appendix = self.opcode.appendix();
+        let wants_app_id = appendix == Appendix::AppId;
+        let wants_address = matches!(appendix, Appendix::Account | Appendix::AccountRepo | Appendix::RepoRedirect);
+        let wants_index = matches!(appendix, Appendix::RepoIndex | Appendix::AccountRepo | Appendix::RepoRedirect);
+        let wants_target = appendix == Appendix::RepoRedirect;
+        let wants_message = self.opcode.message() != Message::None;
+
+        check_arg("app-ID", wants_app_id, &self.app_id)?;
+        check_arg("account address", wants_address, &self.address)?;
+        check_arg("repo index", wants_index, &self.repo_index)?;
+        check_arg("target repo index", wants_target, &self.target_index)?;
+        check_arg("message", wants_message, &self.message)?;
+
+        let empty = |s: &Option<String>| s.as_deref().map(str::trim) == Some("");
+        if empty(&self.app_id) || empty(&self.address) || empty(&self.message) {
+            let err = Error::new(
+                &format!("{} arguments can't be empty", self.opcode.to_hex()),
+                ErrorKind::InvalidArgument,
+            );
+            return Err(OpcodeError::from(err));
+        }
+
+        Ok(())
+    }
+}
+
+fn next_str<'a, I: Iterator<Item = &'a Vec<u8>>>(args: &mut I, label: &str) -> Result<String, OpcodeError> {
+    match args.next() {
+        Some(bytes) => match String::from_utf8(bytes.clone()) {
+            Ok(s) => Ok(s),
+            Err(_) => {
+                let err = Error::new(&format!("{} isn't utf-8", label), ErrorKind::InvalidArgument);
+                Err(OpcodeError::from(err))
+            }
+        },
+        None => {
+            let err = Error::new(&format!("missing {}", label), ErrorKind::MissingArgument);
+            Err(OpcodeError::from(err))
+        }
+    }
+}
+
+fn next_index<'a, I: Iterator<Item = &'a Vec<u8>>>(args: &mut I) -> Result<u64, OpcodeError> {
+    let index = next_str(args, "repo index")?;
+    match index.parse::<u64>() {
+        Ok(i) if i.to_string() == index => Ok(i),
+        _ => {
+            let err = Error::new("repo index isn't a decimal number", ErrorKind::InvalidArgument);
+            Err(OpcodeError::from(err))
+        }
+    }
+}
+
+fn check_arg<T>(label: &str, wanted: bool, arg: &Option<T>) -> Result<(), OpcodeError> {
+    if wanted && arg.is_none() {
+        let err = Error::new(&format!("missing {}", label), ErrorKind::MissingArgument);
+        Err(OpcodeError::from(err))
+    } else if !wanted && arg.is_some() {
+        let err = Error::new(&format!("unexpected {}", label), ErrorKind::UnexpectedArgument);
+        Err(OpcodeError::from(err))
+    } else {
+        Ok(())
+    }
+}
+
+/// Assembles an `Action`; `build` rejects argument combinations the opcode doesn't take.
+pub struct ActionBuilder {
+    action: Action,
+}
+
+impl ActionBuilder {
+    pub fn app_id<T: AsRef<str>>(mut self, app_id: T) -> ActionBuilder {
+        self.action.app_id = Some(app_id.as_ref().to_string());
+        self
+    }
+
+    pub fn address<T: AsRef<str>>(mut self, address: T) -> ActionBuilder {
+        self.action.address = Some(address.as_ref().to_string());
+        self
+    }
+
+    pub fn repo_index(mut self, repo_index: u64) -> ActionBuilder {
+        self.action.repo_index = Some(repo_index);
+        self
+    }
+
+    pub fn target_index(mut self, target_index: u64) -> ActionBuilder {
+        self.action.target_index = Some(target_index);
+        self
+    }
+
+    pub fn message<T: AsRef<str>>(mut self, message: T) -> ActionBuilder {
+        self.action.message = Some(message.as_ref().to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<Action, OpcodeError> {
+        self.action.validate()?;
+
+        Ok(self.action)
+    }
+}
+
+mod err {
+    #[derive(Debug)]
+    pub enum OpcodeError {
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        UnknownOpcode,
+        MissingArgument,
+        UnexpectedArgument,
+        InvalidArgument,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for OpcodeError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                OpcodeError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<Error> for OpcodeError {
+        fn from(error: Error) -> Self {
+            OpcodeError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod protocol_table {
+    use super::*;
+
+    fn kind(res: Result<Action, OpcodeError>) -> ErrorKind {
+        match res.unwrap_err() {
+            OpcodeError::Error(e) => e.kind,
+        }
+    }
+
+    #[test]
+    fn opcode_hex_round_trip() {
+        for opcode in OPCODES.iter() {
+            assert_eq!(Opcode::from_hex(opcode.to_hex()).unwrap(), *opcode);
+            assert_eq!(opcode.to_bytes()[0], PREFIX);
+        }
+
+        assert_eq!(Opcode::RemoveRepoUrl.to_hex(), "0x7210");
+        assert!(Opcode::from_hex("0x720a").is_err());
+    }
+
+    #[test]
+    fn builder_rejects_bad_arguments() {
+        let missing = Action::builder(Opcode::AddRepoUrl).message("https://example.com").build();
+        let unexpected = Action::builder(Opcode::CreateRepo)
+            .repo_index(1)
+            .message("repoint")
+            .build();
+        let empty = Action::create_account("");
+
+        assert_eq!(kind(missing), ErrorKind::MissingArgument);
+        assert_eq!(kind(unexpected), ErrorKind::UnexpectedArgument);
+        assert_eq!(kind(empty), ErrorKind::InvalidArgument);
+    }
+
+    #[test]
+    fn pushes_follow_protocol_order() {
+        let action = Action::builder(Opcode::LikeRepo)
+            .address("1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt")
+            .repo_index(3)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            action.pushes(),
+            vec![
+                vec![0x72, 0x11],
+                b"1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt".to_vec(),
+                b"3".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_pushes_round_trip() {
+        let actions = vec![
+            Action::instantiate(),
+            Action::create_repo("repoint").unwrap(),
+            Action::add_repo_url(0, "https://github.com/7db9a/repoint").unwrap(),
+            Action::builder(Opcode::RedirectRepo)
+                .repo_index(0)
+                .address("1JvFXyZMC31ShnD8PSKgN1HKQ2kGQLVpCt")
+                .target_index(12)
+                .build()
+                .unwrap(),
+        ];
+
+        for action in actions {
+            assert_eq!(Action::from_pushes(&action.pushes()).unwrap(), action);
+        }
+    }
+
+    #[test]
+    fn from_pushes_rejects_bad_schema() {
+        let extra = vec![vec![0x72, 0x03], b"7db9a".to_vec(), b"extra".to_vec()];
+        let bad_index = vec![vec![0x72, 0x09], b"01".to_vec(), b"https://example.com".to_vec()];
+
+        assert_eq!(kind(Action::from_pushes(&extra)), ErrorKind::UnexpectedArgument);
+        assert_eq!(kind(Action::from_pushes(&bad_index)), ErrorKind::InvalidArgument);
+    }
+}
@@ -0,0 +1,284 @@
+/*
+This module decides where the signing key comes from. The account file is the default; CI can inject a key through the environment or stdin, and tests can hold one in memory.
+*/
+use crate::account_file::{self, AccountError, AccountKey};
+use crate::keystore::{self, KeystoreError};
+use crate::session;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use err::Error;
+pub use err::{ErrorKind, ProviderError};
+
+/// Env var read by `EnvVar::default()`.
+pub const PRIVKEY_VAR: &str = "REPOINT_PRIVKEY";
+
+/// Something that can hand over the WIF private key to sign with.
+pub trait KeyProvider {
+    fn privkey(&self) -> Result<String, ProviderError>;
+}
+
+/// Where to get the key, as written in config.toml's `[keys] source` or given with `--key-source`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeySource {
+    #[default]
+    Account,
+    Env,
+    Stdin,
+}
+
+impl FromStr for KeySource {
+    type Err = ProviderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "account" => Ok(KeySource::Account),
+            "env" => Ok(KeySource::Env),
+            "stdin" => Ok(KeySource::Stdin),
+            _ => {
+                let err = Error::new(
+                    &format!("unknown key source {}, expected account, env or stdin", s),
+                    ErrorKind::UnknownSource,
+                );
+                Err(ProviderError::from(err))
+            }
+        }
+    }
+}
+
+impl KeySource {
+    /// The provider for this source, with its default settings.
+    pub fn provider(self, home: PathBuf) -> Box<dyn KeyProvider> {
+        match self {
+            KeySource::Account => Box::new(AccountFile::new(home)),
+            KeySource::Env => Box::new(EnvVar::default()),
+            KeySource::Stdin => Box::new(Stdin),
+        }
+    }
+}
+
+/// The default account's key in account.toml, or the one cached by `login` while
+/// its session lasts. Encrypted keys prompt for the passphrase.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountFile {
+    pub account: PathBuf,
+    pub session: PathBuf,
+}
+
+impl AccountFile {
+    /// Uses `account.toml` and `session.toml` in the `home` dir, usually `$HOME/.repoint`.
+    pub fn new(home: PathBuf) -> AccountFile {
+        AccountFile {
+            account: home.join("account.toml"),
+            session: home.join("session.toml"),
+        }
+    }
+}
+
+impl KeyProvider for AccountFile {
+    fn privkey(&self) -> Result<String, ProviderError> {
+        let config = account_file::load(&self.account)?;
+        let account = config.default_account()?;
+
+        if let Ok(Some(session)) = session::load(&self.session) {
+            if session.address == account.pubaddr {
+                return Ok(session.privkey);
+            }
+        }
+
+        match &account.key {
+            AccountKey::Encrypted(key) => {
+                let passphrase = keystore::read_passphrase(format!("Passphrase for {}: ", account.name))?;
+                Ok(keystore::decrypt(key, passphrase)?)
+            }
+            AccountKey::Plain(privkey) => Ok(privkey.clone()),
+            AccountKey::Missing => Err(missing(&format!("account {} has no private key", account.name))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnvVar {
+    pub var: String,
+}
+
+impl Default for EnvVar {
+    fn default() -> Self {
+        EnvVar {
+            var: PRIVKEY_VAR.to_string(),
+        }
+    }
+}
+
+impl KeyProvider for EnvVar {
+    fn privkey(&self) -> Result<String, ProviderError> {
+        match std::env::var(&self.var) {
+            Ok(privkey) if !privkey.trim().is_empty() => Ok(privkey.trim().to_string()),
+            _ => Err(missing(&format!("{} isn't set", self.var))),
+        }
+    }
+}
+
+/// Reads one line from stdin, e.g. piped from a secrets manager.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stdin;
+
+impl KeyProvider for Stdin {
+    fn privkey(&self) -> Result<String, ProviderError> {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        if line.trim().is_empty() {
+            return Err(missing("no private key on stdin"));
+        }
+
+        Ok(line.trim().to_string())
+    }
+}
+
+/// A fixed key, for tests and for embedding the library.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InMemory {
+    pub privkey: String,
+}
+
+impl InMemory {
+    pub fn new<T: AsRef<str>>(privkey: T) -> InMemory {
+        InMemory {
+            privkey: privkey.as_ref().to_string(),
+        }
+    }
+}
+
+impl KeyProvider for InMemory {
+    fn privkey(&self) -> Result<String, ProviderError> {
+        Ok(self.privkey.clone())
+    }
+}
+
+fn missing(msg: &str) -> ProviderError {
+    ProviderError::from(Error::new(msg, ErrorKind::MissingKey))
+}
+
+mod err {
+    use super::{AccountError, KeystoreError};
+
+    #[derive(Debug)]
+    pub enum ProviderError {
+        IoError(std::io::Error),
+        AccountError(AccountError),
+        KeystoreError(KeystoreError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        MissingKey,
+        UnknownSource,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for ProviderError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                ProviderError::IoError(e) => write!(f, "{}", e),
+                ProviderError::AccountError(e) => write!(f, "{}", e),
+                ProviderError::KeystoreError(e) => write!(f, "{}", e),
+                ProviderError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for ProviderError {
+        fn from(error: std::io::Error) -> Self {
+            ProviderError::IoError(error)
+        }
+    }
+
+    impl From<AccountError> for ProviderError {
+        fn from(error: AccountError) -> Self {
+            ProviderError::AccountError(error)
+        }
+    }
+
+    impl From<KeystoreError> for ProviderError {
+        fn from(error: KeystoreError) -> Self {
+            ProviderError::KeystoreError(error)
+        }
+    }
+
+    impl From<Error> for ProviderError {
+        fn from(error: Error) -> Self {
+            ProviderError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod providers {
+    use super::*;
+    use std::fs;
+
+    const PRIVKEY: &str = "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ";
+    const ADDRESS: &str = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S";
+
+    fn setup_home(name: &str, account: &str) -> PathBuf {
+        let mut home = std::env::temp_dir();
+        home.push(format!("repoint-provider-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&home).unwrap();
+        fs::write(home.join("account.toml"), account).unwrap();
+        let _ = fs::remove_file(home.join("session.toml"));
+        home
+    }
+
+    #[test]
+    fn in_memory_and_env_keys() {
+        std::env::set_var("REPOINT_TEST_PRIVKEY", PRIVKEY);
+        let env = EnvVar { var: "REPOINT_TEST_PRIVKEY".to_string() };
+        let unset = EnvVar { var: "REPOINT_TEST_PRIVKEY_UNSET".to_string() };
+
+        assert_eq!(InMemory::new(PRIVKEY).privkey().unwrap(), PRIVKEY);
+        assert_eq!(env.privkey().unwrap(), PRIVKEY);
+        match unset.privkey().unwrap_err() {
+            ProviderError::Error(e) => assert_eq!(e.kind, ErrorKind::MissingKey),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn account_file_key() {
+        let account = format!("['account']\nname = \"7db9a\"\npubaddr = \"{}\"\nxpriv = \"{}\"\n", ADDRESS, PRIVKEY);
+        let home = setup_home("account", &account);
+
+        assert_eq!(AccountFile::new(home.clone()).privkey().unwrap(), PRIVKEY);
+
+        fs::remove_dir_all(home).unwrap();
+    }
+
+    #[test]
+    fn key_source_from_str() {
+        assert_eq!("env".parse::<KeySource>().unwrap(), KeySource::Env);
+        assert_eq!("account".parse::<KeySource>().unwrap(), KeySource::Account);
+        assert!("ledger".parse::<KeySource>().is_err());
+    }
+}
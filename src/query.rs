@@ -0,0 +1,147 @@
+/*
+This module searches the index for repos, backing `repoint query`.
+*/
+use crate::index::{Account, Index, Repo, RepoRef};
+use crate::resolve;
+
+/// What to look for. With neither field set, every repo matches.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    /// Repo name: exact matches come first, then prefix matches.
+    pub name: Option<String>,
+    /// Account name or address that must own the repo.
+    pub author: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepoMatch<'a> {
+    pub account: &'a Account,
+    pub repo: &'a Repo,
+    pub exact: bool,
+    /// Where the repo redirects to in the end, as `account/repo`, or why that can't be followed.
+    pub redirect: Option<String>,
+    /// Who flagged it, among those `moderation` trusts. Empty until moderated.
+    pub flagged_by: Vec<String>,
+}
+
+impl<'a> RepoMatch<'a> {
+    /// `account/repo`, the unambiguous form `get --name` accepts.
+    pub fn full_name(&self) -> String {
+        format!(
+            "{}/{}",
+            self.account.name.as_deref().unwrap_or(&self.account.address),
+            self.repo.name
+        )
+    }
+
+    /// One line for humans: name, address and index, description, where it redirects and who flagged it.
+    pub fn summary(&self) -> String {
+        let mut line = format!(
+            "{}\t{}:{}\t{}",
+            self.full_name(),
+            self.account.address,
+            self.repo.index,
+            self.repo.description.as_deref().unwrap_or("")
+        );
+        if let Some(redirect) = &self.redirect {
+            line.push_str(&format!("\t-> {}", redirect));
+        }
+        if !self.flagged_by.is_empty() {
+            line.push_str(&format!("\tflagged by {}", self.flagged_by.join(", ")));
+        }
+
+        line
+    }
+}
+
+pub fn query<'a>(index: &'a Index, query: &Query) -> Vec<RepoMatch<'a>> {
+    let mut refs: Vec<(&RepoRef, bool)> = match &query.name {
+        Some(name) => {
+            let exact = index.repos_named(name);
+            let prefix = index
+                .repos_with_prefix(name)
+                .into_iter()
+                .filter(|r| !exact.contains(r))
+                .map(|r| (r, false));
+            exact.iter().map(|r| (*r, true)).chain(prefix).collect()
+        }
+        None => index.all_repos().into_iter().map(|r| (r, false)).collect(),
+    };
+
+    if let Some(author) = &query.author {
+        let owners = index.accounts_named(author);
+        refs.retain(|(r, _)| r.address == *author || owners.contains(&&r.address));
+    }
+
+    let mut matches: Vec<RepoMatch> = refs
+        .into_iter()
+        .filter_map(|(r, exact)| {
+            let account = index.account(&r.address)?;
+            let repo = index.repo(r)?;
+            let redirect = repo.redirect.as_ref().map(|_| match resolve::trace_repo(index, r) {
+                Ok(resolved) => resolved.target.name(),
+                Err(e) => format!("({})", e),
+            });
+            Some(RepoMatch { account, repo, exact, redirect, flagged_by: vec![] })
+        })
+        .collect();
+    matches.sort_by_key(|m| (!m.exact, m.repo.name.to_lowercase(), m.full_name()));
+
+    matches
+}
+
+#[cfg(test)]
+mod search {
+    use super::*;
+    use crate::index::testing::{setup_index, ALICE, BOB};
+    use crate::opcode::{Action, Opcode};
+
+    const ACCOUNTS: &[(&str, &str, &[&str])] =
+        &[(ALICE, "7db9a", &["repoint", "repoint-demo"]), (BOB, "bob", &["repoint-demo"])];
+
+    fn names(matches: Vec<RepoMatch>) -> Vec<String> {
+        matches.iter().map(|m| m.full_name()).collect()
+    }
+
+    #[test]
+    fn query_exact_before_prefix() {
+        let index = setup_index(ACCOUNTS, vec![]);
+        let q = Query { name: Some("repoint".to_string()), author: None };
+
+        assert_eq!(
+            names(query(&index, &q)),
+            vec!["7db9a/repoint", "7db9a/repoint-demo", "bob/repoint-demo"]
+        );
+    }
+
+    #[test]
+    fn query_by_author() {
+        let index = setup_index(ACCOUNTS, vec![]);
+        let by_name = Query { name: Some("repoint-demo".to_string()), author: Some("7db9a".to_string()) };
+        let by_addr = Query { name: None, author: Some(BOB.to_string()) };
+
+        assert_eq!(names(query(&index, &by_name)), vec!["7db9a/repoint-demo"]);
+        assert_eq!(names(query(&index, &by_addr)), vec!["bob/repoint-demo"]);
+    }
+
+    #[test]
+    fn query_no_match() {
+        let index = setup_index(ACCOUNTS, vec![]);
+        let q = Query { name: Some("nope".to_string()), author: None };
+
+        assert!(query(&index, &q).is_empty());
+    }
+
+    #[test]
+    fn query_shows_redirects() {
+        let mut index = setup_index(ACCOUNTS, vec![]);
+        let to_bob = Action::builder(Opcode::RedirectRepo).repo_index(1).address(BOB).target_index(0).build().unwrap();
+        index.apply(ALICE, &to_bob).unwrap();
+        let q = Query { name: Some("repoint-demo".to_string()), author: None };
+        let matches = query(&index, &q);
+
+        assert_eq!(matches[0].redirect.as_deref(), Some("bob/repoint-demo"));
+        assert!(matches[0].summary().ends_with("\t-> bob/repoint-demo"));
+        assert_eq!(matches[1].redirect, None);
+    }
+}
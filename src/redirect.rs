@@ -0,0 +1,193 @@
+/*
+This module plans redirects. Names can't change, so an account or one of its repos is pointed at a new one instead, and `get` follows. A redirect is refused here if it points at nothing repoint knows, changes nothing, or would send lookups round in a loop.
+*/
+use crate::index::{Account, Index, RepoRef};
+use crate::opcode::{Action, Opcode, OpcodeError};
+use crate::resolve::{self, ResolveError};
+
+use err::Error;
+pub use err::{ErrorKind, RedirectError};
+
+/// The action that redirects `from`'s account to the account at `to`.
+pub fn account(index: &Index, from: &str, to: &str) -> Result<Action, RedirectError> {
+    let current = named_account(index, from)?;
+    if index.account(to).and_then(|a| a.name.as_ref()).is_none() {
+        let err = Error::new(&format!("{} isn't a repoint account", to), ErrorKind::UnknownTarget);
+        return Err(RedirectError::from(err));
+    }
+    if current.redirect.as_deref() == Some(to) {
+        let err = Error::new(&format!("{} already redirects to {}", from, to), ErrorKind::NoChange);
+        return Err(RedirectError::from(err));
+    }
+
+    let action = Action::builder(Opcode::RedirectAccount).address(to).build()?;
+    let after = applied(index, from, &action)?;
+    resolve::trace_addr(&after, from)?;
+
+    Ok(action)
+}
+
+/// The action that redirects `from`'s repo called `name` to `to`.
+pub fn repo(index: &Index, from: &str, name: &str, to: &RepoRef) -> Result<Action, RedirectError> {
+    let current = named_account(index, from)?
+        .repos
+        .iter()
+        .find(|r| r.name.to_lowercase() == name.to_lowercase())
+        .ok_or_else(|| RedirectError::from(Error::new(&format!("{} has no repo {}", from, name), ErrorKind::UnknownRepo)))?;
+    if index.repo(to).is_none() {
+        let err = Error::new(&format!("no repo {}:{}", to.address, to.index), ErrorKind::UnknownTarget);
+        return Err(RedirectError::from(err));
+    }
+    if current.redirect.as_ref() == Some(to) {
+        let err = Error::new(&format!("{} already redirects to {}:{}", name, to.address, to.index), ErrorKind::NoChange);
+        return Err(RedirectError::from(err));
+    }
+
+    let action = Action::builder(Opcode::RedirectRepo)
+        .repo_index(current.index)
+        .address(&to.address)
+        .target_index(to.index)
+        .build()?;
+    let after = applied(index, from, &action)?;
+    resolve::trace_repo(&after, &RepoRef::new(from, current.index))?;
+
+    Ok(action)
+}
+
+fn named_account<'a>(index: &'a Index, from: &str) -> Result<&'a Account, RedirectError> {
+    match index.account(from) {
+        Some(account) if account.name.is_some() => Ok(account),
+        _ => {
+            let err = Error::new(&format!("{} hasn't created a repoint account", from), ErrorKind::NoAccount);
+            Err(RedirectError::from(err))
+        }
+    }
+}
+
+// The index as it would be once `action` is published, to check where lookups end up.
+fn applied(index: &Index, from: &str, action: &Action) -> Result<Index, RedirectError> {
+    let mut after = index.clone();
+    after
+        .apply(from, action)
+        .map_err(|reason| RedirectError::from(Error::new(&reason, ErrorKind::Rejected)))?;
+
+    Ok(after)
+}
+
+mod err {
+    use super::{OpcodeError, ResolveError};
+
+    #[derive(Debug)]
+    pub enum RedirectError {
+        OpcodeError(OpcodeError),
+        ResolveError(ResolveError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        NoAccount,
+        UnknownRepo,
+        UnknownTarget,
+        NoChange,
+        /// The indexer would reject it.
+        Rejected,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for RedirectError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                RedirectError::OpcodeError(e) => write!(f, "{}", e),
+                RedirectError::ResolveError(e) => write!(f, "{}", e),
+                RedirectError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<OpcodeError> for RedirectError {
+        fn from(error: OpcodeError) -> Self {
+            RedirectError::OpcodeError(error)
+        }
+    }
+
+    impl From<ResolveError> for RedirectError {
+        fn from(error: ResolveError) -> Self {
+            RedirectError::ResolveError(error)
+        }
+    }
+
+    impl From<Error> for RedirectError {
+        fn from(error: Error) -> Self {
+            RedirectError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod planning {
+    use super::*;
+    use crate::index::testing::{setup_index, ALICE, BOB};
+
+    const ACCOUNTS: &[(&str, &str, &[&str])] = &[(ALICE, "7db9a", &["repoint"]), (BOB, "bob", &["notes"])];
+
+    fn kind(res: Result<Action, RedirectError>) -> Option<ErrorKind> {
+        match res.unwrap_err() {
+            RedirectError::Error(e) => Some(e.kind),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn redirect_account_and_repo() {
+        let index = setup_index(ACCOUNTS, vec![]);
+        let to_bob = account(&index, ALICE, BOB).unwrap();
+        let to_notes = repo(&index, ALICE, "Repoint", &RepoRef::new(BOB, 0)).unwrap();
+
+        assert_eq!(to_bob.address.as_deref(), Some(BOB));
+        assert_eq!(to_notes.repo_index, Some(0));
+        assert_eq!(to_notes.target_index, Some(0));
+        assert_eq!(kind(account(&index, ALICE, "1NotAnAccount")), Some(ErrorKind::UnknownTarget));
+        assert_eq!(kind(repo(&index, ALICE, "notes", &RepoRef::new(BOB, 0))), Some(ErrorKind::UnknownRepo));
+    }
+
+    #[test]
+    fn refuse_redirect_loops() {
+        let bob_to_alice = Action::builder(Opcode::RedirectAccount).address(ALICE).build().unwrap();
+        let index = setup_index(ACCOUNTS, vec![(BOB, bob_to_alice)]);
+
+        match account(&index, ALICE, BOB).unwrap_err() {
+            RedirectError::ResolveError(ResolveError::Error(e)) => assert_eq!(e.kind, resolve::ErrorKind::RedirectCycle),
+            e => panic!("unexpected error {}", e),
+        }
+        assert!(repo(&index, ALICE, "repoint", &RepoRef::new(ALICE, 0)).is_err());
+    }
+
+    #[test]
+    fn refuse_unchanged_redirect() {
+        let alice_to_bob = Action::builder(Opcode::RedirectAccount).address(BOB).build().unwrap();
+        let index = setup_index(ACCOUNTS, vec![(ALICE, alice_to_bob)]);
+
+        assert_eq!(kind(account(&index, ALICE, BOB)), Some(ErrorKind::NoChange));
+    }
+}
@@ -0,0 +1,92 @@
+/*
+This module is a stand-in HTTP server for backend tests. It answers each request with the recorded response for its method and path, and keeps what it was sent so tests can check it. JSON-RPC requests all go to the same path, so a recorded path can be followed by a space and a fragment the body must contain, like `/ "method":"getblockcount"`.
+*/
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Method, path, status and body.
+pub type Recorded = (&'static str, String, u16, String);
+
+pub struct ReplayServer {
+    /// `http://127.0.0.1:<port>`
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl ReplayServer {
+    /// Serves `responses` on a free local port until the test ends. Requests with no
+    /// recorded response get a 404.
+    pub fn start(responses: Vec<Recorded>) -> ReplayServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind replay server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let seen = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = serve(stream, &responses, &seen);
+            }
+        });
+
+        ReplayServer { url, requests }
+    }
+
+    /// Every request so far as `METHOD path body`, or `METHOD path` if it had no body.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve(stream: TcpStream, responses: &[Recorded], seen: &Mutex<Vec<String>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        if header.trim().is_empty() {
+            break;
+        }
+        let lower = header.to_lowercase();
+        if let Some(value) = lower.strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body).to_string();
+
+    seen.lock().unwrap().push(if body.is_empty() {
+        format!("{} {}", method, path)
+    } else {
+        format!("{} {} {}", method, path, body)
+    });
+
+    let (status, reply) = responses
+        .iter()
+        .find(|(m, p, _, _)| *m == method && routes(p, &path, &body))
+        .map(|(_, _, status, reply)| (*status, reply.as_str()))
+        .unwrap_or((404, "not found"));
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} Replay\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reply.len(),
+        reply
+    )?;
+
+    stream.flush()
+}
+
+fn routes(recorded: &str, path: &str, body: &str) -> bool {
+    let mut parts = recorded.splitn(2, ' ');
+    parts.next() == Some(path) && parts.next().is_none_or(|fragment| body.contains(fragment))
+}
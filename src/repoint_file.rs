@@ -3,19 +3,13 @@ This module manages the specifics of the repoint file.
 */
 extern crate toml;
 extern crate toml_edit;
-pub use toml_edit::{value, Document};
-use easy_hasher::easy_hasher::sha256;
+pub use toml_edit::{value, Document, Item};
 
-use fixture;
-use err::Error;
-pub use err::{ErrorKind, RepointFileError};
+pub use err::{Error, ErrorKind, RepointFileError};
 
 use std::fs::File;
 pub use std::fs::read_to_string;
 use std::io::Write; // Not sure why, but file.write_all doesn't work without it. Not explicit to me.
-use std::path::PathBuf;
-use std::fs::{create_dir_all, metadata, OpenOptions};
-use std::io::prelude::*;
 
 /// Reveals the state of the repoint file.
 #[derive(Clone, Debug, PartialEq)]
@@ -26,7 +20,7 @@ pub enum RepointFileState {
 }
 
 /// Creates a repoint file with basic info.
-pub fn init<T: AsRef<str>>(path: T, version: T) -> Result<Document, RepointFileError> {
+pub fn init<T: AsRef<str>>(_path: T, version: T) -> Result<Document, RepointFileError> {
     let toml = format!(
         r#"['repository']
 version = "{}""#,
@@ -38,23 +32,6 @@ version = "{}""#,
     Ok(doc)
 }
 
-/// Creates a repoint file with basic info.
-pub fn init_account<T: AsRef<str>>(path: T, name: T, pubaddr: T, xpriv: T) -> Result<Document, RepointFileError> {
-    let toml = format!(
-        r#"['account']
-name = "{}"
-pubaddr = "{}"
-xpriv = "{}""#,
-        name.as_ref(),
-        pubaddr.as_ref(),
-        xpriv.as_ref(),
-    );
-
-    let doc = toml.parse::<Document>()?;
-
-    Ok(doc)
-}
-
 /// Open a repoint file.
 pub fn open<T: AsRef<str>>(path: T) -> Result<Document, RepointFileError> {
     let data = read_to_string(path.as_ref())?;
@@ -75,31 +52,28 @@ pub fn write<T: AsRef<str>>(toml_doc: Document, path: T) -> Result<(), RepointFi
 /// Valid if the version field can be read. Should rename pass
 /// toml value into method, that other fields can be validated.
 pub fn is_valid(doc: &Document) -> RepointFileState {
-    let mut valid: RepointFileState;
-    let version = entry_exists(&doc, "repository", Some("version"));
+    let version = entry_exists(doc, "repository", Some("version"));
 
     if version {
-        valid = RepointFileState::Valid;
+        RepointFileState::Valid
     } else {
-        valid = RepointFileState::Invalid;
+        RepointFileState::Invalid
     }
-
-    valid
 }
 
-///! Retrieve field data from a repoint file. For example, if the file name is provided, it will attempt to retrieve the field `repoint` nested in the `README.md` entry.
-///!  ```ignore
-///!  [README.md]
-///!  repoint = "The README."
-///!  ```
-///! If no file name is given, it will retrieve all the nested value in the key and not necessarily a specific field.
+/// Retrieve field data from a repoint file. For example, if the file name is provided, it will attempt to retrieve the field `repoint` nested in the `README.md` entry.
+///  ```ignore
+///  [README.md]
+///  repoint = "The README."
+///  ```
+/// If no file name is given, it will retrieve all the nested value in the key and not necessarily a specific field.
 pub fn repoint<T: AsRef<str>>(
     doc: &Document,
     file_name: Option<T>,
     key: T,
 ) -> Result<String, RepointFileError> {
-    if file_name.is_some() {
-        if let Some(data) = doc[file_name.unwrap().as_ref()][key.as_ref()].as_str() {
+    if let Some(file_name) = file_name {
+        if let Some(data) = doc[file_name.as_ref()][key.as_ref()].as_str() {
             Ok(data.to_string())
         } else {
             let err = Error::new(
@@ -144,11 +118,11 @@ pub fn insert_entry<T: AsRef<str>>(
     key: T,
     repoint: T,
 ) -> Result<Document, RepointFileError> {
-    let status = is_valid(&doc);
+    let status = is_valid(doc);
     if status == RepointFileState::Valid {
-        insert_entry_same_doc(&doc, file_name, key, repoint)
+        insert_entry_same_doc(doc, file_name, key, repoint)
     } else if status == RepointFileState::NonExistant && file_name.is_some() {
-        insert_entry_new_doc(&doc, file_name.unwrap(), key, repoint)
+        insert_entry_new_doc(doc, file_name.unwrap(), key, repoint)
     } else {
         // Invalid
         let err = Error::new("invalid repoint file", ErrorKind::InvalidFile);
@@ -162,19 +136,18 @@ fn insert_entry_new_doc<T: AsRef<str>>(
     key: T,
     repoint: T,
 ) -> Result<Document, RepointFileError> {
-    let mut toml_add: String;
     let toml = doc.to_string();
-    if key.as_ref() == "repository" {
-        toml_add = format!(
+    let toml_add = if key.as_ref() == "repository" {
+        format!(
             r#"
 ['{}']
 repoint = "{}""#,
             file_name.as_ref(),
             repoint.as_ref()
-        );
+        )
     } else {
-        toml_add = format!("['{}']", file_name.as_ref());
-    }
+        format!("['{}']", file_name.as_ref())
+    };
 
     let toml = toml + &toml_add;
 
@@ -230,14 +203,14 @@ pub fn add_entry<T: AsRef<str>>(
     name: T,
     repoint: T,
 ) -> Result<Document, RepointFileError> {
-    let file_state = is_valid(&doc);
+    let file_state = is_valid(doc);
     if file_state == RepointFileState::NonExistant {
         let err = Error::new("repoint file doesn't exist", ErrorKind::NoFile);
         Err(RepointFileError::from(err))
     } else if file_name.is_none() {
-        let entry_exists = entry_exists(&doc, "repository", Some(name.as_ref()));
+        let entry_exists = entry_exists(doc, "repository", Some(name.as_ref()));
         if !entry_exists {
-            insert_entry(&doc, None, name.as_ref(), repoint.as_ref())
+            insert_entry(doc, None, name.as_ref(), repoint.as_ref())
         } else {
             let err = Error::new(
                 "failed to add sub-entry to about field repoint file",
@@ -247,10 +220,10 @@ pub fn add_entry<T: AsRef<str>>(
         }
     } else {
         let file_name = file_name.unwrap();
-        let entry_exists = entry_exists(&doc, file_name.as_ref(), None);
+        let entry_exists = entry_exists(doc, file_name.as_ref(), None);
         if !entry_exists {
             insert_entry(
-                &doc,
+                doc,
                 Some(file_name.as_ref()),
                 name.as_ref(),
                 repoint.as_ref(),
@@ -271,16 +244,16 @@ pub fn update_entry<T: AsRef<str>>(
     key: T,
     repoint: T,
 ) -> Result<Document, RepointFileError> {
-    let file_state = is_valid(&doc);
+    let file_state = is_valid(doc);
     if file_state == RepointFileState::NonExistant {
         let err = Error::new("repoint file doesn't exist", ErrorKind::InvalidFile);
         Err(RepointFileError::from(err))
     } else if file_name.is_some() {
         let file_name = file_name.unwrap();
-        let entry_exists = entry_exists(&doc, file_name.as_ref(), None);
+        let entry_exists = entry_exists(doc, file_name.as_ref(), None);
         if entry_exists {
             insert_entry(
-                &doc,
+                doc,
                 Some(file_name.as_ref()),
                 key.as_ref(),
                 repoint.as_ref(),
@@ -293,9 +266,9 @@ pub fn update_entry<T: AsRef<str>>(
             Err(RepointFileError::from(err))
         }
     } else {
-        let entry_exists = entry_exists(&doc, "repository", Some(repoint.as_ref()));
+        let entry_exists = entry_exists(doc, "repository", Some(repoint.as_ref()));
         if entry_exists {
-            insert_entry(&doc, Some("repository"), key.as_ref(), repoint.as_ref())
+            insert_entry(doc, Some("repository"), key.as_ref(), repoint.as_ref())
         } else {
             let err = Error::new(
                 "file entry doesn't exist in repoint file",
@@ -335,74 +308,6 @@ pub fn delete_entry<T: AsRef<str>>(
     doc
 }
 
-pub enum FileType {
-    Account,
-    Repo,
-}
-
-pub fn hash_file(file: FileType) -> std::io::Result<bool> {
-    //file.write_all(stuff.as_bytes()).unwrap();
-    //
-
-    let repoint_path = match file {
-        FileType::Account => {
-            let mut account_path = dirs::home_dir().unwrap();
-            account_path.push(".repoint");
-            account_path.push("account.toml");
-            account_path
-        },
-        FileType::Repo => {
-            let mut repository_path = PathBuf::new();
-            repository_path.push("repoint.toml");
-            repository_path
-        }
-    };
-
-    let mut file = OpenOptions::new()
-       .read(true)
-       .write(true)
-       .append(true)
-       .open(repoint_path)?;//path.clone().into_os_string().into_string().unwrap())
-
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-
-    let repoint_hash = sha256(&contents);
-
-    let mut hash_path = PathBuf::from("/tmp");
-    hash_path.push("repoint");
-    hash_path.push("test");
-    hash_path.push("mock_send_filehashes");
-    let mut repoint_hash_path = hash_path.clone();
-    create_dir_all(&hash_path).expect("Failed to create directories.");
-
-    let meta_res = metadata(hash_path.clone());
-
-    let txs_already_exists = match meta_res {
-        Ok(m) => {
-            if m.is_dir() {
-                repoint_hash_path.push(repoint_hash.to_hex_string());
-                let meta_res = metadata(repoint_hash_path.clone());
-                match meta_res {
-                    Ok(m) => {
-                        println!("Mock test: tx already exists: {:#?}", repoint_hash_path);
-                        true
-                    },
-                    Err(_) => {
-                        std::fs::File::create(&repoint_hash_path).expect("failed to create hash file");
-                        false
-                    }
-                }
-            } else {
-                panic!("Unable to create repoint temp dir path.");
-            }
-        },
-        Err(_) => panic!("Something bad happened when trying to create a test file hash.")
-    };
-
-    Ok(txs_already_exists)
-}
-
 mod err {
     pub use toml_edit::TomlError;
     
@@ -460,6 +365,17 @@ mod err {
             RepointFileError::TomlError(error)
         }
     }
+
+    impl std::fmt::Display for RepointFileError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                RepointFileError::IoError(e) => write!(f, "{}", e),
+                RepointFileError::TomlError(e) => write!(f, "{}", e),
+                RepointFileError::BoxRepointFileError(e) => write!(f, "{}", e),
+                RepointFileError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
     
     impl std::error::Error for Error {
         fn description(&self) -> &str {
@@ -475,6 +391,7 @@ mod err {
 }
 
 #[cfg(test)]
+#[allow(clippy::option_map_unit_fn)]
 mod toml_edit_integration {
     use super::*;
 
@@ -617,6 +534,7 @@ name = "name"
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod integration {
     use super::*;
     use fixture::Fixture;
@@ -0,0 +1,337 @@
+/*
+This module resolves human names and addresses to accounts and repos, following redirects. It backs `repoint get`.
+*/
+use crate::index::{Account, Index, Repo, RepoRef};
+
+use std::collections::BTreeSet;
+
+use err::Error;
+pub use err::{ErrorKind, ResolveError};
+
+/// The most redirects followed before giving up, even without a loop.
+pub const MAX_HOPS: usize = 16;
+
+/// What a name or address resolved to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target<'a> {
+    Account(&'a Account),
+    Repo(&'a Account, &'a Repo),
+}
+
+impl<'a> Target<'a> {
+    pub fn account(&self) -> &'a Account {
+        match self {
+            Target::Account(account) => account,
+            Target::Repo(account, _) => account,
+        }
+    }
+
+    /// `account` or `account/repo`.
+    pub fn name(&self) -> String {
+        let account = self.account();
+        let account_name = account.name.as_deref().unwrap_or(&account.address);
+        match self {
+            Target::Account(_) => account_name.to_string(),
+            Target::Repo(_, repo) => format!("{}/{}", account_name, repo.name),
+        }
+    }
+
+    /// A repo's urls, or every url of an account's repos.
+    pub fn uris(&self) -> Vec<&'a String> {
+        match self {
+            Target::Account(account) => account.repos.iter().flat_map(|r| r.urls.iter()).collect(),
+            Target::Repo(_, repo) => repo.urls.iter().collect(),
+        }
+    }
+}
+
+/// A lookup's starting point and where its redirects ended. They're the same when nothing redirects.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resolved<'a> {
+    pub original: Target<'a>,
+    pub target: Target<'a>,
+    /// How many redirects were followed.
+    pub hops: usize,
+}
+
+impl<'a> Resolved<'a> {
+    pub fn redirected(&self) -> bool {
+        self.hops > 0
+    }
+}
+
+/// Resolves `name`, which is `account/repo`, a repo name or an account name,
+/// in that order of preference, then follows redirects to the final target.
+pub fn resolve_name<'a, T: AsRef<str>>(index: &'a Index, name: T) -> Result<Target<'a>, ResolveError> {
+    trace_name(index, name).map(|resolved| resolved.target)
+}
+
+/// Resolves an account by address, following account redirects.
+pub fn resolve_addr<'a, T: AsRef<str>>(index: &'a Index, addr: T) -> Result<Target<'a>, ResolveError> {
+    trace_addr(index, addr).map(|resolved| resolved.target)
+}
+
+/// Resolves a repo, following repo redirects.
+pub fn resolve_repo<'a>(index: &'a Index, repo: &RepoRef) -> Result<Target<'a>, ResolveError> {
+    trace_repo(index, repo).map(|resolved| resolved.target)
+}
+
+/// Like `resolve_name`, but keeps what `name` named before any redirects.
+pub fn trace_name<'a, T: AsRef<str>>(index: &'a Index, name: T) -> Result<Resolved<'a>, ResolveError> {
+    let name = name.as_ref();
+    if let Some(pos) = name.find('/') {
+        let (account_name, repo_name) = (&name[..pos], &name[pos + 1..]);
+        let refs: Vec<&RepoRef> = index
+            .repos_named(repo_name)
+            .into_iter()
+            .filter(|r| index.accounts_named(account_name).contains(&&r.address) || r.address == account_name)
+            .collect();
+        return one_repo(index, name, refs);
+    }
+
+    let refs = index.repos_named(name);
+    if !refs.is_empty() {
+        return one_repo(index, name, refs);
+    }
+
+    let addrs = index.accounts_named(name);
+    match addrs.len() {
+        0 => Err(unknown(name)),
+        1 => trace_addr(index, addrs[0]),
+        _ => Err(ambiguous(name, addrs.iter().map(|a| a.to_string()).collect())),
+    }
+}
+
+/// Like `resolve_addr`, but keeps the account at `addr`.
+pub fn trace_addr<'a, T: AsRef<str>>(index: &'a Index, addr: T) -> Result<Resolved<'a>, ResolveError> {
+    let original = index.account(addr.as_ref()).ok_or_else(|| unknown(addr.as_ref()))?;
+    let mut seen = BTreeSet::new();
+    let mut account = original;
+    while let Some(to) = &account.redirect {
+        if !seen.insert(account.address.clone()) {
+            return Err(cycle(&account.address));
+        }
+        if seen.len() > MAX_HOPS {
+            return Err(too_many_hops(&original.address));
+        }
+        account = index.account(to).ok_or_else(|| unknown(to))?;
+    }
+
+    Ok(Resolved { original: Target::Account(original), target: Target::Account(account), hops: seen.len() })
+}
+
+/// Like `resolve_repo`, but keeps `repo` itself.
+pub fn trace_repo<'a>(index: &'a Index, repo: &RepoRef) -> Result<Resolved<'a>, ResolveError> {
+    let found = |repo: &RepoRef| -> Result<Target<'a>, ResolveError> {
+        let label = format!("{}:{}", repo.address, repo.index);
+        let account = index.account(&repo.address).ok_or_else(|| unknown(&label))?;
+        let found = index.repo(repo).ok_or_else(|| unknown(&label))?;
+        Ok(Target::Repo(account, found))
+    };
+    let original = found(repo)?;
+    let mut seen = BTreeSet::new();
+    let mut current = repo.clone();
+    let mut target = original.clone();
+    while let Target::Repo(_, Repo { redirect: Some(to), .. }) = target {
+        if !seen.insert(current.clone()) {
+            return Err(cycle(&format!("{}:{}", current.address, current.index)));
+        }
+        if seen.len() > MAX_HOPS {
+            return Err(too_many_hops(&format!("{}:{}", repo.address, repo.index)));
+        }
+        current = to.clone();
+        target = found(&current)?;
+    }
+
+    Ok(Resolved { original, target, hops: seen.len() })
+}
+
+fn one_repo<'a>(index: &'a Index, name: &str, refs: Vec<&RepoRef>) -> Result<Resolved<'a>, ResolveError> {
+    match refs.len() {
+        0 => Err(unknown(name)),
+        1 => trace_repo(index, refs[0]),
+        _ => {
+            let candidates = refs
+                .iter()
+                .filter_map(|r| {
+                    let account = index.account(&r.address)?;
+                    let repo = index.repo(r)?;
+                    Some(Target::Repo(account, repo).name())
+                })
+                .collect();
+            Err(ambiguous(name, candidates))
+        }
+    }
+}
+
+fn unknown(name: &str) -> ResolveError {
+    let err = Error::new(&format!("{} is unknown", name), ErrorKind::Unknown);
+    ResolveError::from(err)
+}
+
+fn ambiguous(name: &str, candidates: Vec<String>) -> ResolveError {
+    let err = Error::new(
+        &format!("{} is ambiguous, use one of: {}", name, candidates.join(", ")),
+        ErrorKind::Ambiguous,
+    );
+    ResolveError::from(err)
+}
+
+fn too_many_hops(name: &str) -> ResolveError {
+    let err = Error::new(&format!("redirects from {} go on for more than {} hops", name, MAX_HOPS), ErrorKind::TooManyHops);
+    ResolveError::from(err)
+}
+
+fn cycle(name: &str) -> ResolveError {
+    let err = Error::new(&format!("redirects from {} loop", name), ErrorKind::RedirectCycle);
+    ResolveError::from(err)
+}
+
+mod err {
+    #[derive(Debug)]
+    pub enum ResolveError {
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        Unknown,
+        Ambiguous,
+        RedirectCycle,
+        TooManyHops,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for ResolveError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                ResolveError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<Error> for ResolveError {
+        fn from(error: Error) -> Self {
+            ResolveError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod lookup {
+    use super::*;
+    use crate::index::testing::{setup_index, ALICE, BOB};
+    use crate::opcode::{Action, Opcode};
+
+    const ACCOUNTS: &[(&str, &str, &[&str])] = &[(ALICE, "7db9a", &["repoint"]), (BOB, "bob", &["repoint", "notes"])];
+
+    fn kind(res: Result<Target, ResolveError>) -> ErrorKind {
+        match res.unwrap_err() {
+            ResolveError::Error(e) => e.kind,
+        }
+    }
+
+    #[test]
+    fn resolve_qualified_and_unique_names() {
+        let urls = vec![
+            (ALICE, Action::add_repo_url(0, "https://github.com/7db9a/repoint").unwrap()),
+            (ALICE, Action::add_repo_url(0, "https://gitlab.com/7db9a/repoint").unwrap()),
+        ];
+        let index = setup_index(ACCOUNTS, urls);
+        let repo = resolve_name(&index, "7db9a/repoint").unwrap();
+
+        assert_eq!(repo.uris(), vec!["https://github.com/7db9a/repoint", "https://gitlab.com/7db9a/repoint"]);
+        assert_eq!(resolve_name(&index, "notes").unwrap().name(), "bob/notes");
+        assert_eq!(resolve_name(&index, "bob").unwrap().account().address, BOB);
+        assert_eq!(resolve_addr(&index, ALICE).unwrap().name(), "7db9a");
+    }
+
+    #[test]
+    fn resolve_rejects_ambiguous_and_unknown() {
+        let index = setup_index(ACCOUNTS, vec![]);
+
+        assert_eq!(kind(resolve_name(&index, "repoint")), ErrorKind::Ambiguous);
+        assert_eq!(kind(resolve_name(&index, "nope")), ErrorKind::Unknown);
+        assert_eq!(kind(resolve_addr(&index, "1NotAnAccount")), ErrorKind::Unknown);
+    }
+
+    #[test]
+    fn resolve_follows_redirects() {
+        let to_bob = Action::builder(Opcode::RedirectRepo)
+            .repo_index(0)
+            .address(BOB)
+            .target_index(1)
+            .build()
+            .unwrap();
+        let account_to_bob = Action::builder(Opcode::RedirectAccount).address(BOB).build().unwrap();
+        let index = setup_index(ACCOUNTS, vec![(ALICE, to_bob), (ALICE, account_to_bob)]);
+
+        assert_eq!(resolve_name(&index, "7db9a/repoint").unwrap().name(), "bob/notes");
+        assert_eq!(resolve_addr(&index, ALICE).unwrap().name(), "bob");
+    }
+
+    #[test]
+    fn resolve_detects_redirect_cycles() {
+        let to_bob = Action::builder(Opcode::RedirectAccount).address(BOB).build().unwrap();
+        let to_alice = Action::builder(Opcode::RedirectAccount).address(ALICE).build().unwrap();
+        let index = setup_index(ACCOUNTS, vec![(ALICE, to_bob), (BOB, to_alice)]);
+
+        assert_eq!(kind(resolve_addr(&index, ALICE)), ErrorKind::RedirectCycle);
+    }
+
+    #[test]
+    fn trace_keeps_original() {
+        let to_bob = Action::builder(Opcode::RedirectRepo)
+            .repo_index(0)
+            .address(BOB)
+            .target_index(1)
+            .build()
+            .unwrap();
+        let index = setup_index(ACCOUNTS, vec![(ALICE, to_bob)]);
+        let traced = trace_name(&index, "7db9a/repoint").unwrap();
+
+        assert!(traced.redirected());
+        assert_eq!(traced.original.name(), "7db9a/repoint");
+        assert_eq!(traced.target.name(), "bob/notes");
+        assert!(!trace_addr(&index, ALICE).unwrap().redirected());
+    }
+
+    #[test]
+    fn resolve_gives_up_after_max_hops() {
+        let addresses: Vec<String> = (0..MAX_HOPS as u8 + 2)
+            .map(|i| crate::key::hash_address(&[i; 20], crate::key::Network::Mainnet))
+            .collect();
+        let mut extra = vec![];
+        for (i, address) in addresses.iter().enumerate() {
+            extra.push((address.as_str(), Action::instantiate()));
+            extra.push((address.as_str(), Action::create_account(format!("hop{}", i)).unwrap()));
+        }
+        for pair in addresses.windows(2) {
+            extra.push((pair[0].as_str(), Action::builder(Opcode::RedirectAccount).address(&pair[1]).build().unwrap()));
+        }
+        let index = setup_index(&[], extra);
+
+        assert_eq!(kind(resolve_addr(&index, &addresses[0])), ErrorKind::TooManyHops);
+        assert_eq!(trace_addr(&index, &addresses[1]).unwrap().hops, MAX_HOPS);
+    }
+}
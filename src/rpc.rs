@@ -0,0 +1,339 @@
+/*
+This module talks to a self-hosted node over its JSON-RPC interface. Reading transactions needs `-txindex`, and address history comes from the node's wallet, so the addresses must be imported with `importaddress`.
+
+```ignore
+[backend]
+kind = "rpc"
+url = "http://127.0.0.1:8332"
+cookie = "/home/me/.bitcoin/.cookie"
+```
+*/
+extern crate serde_json;
+extern crate ureq;
+
+use crate::backend::{self, BackendError, ChainBackend, ChainTx, Error, ErrorKind, TxRef, Utxo};
+
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+pub const DEFAULT_URL: &str = "http://127.0.0.1:8332";
+
+const TIMEOUT_MS: u64 = 30_000;
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+/// What a node answers a transaction that doesn't decode, or fails validation, with.
+const RPC_VALIDATION_ERRORS: [i64; 3] = [-22, -25, -26];
+
+/// How to log in to the node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RpcAuth {
+    None,
+    /// The `.cookie` file the node writes on start, read on every call since it changes on restart.
+    Cookie(PathBuf),
+    UserPass { user: String, password: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rpc {
+    pub url: String,
+    pub auth: RpcAuth,
+}
+
+impl Rpc {
+    pub fn new<T: AsRef<str>>(url: T, auth: RpcAuth) -> Rpc {
+        Rpc {
+            url: url.as_ref().to_string(),
+            auth,
+        }
+    }
+
+    /// Calls `method` and returns its result. RPC errors become `failed`, except
+    /// unknown txids and addresses which are `NotFound` and invalid transactions
+    /// which are `Rejected`.
+    fn call(&self, method: &str, params: Value, failed: ErrorKind) -> Result<Value, BackendError> {
+        let mut request = ureq::post(&self.url);
+        request.timeout_connect(TIMEOUT_MS).timeout_read(TIMEOUT_MS);
+        if let Some((user, password)) = self.credentials()? {
+            request.auth(&user, &password);
+        }
+        let response = request.send_json(json!({
+            "jsonrpc": "1.0",
+            "id": "repoint",
+            "method": method,
+            "params": params,
+        }));
+
+        if let Some(e) = response.synthetic_error() {
+            return Err(error(&format!("{}: {}", self.url, e), ErrorKind::Unreachable));
+        }
+        if response.status() == 401 || response.status() == 403 {
+            return Err(error(&format!("{}: {}", self.url, response.status_line()), ErrorKind::Unauthorized));
+        }
+
+        // Nodes answer errors with a 500 and the error in the body.
+        let status = response.status_line().to_string();
+        let body: Value = response
+            .into_json()
+            .map_err(|_| error(&format!("{}: {} {}", self.url, method, status), ErrorKind::InvalidResponse))?;
+        match &body["error"] {
+            Value::Null => Ok(body["result"].clone()),
+            e => {
+                let kind = match e["code"].as_i64() {
+                    Some(RPC_INVALID_ADDRESS_OR_KEY) => ErrorKind::NotFound,
+                    Some(code) if RPC_VALIDATION_ERRORS.contains(&code) => ErrorKind::Rejected,
+                    _ => failed,
+                };
+                let message = e["message"].as_str().unwrap_or("unknown error");
+                Err(error(&format!("{}: {}", method, message), kind))
+            }
+        }
+    }
+
+    fn credentials(&self) -> Result<Option<(String, String)>, BackendError> {
+        match &self.auth {
+            RpcAuth::None => Ok(None),
+            RpcAuth::UserPass { user, password } => Ok(Some((user.clone(), password.clone()))),
+            RpcAuth::Cookie(path) => {
+                let cookie = read_to_string(path)?;
+                let mut parts = cookie.trim().splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(user), Some(password)) => Ok(Some((user.to_string(), password.to_string()))),
+                    _ => Err(error(&format!("{:?} isn't a cookie file", path), ErrorKind::Unauthorized)),
+                }
+            }
+        }
+    }
+
+    fn block_count(&self) -> Result<u64, BackendError> {
+        self.call("getblockcount", json!([]), ErrorKind::InvalidResponse)?
+            .as_u64()
+            .ok_or_else(|| invalid("getblockcount didn't return a number"))
+    }
+}
+
+impl ChainBackend for Rpc {
+    fn broadcast(&self, hex: &str) -> Result<String, BackendError> {
+        self.call("sendrawtransaction", json!([hex]), ErrorKind::Unreachable)
+            .or_else(|e| backend::already_sent(hex, e).map(Value::String))?
+            .as_str()
+            .map(|txid| txid.to_string())
+            .ok_or_else(|| invalid("sendrawtransaction didn't return a txid"))
+    }
+
+    fn get_tx(&self, txid: &str) -> Result<ChainTx, BackendError> {
+        let tx = self.call("getrawtransaction", json!([txid, true]), ErrorKind::InvalidResponse)?;
+        let hex = tx["hex"].as_str().ok_or_else(|| invalid("getrawtransaction has no hex"))?;
+        let height = match tx["blockhash"].as_str() {
+            Some(hash) => self.call("getblockheader", json!([hash]), ErrorKind::InvalidResponse)?["height"].as_u64(),
+            None => None,
+        };
+
+        Ok(ChainTx {
+            txid: txid.to_string(),
+            hex: hex.to_string(),
+            height,
+        })
+    }
+
+    fn address_history(&self, address: &str) -> Result<Vec<TxRef>, BackendError> {
+        let tip = self.block_count()?;
+        let listed = self.call("listtransactions", json!(["*", 100_000, 0, true]), ErrorKind::InvalidResponse)?;
+
+        let mut seen = BTreeSet::new();
+        let mut refs = vec![];
+        for item in array(&listed)?.iter().filter(|i| i["address"].as_str() == Some(address)) {
+            let txid = string(item, "txid")?;
+            if !seen.insert(txid.clone()) {
+                continue;
+            }
+            let confirmations = item["confirmations"].as_i64().unwrap_or(0);
+            refs.push(TxRef {
+                txid,
+                height: if confirmations > 0 { Some(tip + 1 - confirmations as u64) } else { None },
+            });
+        }
+        refs.sort_by_key(|r| r.height.unwrap_or(u64::MAX));
+
+        Ok(refs)
+    }
+
+    /// From the wallet if it watches `address`, otherwise by scanning the UTXO set.
+    fn utxos(&self, address: &str) -> Result<Vec<Utxo>, BackendError> {
+        if let Ok(listed) = self.call("listunspent", json!([0, 9_999_999, [address]]), ErrorKind::InvalidResponse) {
+            let tip = self.block_count()?;
+            let mut utxos = vec![];
+            for item in array(&listed)?.iter() {
+                let confirmations = item["confirmations"].as_u64().unwrap_or(0);
+                utxos.push(Utxo {
+                    txid: string(item, "txid")?,
+                    vout: number(item, "vout")? as u32,
+                    value: sats(&item["amount"])?,
+                    height: if confirmations > 0 { Some(tip + 1 - confirmations) } else { None },
+                });
+            }
+            if !utxos.is_empty() {
+                return Ok(utxos);
+            }
+        }
+
+        let scan = self.call(
+            "scantxoutset",
+            json!(["start", [format!("addr({})", address)]]),
+            ErrorKind::InvalidResponse,
+        )?;
+        let mut utxos = vec![];
+        for item in array(&scan["unspents"])?.iter() {
+            utxos.push(Utxo {
+                txid: string(item, "txid")?,
+                vout: number(item, "vout")? as u32,
+                value: sats(&item["amount"])?,
+                height: item["height"].as_u64().filter(|h| *h > 0),
+            });
+        }
+
+        Ok(utxos)
+    }
+
+    /// The node's relay fee, which it gives in coins per kilobyte.
+    fn fee_rate(&self) -> Result<Option<f64>, BackendError> {
+        let info = self.call("getnetworkinfo", json!([]), ErrorKind::InvalidResponse)?;
+
+        Ok(sats(&info["relayfee"]).ok().map(|per_kb| per_kb as f64 / 1000.0))
+    }
+}
+
+/// Nodes give amounts in coins.
+fn sats(amount: &Value) -> Result<u64, BackendError> {
+    amount
+        .as_f64()
+        .map(|coins| (coins * 100_000_000.0).round() as u64)
+        .ok_or_else(|| invalid("amount missing"))
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, BackendError> {
+    value.as_array().ok_or_else(|| invalid("expected a json array"))
+}
+
+fn string(item: &Value, key: &str) -> Result<String, BackendError> {
+    item[key].as_str().map(|s| s.to_string()).ok_or_else(|| invalid(&format!("{} missing", key)))
+}
+
+fn number(item: &Value, key: &str) -> Result<u64, BackendError> {
+    item[key].as_u64().ok_or_else(|| invalid(&format!("{} missing", key)))
+}
+
+fn invalid(msg: &str) -> BackendError {
+    error(msg, ErrorKind::InvalidResponse)
+}
+
+fn error(msg: &str, kind: ErrorKind) -> BackendError {
+    BackendError::from(Error::new(msg, kind))
+}
+
+#[cfg(test)]
+mod node {
+    use super::*;
+    use crate::replay::{Recorded, ReplayServer};
+
+    const ADDRESS: &str = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S";
+    const TXID: &str = "06c496bbe950bec4725f1f9a077c88631e40632a94fd3242caa65feccf5ef367";
+    const FUNDING: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+    const BLOCK: &str = "00000000000000000297e3ba53e1f7f0f24ad1e2a8bcb0f5bb0a8e1c1d8da2e2";
+
+    fn reply(method: &str, result: Value) -> Recorded {
+        let body = json!({ "result": result, "error": null, "id": "repoint" });
+        ("POST", format!("/ \"method\":\"{}\"", method), 200, body.to_string())
+    }
+
+    fn reply_error(method: &str, code: i64, message: &str) -> Recorded {
+        let body = json!({ "result": null, "error": { "code": code, "message": message }, "id": "repoint" });
+        ("POST", format!("/ \"method\":\"{}\"", method), 500, body.to_string())
+    }
+
+    #[test]
+    fn rpc_reads() {
+        let server = ReplayServer::start(vec![
+            reply("getblockcount", json!(650_011)),
+            reply("getrawtransaction", json!({ "txid": TXID, "hex": "0100000000", "blockhash": BLOCK })),
+            reply("getblockheader", json!({ "hash": BLOCK, "height": 650_000 })),
+            reply(
+                "listtransactions",
+                json!([
+                    { "address": ADDRESS, "txid": FUNDING, "confirmations": 22, "category": "receive" },
+                    { "address": ADDRESS, "txid": TXID, "confirmations": 0, "category": "send" },
+                    { "address": "19HxigV4QyBv3tHpQVcUEQyq1pzZVdoAut", "txid": TXID, "confirmations": 0 },
+                ]),
+            ),
+            reply("listunspent", json!([])),
+            reply("scantxoutset", json!({ "unspents": [{ "txid": FUNDING, "vout": 1, "amount": 0.001, "height": 649_990 }] })),
+            reply("getnetworkinfo", json!({ "version": 101_000_300, "relayfee": 0.0000025 })),
+        ]);
+        let rpc = Rpc::new(&server.url, RpcAuth::None);
+
+        assert_eq!(rpc.get_tx(TXID).unwrap().height, Some(650_000));
+        assert_eq!(
+            rpc.address_history(ADDRESS).unwrap(),
+            vec![
+                TxRef { txid: FUNDING.to_string(), height: Some(649_990) },
+                TxRef { txid: TXID.to_string(), height: None },
+            ]
+        );
+        assert_eq!(
+            rpc.utxos(ADDRESS).unwrap(),
+            vec![Utxo { txid: FUNDING.to_string(), vout: 1, value: 100_000, height: Some(649_990) }]
+        );
+        assert_eq!(rpc.fee_rate().unwrap(), Some(0.25));
+    }
+
+    #[test]
+    fn rpc_broadcast_with_cookie() {
+        let mut cookie = std::env::temp_dir();
+        cookie.push(format!("repoint-rpc-cookie-{}", std::process::id()));
+        std::fs::write(&cookie, "__cookie__:secret\n").unwrap();
+        let server = ReplayServer::start(vec![reply("sendrawtransaction", json!(TXID))]);
+        let rpc = Rpc::new(&server.url, RpcAuth::Cookie(cookie.clone()));
+
+        assert_eq!(rpc.broadcast("0100000000").unwrap(), TXID);
+        assert!(server.requests()[0].contains("\"params\":[\"0100000000\"]"));
+
+        std::fs::remove_file(cookie).unwrap();
+    }
+
+    #[test]
+    fn rpc_errors() {
+        let server = ReplayServer::start(vec![
+            reply_error("sendrawtransaction", -26, "66: insufficient priority"),
+            reply_error("getrawtransaction", -5, "No such mempool or blockchain transaction"),
+        ]);
+        let rpc = Rpc::new(&server.url, RpcAuth::UserPass { user: "repoint".to_string(), password: "pw".to_string() });
+
+        match rpc.broadcast("0100000000").unwrap_err() {
+            BackendError::Error(e) => assert_eq!(e.kind, ErrorKind::Rejected),
+            e => panic!("unexpected error {}", e),
+        }
+        match rpc.get_tx(TXID).unwrap_err() {
+            BackendError::Error(e) => assert_eq!(e.kind, ErrorKind::NotFound),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn rpc_broadcast_outcomes() {
+        let hex = crate::sign(&crate::opcode::Action::instantiate());
+        let txid = crate::tx::Transaction::from_hex(&hex).unwrap().txid();
+        let rpc = |code: i64, message: &str| {
+            let server = ReplayServer::start(vec![reply_error("sendrawtransaction", code, message)]);
+            let result = Rpc::new(&server.url, RpcAuth::None).broadcast(&hex);
+            result.map_err(|e| match e {
+                BackendError::Error(e) => e.kind,
+                e => panic!("unexpected error {}", e),
+            })
+        };
+
+        assert_eq!(rpc(-27, "Transaction already in block chain"), Ok(txid.clone()));
+        assert_eq!(rpc(-26, "257: txn-already-known"), Ok(txid));
+        assert_eq!(rpc(-25, "Missing inputs"), Err(ErrorKind::Rejected));
+        assert_eq!(rpc(-28, "Loading block index..."), Err(ErrorKind::Unreachable));
+    }
+}
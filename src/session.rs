@@ -0,0 +1,265 @@
+/*
+This module caches an unlocked private key between commands, like an agent, so `push` doesn't ask for it every time. `login` starts a session and `logout` wipes it.
+*/
+extern crate toml;
+
+use crate::key::{self, KeyError};
+
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use err::Error;
+pub use err::{ErrorKind, SessionError};
+
+/// How long a session lasts unless `login --ttl` says otherwise.
+pub const DEFAULT_TTL_SECS: u64 = 60 * 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub address: String,
+    pub privkey: String,
+    /// Unix time in seconds after which the session is ignored.
+    pub expires: u64,
+}
+
+impl Session {
+    /// Starts a session for `privkey`, provided it's the key for `address`.
+    pub fn start<T: AsRef<str>>(privkey: T, address: T, ttl_secs: u64) -> Result<Session, SessionError> {
+        key::check_address(privkey.as_ref(), address.as_ref())?;
+
+        Ok(Session {
+            address: address.as_ref().to_string(),
+            privkey: privkey.as_ref().trim().to_string(),
+            expires: now() + ttl_secs,
+        })
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now() >= self.expires
+    }
+}
+
+/// Writes the session readable by the owner only, creating its directory if need be.
+pub fn save<P: AsRef<Path>>(session: &Session, path: P) -> Result<(), SessionError> {
+    if let Some(dir) = path.as_ref().parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let toml = format!(
+        "address = \"{}\"\nprivkey = \"{}\"\nexpires = {}\n",
+        session.address, session.privkey, session.expires
+    );
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path.as_ref())?;
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(toml.as_bytes())?;
+
+    Ok(())
+}
+
+/// The current session, or `None` if there isn't one or it has expired,
+/// in which case the expired one is wiped.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Session>, SessionError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let value: toml::Value = fs::read_to_string(path)?.parse()?;
+    let field = |key: &str| value.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+    let session = match (field("address"), field("privkey"), value.get("expires").and_then(|v| v.as_integer())) {
+        (Some(address), Some(privkey), Some(expires)) => Session {
+            address,
+            privkey,
+            expires: expires as u64,
+        },
+        _ => {
+            let err = Error::new("session file is missing fields", ErrorKind::InvalidSession);
+            return Err(SessionError::from(err));
+        }
+    };
+
+    if session.is_expired() {
+        clear(path)?;
+        return Ok(None);
+    }
+
+    Ok(Some(session))
+}
+
+/// Overwrites the session file before removing it. Not having a session is fine.
+pub fn clear<P: AsRef<Path>>(path: P) -> Result<(), SessionError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let len = fs::metadata(path)?.len() as usize;
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; len])?;
+    file.sync_all()?;
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+mod err {
+    use super::KeyError;
+    pub use toml::de::Error as TomlError;
+
+    #[derive(Debug)]
+    pub enum SessionError {
+        IoError(std::io::Error),
+        TomlError(TomlError),
+        KeyError(KeyError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        InvalidSession,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for SessionError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                SessionError::IoError(e) => write!(f, "{}", e),
+                SessionError::TomlError(e) => write!(f, "{}", e),
+                SessionError::KeyError(e) => write!(f, "{}", e),
+                SessionError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for SessionError {
+        fn from(error: std::io::Error) -> Self {
+            SessionError::IoError(error)
+        }
+    }
+
+    impl From<TomlError> for SessionError {
+        fn from(error: TomlError) -> Self {
+            SessionError::TomlError(error)
+        }
+    }
+
+    impl From<KeyError> for SessionError {
+        fn from(error: KeyError) -> Self {
+            SessionError::KeyError(error)
+        }
+    }
+
+    impl From<Error> for SessionError {
+        fn from(error: Error) -> Self {
+            SessionError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod lifecycle {
+    use super::*;
+    use std::path::PathBuf;
+
+    // The uncompressed WIF example from the bitcoin wiki.
+    const PRIVKEY: &str = "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ";
+    const ADDRESS: &str = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S";
+
+    fn setup_path(name: &str) -> PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!("repoint-session-{}-{}.toml", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn login_save_load_logout() {
+        let path = setup_path("cycle");
+        let session = Session::start(PRIVKEY, ADDRESS, DEFAULT_TTL_SECS).unwrap();
+        save(&session, &path).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(load(&path).unwrap(), Some(session));
+
+        clear(&path).unwrap();
+
+        assert!(!path.exists());
+        assert_eq!(load(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn expired_session_is_ignored() {
+        let path = setup_path("expired");
+        let mut session = Session::start(PRIVKEY, ADDRESS, 0).unwrap();
+        session.expires -= 1;
+        save(&session, &path).unwrap();
+
+        assert_eq!(load(&path).unwrap(), None);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn save_creates_missing_dir() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("repoint-session-home-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(".repoint").join("session.toml");
+
+        let session = Session::start(PRIVKEY, ADDRESS, DEFAULT_TTL_SECS).unwrap();
+        save(&session, &path).unwrap();
+
+        assert_eq!(load(&path).unwrap(), Some(session));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn save_tightens_permissions() {
+        let path = setup_path("perms");
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, Permissions::from_mode(0o644)).unwrap();
+        save(&Session::start(PRIVKEY, ADDRESS, DEFAULT_TTL_SECS).unwrap(), &path).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        clear(&path).unwrap();
+    }
+
+    #[test]
+    fn login_rejects_foreign_key() {
+        assert!(Session::start(PRIVKEY, "19HxigV4QyBv3tHpQVcUEQyq1pzZVdoAut", DEFAULT_TTL_SECS).is_err());
+    }
+}
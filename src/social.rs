@@ -0,0 +1,200 @@
+/*
+This module plans likes, follows, flags and tips, the opcodes that point at someone's repo by `$account-address $repo-index`. Ones that wouldn't change anything, like liking a repo twice, are refused here rather than published and rejected by every indexer.
+
+A tip's op-return goes in the same transaction as the payment to the repo owner's address, which is how indexers know what it was worth.
+*/
+use crate::index::{Index, RepoRef};
+use crate::opcode::{Action, Opcode, OpcodeError};
+
+use err::Error;
+pub use err::{ErrorKind, SocialError};
+
+/// The most satoshis `tip` sends when config.toml's `[tips] max` isn't set: 0.001 BSV.
+pub const DEFAULT_MAX_TIP: u64 = 100_000;
+
+/// The action `from` publishes to like, unlike, follow, unfollow, flag or unflag `repo`.
+pub fn edge(index: &Index, from: &str, opcode: Opcode, repo: &RepoRef) -> Result<Action, SocialError> {
+    let (adds, current, verb) = match opcode {
+        Opcode::LikeRepo => (true, index.likes(repo), "likes"),
+        Opcode::UnlikeRepo => (false, index.likes(repo), "likes"),
+        Opcode::FollowRepo => (true, index.followers(repo), "follows"),
+        Opcode::UnfollowRepo => (false, index.followers(repo), "follows"),
+        Opcode::FlagRepo => (true, index.flags(repo), "flags"),
+        Opcode::UnflagRepo => (false, index.flags(repo), "flags"),
+        _ => {
+            let err = Error::new(&format!("{} isn't a like, follow or flag", opcode.to_hex()), ErrorKind::NotSocial);
+            return Err(SocialError::from(err));
+        }
+    };
+
+    check_target(index, from, repo)?;
+    let already = current.iter().any(|address| *address == from);
+    if adds == already {
+        let msg = if adds {
+            format!("{} already {} {}:{}", from, verb, repo.address, repo.index)
+        } else {
+            format!("{} doesn't {} {}:{}", from, verb.trim_end_matches('s'), repo.address, repo.index)
+        };
+        return Err(SocialError::from(Error::new(&msg, ErrorKind::NoChange)));
+    }
+
+    Ok(Action::builder(opcode).address(&repo.address).repo_index(repo.index).build()?)
+}
+
+/// The action that goes with tipping `repo` `amount` satoshis, which must be at most `max`.
+pub fn tip(index: &Index, from: &str, repo: &RepoRef, amount: u64, max: u64) -> Result<Action, SocialError> {
+    check_target(index, from, repo)?;
+    if repo.address == from {
+        return Err(SocialError::from(Error::new("can't tip your own repo", ErrorKind::OwnRepo)));
+    }
+    if amount > max {
+        let msg = format!("{} sats is more than the {} sat limit, raise [tips] max in config.toml to send it", amount, max);
+        return Err(SocialError::from(Error::new(&msg, ErrorKind::OverMax)));
+    }
+
+    Ok(Action::builder(Opcode::TipRepo).address(&repo.address).repo_index(repo.index).build()?)
+}
+
+fn check_target(index: &Index, from: &str, repo: &RepoRef) -> Result<(), SocialError> {
+    if index.account(from).and_then(|a| a.name.as_ref()).is_none() {
+        let err = Error::new(&format!("{} hasn't created a repoint account", from), ErrorKind::NoAccount);
+        return Err(SocialError::from(err));
+    }
+    if index.repo(repo).is_none() {
+        let err = Error::new(&format!("no repo {}:{}", repo.address, repo.index), ErrorKind::UnknownRepo);
+        return Err(SocialError::from(err));
+    }
+
+    Ok(())
+}
+
+mod err {
+    use super::OpcodeError;
+
+    #[derive(Debug)]
+    pub enum SocialError {
+        OpcodeError(OpcodeError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        NotSocial,
+        NoAccount,
+        UnknownRepo,
+        /// Liking what you already like, unfollowing what you don't follow, etc.
+        NoChange,
+        OwnRepo,
+        /// A tip over `[tips] max`.
+        OverMax,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for SocialError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                SocialError::OpcodeError(e) => write!(f, "{}", e),
+                SocialError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<OpcodeError> for SocialError {
+        fn from(error: OpcodeError) -> Self {
+            SocialError::OpcodeError(error)
+        }
+    }
+
+    impl From<Error> for SocialError {
+        fn from(error: Error) -> Self {
+            SocialError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod edges {
+    use super::*;
+
+    use crate::index::testing::{setup_index, ALICE, BOB, CAROL};
+
+    const ACCOUNTS: &[(&str, &str, &[&str])] = &[(BOB, "acme", &["anvil"]), (ALICE, "7db9a", &[])];
+
+    #[test]
+    fn like_and_follow() {
+        let index = setup_index(ACCOUNTS, vec![]);
+        let anvil = RepoRef::new(BOB, 0);
+        let like = edge(&index, ALICE, Opcode::LikeRepo, &anvil).unwrap();
+
+        assert_eq!(like.address.as_deref(), Some(BOB));
+        assert_eq!(like.repo_index, Some(0));
+        assert_eq!(edge(&index, ALICE, Opcode::FollowRepo, &anvil).unwrap().opcode, Opcode::FollowRepo);
+    }
+
+    #[test]
+    fn refuse_no_change() {
+        let liked = Action::builder(Opcode::LikeRepo).address(BOB).repo_index(0).build().unwrap();
+        let index = setup_index(ACCOUNTS, vec![(ALICE, liked)]);
+        let anvil = RepoRef::new(BOB, 0);
+
+        assert_eq!(index.likes(&anvil), vec![ALICE]);
+        assert!(edge(&index, ALICE, Opcode::UnlikeRepo, &anvil).is_ok());
+        for opcode in [Opcode::LikeRepo, Opcode::UnfollowRepo, Opcode::UnflagRepo].iter() {
+            match edge(&index, ALICE, *opcode, &anvil).unwrap_err() {
+                SocialError::Error(e) => assert_eq!(e.kind, ErrorKind::NoChange),
+                e => panic!("unexpected error {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn refuse_unknown_repo_or_account() {
+        let index = setup_index(ACCOUNTS, vec![]);
+
+        match edge(&index, ALICE, Opcode::LikeRepo, &RepoRef::new(BOB, 3)).unwrap_err() {
+            SocialError::Error(e) => assert_eq!(e.kind, ErrorKind::UnknownRepo),
+            e => panic!("unexpected error {}", e),
+        }
+        match edge(&index, CAROL, Opcode::LikeRepo, &RepoRef::new(BOB, 0)).unwrap_err() {
+            SocialError::Error(e) => assert_eq!(e.kind, ErrorKind::NoAccount),
+            e => panic!("unexpected error {}", e),
+        }
+        assert!(edge(&index, ALICE, Opcode::CreateRepo, &RepoRef::new(BOB, 0)).is_err());
+    }
+
+    #[test]
+    fn tip_within_max() {
+        let index = setup_index(ACCOUNTS, vec![]);
+        let anvil = RepoRef::new(BOB, 0);
+
+        assert_eq!(tip(&index, ALICE, &anvil, 1000, DEFAULT_MAX_TIP).unwrap().opcode, Opcode::TipRepo);
+        match tip(&index, ALICE, &anvil, 1001, 1000).unwrap_err() {
+            SocialError::Error(e) => assert_eq!(e.kind, ErrorKind::OverMax),
+            e => panic!("unexpected error {}", e),
+        }
+        match tip(&index, BOB, &anvil, 1000, DEFAULT_MAX_TIP).unwrap_err() {
+            SocialError::Error(e) => assert_eq!(e.kind, ErrorKind::OwnRepo),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+}
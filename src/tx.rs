@@ -0,0 +1,521 @@
+/*
+This module builds and serializes the raw bitcoin transactions that carry repoint op-returns.
+*/
+extern crate hex;
+
+use err::Error;
+pub use err::{ErrorKind, TxError};
+
+/// Pushes an empty byte array, making the output provably unspendable alongside `OP_RETURN`.
+pub const OP_FALSE: u8 = 0x00;
+pub const OP_RETURN: u8 = 0x6a;
+pub const OP_PUSHDATA1: u8 = 0x4c;
+pub const OP_PUSHDATA2: u8 = 0x4d;
+pub const OP_PUSHDATA4: u8 = 0x4e;
+pub const OP_1NEGATE: u8 = 0x4f;
+pub const OP_1: u8 = 0x51;
+pub const OP_16: u8 = 0x60;
+pub const OP_DUP: u8 = 0x76;
+pub const OP_HASH160: u8 = 0xa9;
+pub const OP_EQUALVERIFY: u8 = 0x88;
+pub const OP_CHECKSIG: u8 = 0xac;
+
+pub const SIGHASH_ALL: u32 = 0x01;
+/// Bitcoinsv's replay protection: signatures commit to the spent value, BIP143 style.
+pub const SIGHASH_FORKID: u32 = 0x40;
+
+/// A bitcoin transaction, serialized in the legacy (non-segwit) format bitcoinsv uses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transaction {
+    pub version: u32,
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+    pub lock_time: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxIn {
+    /// Previous txid in internal byte order (reversed from how explorers display it).
+    pub prev_txid: [u8; 32],
+    pub prev_vout: u32,
+    pub script_sig: Vec<u8>,
+    pub sequence: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxOut {
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+impl Transaction {
+    pub fn new() -> Transaction {
+        Transaction {
+            version: 1,
+            inputs: vec![],
+            outputs: vec![],
+            lock_time: 0,
+        }
+    }
+
+    pub fn add_input(&mut self, input: TxIn) -> &mut Transaction {
+        self.inputs.push(input);
+        self
+    }
+
+    pub fn add_output(&mut self, output: TxOut) -> &mut Transaction {
+        self.outputs.push(output);
+        self
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        buf.extend_from_slice(&self.version.to_le_bytes());
+
+        write_varint(&mut buf, self.inputs.len() as u64);
+        for input in self.inputs.iter() {
+            buf.extend_from_slice(&input.prev_txid);
+            buf.extend_from_slice(&input.prev_vout.to_le_bytes());
+            write_varint(&mut buf, input.script_sig.len() as u64);
+            buf.extend_from_slice(&input.script_sig);
+            buf.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+
+        write_varint(&mut buf, self.outputs.len() as u64);
+        for output in self.outputs.iter() {
+            buf.extend_from_slice(&output.value.to_le_bytes());
+            write_varint(&mut buf, output.script_pubkey.len() as u64);
+            buf.extend_from_slice(&output.script_pubkey);
+        }
+
+        buf.extend_from_slice(&self.lock_time.to_le_bytes());
+
+        buf
+    }
+
+    /// Lowercase hex of the serialized transaction, ready to broadcast.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.serialize())
+    }
+
+    /// Double sha256 of the serialization, byte-reversed as explorers show it.
+    pub fn txid(&self) -> String {
+        let mut hash = crate::key::sha256d(&self.serialize());
+        hash.reverse();
+        hex::encode(hash)
+    }
+
+    /// The hash input `index` signs, BIP143 style as bitcoinsv requires with `SIGHASH_FORKID`.
+    /// `script_code` is the script of the output being spent and `value` its satoshis.
+    pub fn sighash(&self, index: usize, script_code: &[u8], value: u64, sighash_type: u32) -> Vec<u8> {
+        let mut prevouts = vec![];
+        let mut sequences = vec![];
+        for input in self.inputs.iter() {
+            prevouts.extend_from_slice(&input.prev_txid);
+            prevouts.extend_from_slice(&input.prev_vout.to_le_bytes());
+            sequences.extend_from_slice(&input.sequence.to_le_bytes());
+        }
+        let mut outputs = vec![];
+        for output in self.outputs.iter() {
+            outputs.extend_from_slice(&output.value.to_le_bytes());
+            write_varint(&mut outputs, output.script_pubkey.len() as u64);
+            outputs.extend_from_slice(&output.script_pubkey);
+        }
+
+        let input = &self.inputs[index];
+        let mut preimage = vec![];
+        preimage.extend_from_slice(&self.version.to_le_bytes());
+        preimage.extend_from_slice(&crate::key::sha256d(&prevouts));
+        preimage.extend_from_slice(&crate::key::sha256d(&sequences));
+        preimage.extend_from_slice(&input.prev_txid);
+        preimage.extend_from_slice(&input.prev_vout.to_le_bytes());
+        write_varint(&mut preimage, script_code.len() as u64);
+        preimage.extend_from_slice(script_code);
+        preimage.extend_from_slice(&value.to_le_bytes());
+        preimage.extend_from_slice(&input.sequence.to_le_bytes());
+        preimage.extend_from_slice(&crate::key::sha256d(&outputs));
+        preimage.extend_from_slice(&self.lock_time.to_le_bytes());
+        preimage.extend_from_slice(&sighash_type.to_le_bytes());
+
+        crate::key::sha256d(&preimage)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Transaction, TxError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let version = reader.read_u32()?;
+
+        let mut inputs = vec![];
+        for _ in 0..reader.read_varint()? {
+            let mut prev_txid = [0u8; 32];
+            prev_txid.copy_from_slice(reader.take(32)?);
+            let prev_vout = reader.read_u32()?;
+            let script_len = reader.read_varint()? as usize;
+            let script_sig = reader.take(script_len)?.to_vec();
+            let sequence = reader.read_u32()?;
+            inputs.push(TxIn { prev_txid, prev_vout, script_sig, sequence });
+        }
+
+        let mut outputs = vec![];
+        for _ in 0..reader.read_varint()? {
+            let value = reader.read_u64()?;
+            let script_len = reader.read_varint()? as usize;
+            let script_pubkey = reader.take(script_len)?.to_vec();
+            outputs.push(TxOut { value, script_pubkey });
+        }
+
+        let lock_time = reader.read_u32()?;
+        if reader.pos != bytes.len() {
+            let err = Error::new("trailing bytes after transaction", ErrorKind::InvalidTransaction);
+            return Err(TxError::from(err));
+        }
+
+        Ok(Transaction { version, inputs, outputs, lock_time })
+    }
+
+    pub fn from_hex<T: AsRef<str>>(tx_hex: T) -> Result<Transaction, TxError> {
+        let bytes = hex::decode(tx_hex.as_ref().trim())?;
+        Transaction::deserialize(&bytes)
+    }
+}
+
+/// Walks serialized transaction bytes.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], TxError> {
+        if self.bytes.len() - self.pos < n {
+            let err = Error::new("unexpected end of data", ErrorKind::Truncated);
+            return Err(TxError::from(err));
+        }
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+
+        Ok(slice)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, TxError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, TxError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, TxError> {
+        let b = self.take(8)?;
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(b);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, TxError> {
+        match self.take(1)?[0] {
+            0xfd => Ok(self.read_u16()? as u64),
+            0xfe => Ok(self.read_u32()? as u64),
+            0xff => self.read_u64(),
+            n => Ok(n as u64),
+        }
+    }
+}
+
+impl Default for Transaction {
+    fn default() -> Self {
+        Transaction::new()
+    }
+}
+
+impl TxOut {
+    /// A zero value `OP_FALSE OP_RETURN` output carrying `pushes` as data.
+    pub fn opreturn<T: AsRef<[u8]>>(pushes: &[T]) -> TxOut {
+        TxOut {
+            value: 0,
+            script_pubkey: opreturn_script(pushes),
+        }
+    }
+
+    /// Pays `value` satoshis to the owner of a 20 byte public key hash.
+    pub fn p2pkh(value: u64, pubkey_hash: &[u8]) -> TxOut {
+        TxOut {
+            value,
+            script_pubkey: p2pkh_script(pubkey_hash),
+        }
+    }
+}
+
+/// Bitcoin's CompactSize unsigned integer encoding.
+pub fn write_varint(buf: &mut Vec<u8>, n: u64) {
+    if n < 0xfd {
+        buf.push(n as u8);
+    } else if n <= 0xffff {
+        buf.push(0xfd);
+        buf.extend_from_slice(&(n as u16).to_le_bytes());
+    } else if n <= 0xffff_ffff {
+        buf.push(0xfe);
+        buf.extend_from_slice(&(n as u32).to_le_bytes());
+    } else {
+        buf.push(0xff);
+        buf.extend_from_slice(&n.to_le_bytes());
+    }
+}
+
+/// Appends `data` to `script` with the smallest push opcode that fits it.
+pub fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    let len = data.len();
+    if len < OP_PUSHDATA1 as usize {
+        script.push(len as u8);
+    } else if len <= 0xff {
+        script.push(OP_PUSHDATA1);
+        script.push(len as u8);
+    } else if len <= 0xffff {
+        script.push(OP_PUSHDATA2);
+        script.extend_from_slice(&(len as u16).to_le_bytes());
+    } else {
+        script.push(OP_PUSHDATA4);
+        script.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    script.extend_from_slice(data);
+}
+
+/// Splits a script into its data pushes, small numbers included as the byte they
+/// push. Fails on any other opcode.
+pub fn script_pushes(script: &[u8]) -> Result<Vec<Vec<u8>>, TxError> {
+    let mut reader = Reader { bytes: script, pos: 0 };
+    let mut pushes = vec![];
+    while reader.pos < script.len() {
+        let op = reader.take(1)?[0];
+        let len = match op {
+            OP_1NEGATE => {
+                pushes.push(vec![0x81]);
+                continue;
+            }
+            OP_1..=OP_16 => {
+                pushes.push(vec![op - OP_1 + 1]);
+                continue;
+            }
+            OP_FALSE => 0,
+            0x01..=0x4b => op as usize,
+            OP_PUSHDATA1 => reader.take(1)?[0] as usize,
+            OP_PUSHDATA2 => reader.read_u16()? as usize,
+            OP_PUSHDATA4 => reader.read_u32()? as usize,
+            _ => {
+                let err = Error::new("script holds a non-push opcode", ErrorKind::InvalidScript);
+                return Err(TxError::from(err));
+            }
+        };
+        pushes.push(reader.take(len)?.to_vec());
+    }
+
+    Ok(pushes)
+}
+
+/// The data pushed after `OP_RETURN`, or `None` if the script isn't an op-return.
+/// Accepts both `OP_FALSE OP_RETURN` and the older bare `OP_RETURN` form.
+pub fn opreturn_data(script: &[u8]) -> Result<Option<Vec<Vec<u8>>>, TxError> {
+    let data = match script {
+        [OP_FALSE, OP_RETURN, data @ ..] => data,
+        [OP_RETURN, data @ ..] => data,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(script_pushes(data)?))
+}
+
+/// `OP_FALSE OP_RETURN <push>...`
+pub fn opreturn_script<T: AsRef<[u8]>>(pushes: &[T]) -> Vec<u8> {
+    let mut script = vec![OP_FALSE, OP_RETURN];
+    for data in pushes.iter() {
+        push_data(&mut script, data.as_ref());
+    }
+
+    script
+}
+
+/// `OP_DUP OP_HASH160 <pubkey hash> OP_EQUALVERIFY OP_CHECKSIG`
+pub fn p2pkh_script(pubkey_hash: &[u8]) -> Vec<u8> {
+    let mut script = vec![OP_DUP, OP_HASH160];
+    push_data(&mut script, pubkey_hash);
+    script.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+
+    script
+}
+
+/// The public key hash a P2PKH script pays to, or `None` for any other script.
+pub fn p2pkh_hash(script: &[u8]) -> Option<&[u8]> {
+    match script {
+        [OP_DUP, OP_HASH160, 0x14, hash @ .., OP_EQUALVERIFY, OP_CHECKSIG] if hash.len() == 20 => Some(hash),
+        _ => None,
+    }
+}
+
+/// A txid as explorers show it, in the internal byte order inputs use.
+pub fn txid_bytes<T: AsRef<str>>(txid: T) -> Result<[u8; 32], TxError> {
+    let mut bytes = hex::decode(txid.as_ref())?;
+    if bytes.len() != 32 {
+        let err = Error::new(&format!("{} isn't a txid", txid.as_ref()), ErrorKind::InvalidTransaction);
+        return Err(TxError::from(err));
+    }
+    bytes.reverse();
+
+    let mut prev_txid = [0u8; 32];
+    prev_txid.copy_from_slice(&bytes);
+    Ok(prev_txid)
+}
+
+/// The reverse of `txid_bytes`.
+pub fn display_txid(prev_txid: &[u8; 32]) -> String {
+    let mut bytes = prev_txid.to_vec();
+    bytes.reverse();
+    hex::encode(bytes)
+}
+
+/// Parses an opcode written as hex, such as `0x7202`, into its bytes.
+pub fn opcode_bytes<T: AsRef<str>>(opcode: T) -> Result<Vec<u8>, TxError> {
+    let opcode = opcode.as_ref();
+    let digits = opcode.trim_start_matches("0x");
+    if digits.is_empty() || opcode == digits {
+        let err = Error::new("opcode must be written as 0x-prefixed hex", ErrorKind::InvalidOpcode);
+        return Err(TxError::from(err));
+    }
+
+    Ok(hex::decode(digits)?)
+}
+
+mod err {
+    pub use hex::FromHexError;
+
+    #[derive(Debug)]
+    pub enum TxError {
+        HexError(FromHexError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        InvalidOpcode,
+        InvalidScript,
+        InvalidTransaction,
+        Truncated,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for TxError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                TxError::HexError(e) => write!(f, "{}", e),
+                TxError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<FromHexError> for TxError {
+        fn from(error: FromHexError) -> Self {
+            TxError::HexError(error)
+        }
+    }
+
+    impl From<Error> for TxError {
+        fn from(error: Error) -> Self {
+            TxError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod serialize {
+    use super::*;
+
+    #[test]
+    fn varint_boundaries() {
+        let mut buf = vec![];
+        write_varint(&mut buf, 0xfc);
+        write_varint(&mut buf, 0xfd);
+        write_varint(&mut buf, 0x1_0000);
+
+        assert_eq!(hex::encode(buf), "fcfdfd00fe00000100");
+    }
+
+    #[test]
+    fn push_data_sizes() {
+        let mut script = vec![];
+        push_data(&mut script, &[0xab; 75]);
+        assert_eq!(script[0], 75);
+
+        let mut script = vec![];
+        push_data(&mut script, &[0xab; 76]);
+        assert_eq!(&script[..2], &[OP_PUSHDATA1, 76]);
+
+        let mut script = vec![];
+        push_data(&mut script, &[0xab; 256]);
+        assert_eq!(&script[..3], &[OP_PUSHDATA2, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn small_numbers_are_pushes() {
+        let pushes = script_pushes(&[OP_1NEGATE, OP_1, OP_16, 0x01, 0xab]).unwrap();
+        assert_eq!(pushes, vec![vec![0x81], vec![1], vec![16], vec![0xab]]);
+
+        assert!(script_pushes(&[OP_1, OP_CHECKSIG]).is_err());
+    }
+
+    #[test]
+    fn deserialize_round_trip() {
+        let tx_hex = "010000000001000000000000000026006a0272062068747470733a2f2f6769746875622e636f6d2f37646239612f7265706f696e7400000000";
+        let tx = Transaction::from_hex(tx_hex).unwrap();
+        let pushes = opreturn_data(&tx.outputs[0].script_pubkey).unwrap().unwrap();
+
+        assert_eq!(tx.to_hex(), tx_hex);
+        assert_eq!(pushes[0], vec![0x72, 0x06]);
+        assert_eq!(pushes[1], b"https://github.com/7db9a/repoint".to_vec());
+    }
+
+    #[test]
+    fn deserialize_rejects_truncated() {
+        let tx_hex = "01000000000100000000000000000b006a027203053764623961000000";
+
+        assert!(Transaction::from_hex(tx_hex).is_err());
+    }
+
+    // The native P2WPKH example of BIP143, whose digest bitcoinsv reuses for FORKID signatures.
+    #[test]
+    fn bip143_sighash() {
+        let tx = Transaction::from_hex("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+        let script_code = p2pkh_script(&hex::decode("1d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap());
+
+        assert_eq!(
+            hex::encode(tx.sighash(1, &script_code, 600_000_000, SIGHASH_ALL)),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+    }
+
+    #[test]
+    fn opcode_requires_hex_prefix() {
+        assert!(opcode_bytes("7203").is_err());
+        assert!(opcode_bytes("0x72zz").is_err());
+        assert_eq!(opcode_bytes("0x7219").unwrap(), vec![0x72, 0x19]);
+    }
+}
@@ -0,0 +1,408 @@
+/*
+This module pays for repoint transactions out of the account address's unspent outputs. Inputs are picked confirmed first and largest first until they cover the outputs and the fee, and what's left goes back to the address as change, unless it's too small to be worth an output. Signing comes after, once the fee has been agreed to.
+
+Transactions can be chained: each spends the change of the one before, so no node accepts or mines them out of order. Their txids are only known once signed, so `sign_chain` signs them in order and points each at its parent's final txid.
+*/
+use crate::backend::Utxo;
+use crate::key::{self, KeyError, PrivateKey};
+use crate::tx::{self, Transaction, TxError, TxIn, TxOut};
+
+pub use err::{ErrorKind, WalletError};
+use err::Error;
+
+/// Satoshis per byte, when neither config.toml nor the backend say otherwise.
+pub const DEFAULT_FEE_RATE: f64 = 0.5;
+/// Outputs worth less than this many satoshis aren't relayed.
+pub const DUST_LIMIT: u64 = 546;
+
+/// Satoshis in one BSV.
+pub const SATS_PER_BSV: u64 = 100_000_000;
+
+/// The longest DER signature and its sighash byte.
+const MAX_SIG_LEN: usize = 73;
+/// An uncompressed public key. Sizes are estimated with it, since the key isn't unlocked until after.
+const MAX_PUBKEY_LEN: usize = 65;
+/// Value, script length and a P2PKH script.
+const CHANGE_OUTPUT_LEN: usize = 34;
+
+/// A transaction with its inputs picked, waiting to be signed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Funded {
+    pub tx: Transaction,
+    /// What each input spends, in input order.
+    pub spent: Vec<Utxo>,
+    /// In satoshis.
+    pub fee: u64,
+    /// Index of the change output, if there is one.
+    pub change: Option<usize>,
+    /// The transaction whose change the first input spends, when chained.
+    pub parent: Option<String>,
+}
+
+impl Funded {
+    /// Signs every input with `key`, which must own the outputs they spend.
+    pub fn sign(&mut self, key: &PrivateKey) -> Result<(), WalletError> {
+        let pubkey = key.public_key();
+        let script_code = tx::p2pkh_script(&key::hash160(&pubkey));
+        let sighash_type = tx::SIGHASH_ALL | tx::SIGHASH_FORKID;
+
+        for (i, utxo) in self.spent.iter().enumerate() {
+            let hash = self.tx.sighash(i, &script_code, utxo.value, sighash_type);
+            let mut sig = key.sign(&hash)?;
+            sig.push(sighash_type as u8);
+
+            let mut script_sig = vec![];
+            tx::push_data(&mut script_sig, &sig);
+            tx::push_data(&mut script_sig, &pubkey);
+            self.tx.inputs[i].script_sig = script_sig;
+        }
+
+        Ok(())
+    }
+
+    /// The change output, for the next transaction in a chain to spend.
+    pub fn change_utxo(&self) -> Option<Utxo> {
+        self.change.map(|vout| Utxo {
+            txid: self.tx.txid(),
+            vout: vout as u32,
+            value: self.tx.outputs[vout].value,
+            height: None,
+        })
+    }
+
+    /// Points inputs spending `from` at `to` instead.
+    fn rename_parent(&mut self, from: &str, to: &str) -> Result<(), WalletError> {
+        for (input, utxo) in self.tx.inputs.iter_mut().zip(self.spent.iter_mut()) {
+            if utxo.txid == from {
+                utxo.txid = to.to_string();
+                input.prev_txid = tx::txid_bytes(to)?;
+            }
+        }
+        if self.parent.as_deref() == Some(from) {
+            self.parent = Some(to.to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds inputs from `utxos` to `tx` so it pays `fee_rate` satoshis per byte, and change to `change_hash`.
+/// A `parent` is spent before anything else, chaining `tx` to it. With `keep_change`, `tx` always
+/// gets change, for a later transaction to chain from.
+pub fn fund(
+    tx: Transaction,
+    parent: Option<&Utxo>,
+    utxos: &[Utxo],
+    change_hash: &[u8],
+    fee_rate: f64,
+    keep_change: bool,
+) -> Result<Funded, WalletError> {
+    for output in tx.outputs.iter() {
+        let opreturn = matches!(tx::opreturn_data(&output.script_pubkey), Ok(Some(_)));
+        if !opreturn && is_dust(output.value) {
+            let err = Error::new(
+                &format!("an output of {} sats is below the dust limit of {}", output.value, DUST_LIMIT),
+                ErrorKind::Dust,
+            );
+            return Err(WalletError::from(err));
+        }
+    }
+
+    let paying: u64 = tx.outputs.iter().map(|o| o.value).sum();
+    let mut candidates: Vec<Utxo> = utxos
+        .iter()
+        .filter(|u| parent.filter(|p| p.txid == u.txid && p.vout == u.vout).is_none())
+        .cloned()
+        .collect();
+    candidates.sort_by(|a, b| a.height.is_none().cmp(&b.height.is_none()).then(b.value.cmp(&a.value)));
+    if let Some(parent) = parent {
+        candidates.insert(0, parent.clone());
+    }
+
+    let mut funded = Funded {
+        tx,
+        spent: vec![],
+        fee: 0,
+        change: None,
+        parent: parent.map(|p| p.txid.clone()),
+    };
+    let mut total = 0;
+    for utxo in candidates.into_iter() {
+        funded.tx.add_input(TxIn {
+            prev_txid: tx::txid_bytes(&utxo.txid)?,
+            prev_vout: utxo.vout,
+            script_sig: vec![],
+            sequence: u32::MAX,
+        });
+        total += utxo.value;
+        funded.spent.push(utxo);
+
+        let size = signed_size(&funded.tx);
+        let with_change = fee(size + CHANGE_OUTPUT_LEN, fee_rate);
+        if total >= paying + with_change + DUST_LIMIT {
+            funded.change = Some(funded.tx.outputs.len());
+            funded.tx.add_output(TxOut::p2pkh(total - paying - with_change, change_hash));
+            funded.fee = with_change;
+            return Ok(funded);
+        }
+        if !keep_change && total >= paying + fee(size, fee_rate) {
+            // Too little is left over for change, so the miner gets it.
+            funded.fee = total - paying;
+            return Ok(funded);
+        }
+    }
+
+    let err = Error::new(
+        &format!("needs {} sats and a fee, but only {} are spendable", paying, total),
+        ErrorKind::InsufficientFunds,
+    );
+    Err(WalletError::from(err))
+}
+
+/// Signs `chain` in order. Whatever spends an earlier transaction's change is
+/// pointed at its signed txid before being signed itself.
+pub fn sign_chain(chain: &mut [Funded], key: &PrivateKey) -> Result<(), WalletError> {
+    let unsigned: Vec<String> = chain.iter().map(|f| f.tx.txid()).collect();
+    for i in 0..chain.len() {
+        let (done, rest) = chain.split_at_mut(i + 1);
+        done[i].sign(key)?;
+        let signed = done[i].tx.txid();
+        for later in rest.iter_mut() {
+            later.rename_parent(&unsigned[i], &signed)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Satoshis for `size` bytes at `fee_rate` per byte, rounded up.
+pub fn fee(size: usize, fee_rate: f64) -> u64 {
+    (size as f64 * fee_rate).ceil() as u64
+}
+
+pub fn is_dust(value: u64) -> bool {
+    value < DUST_LIMIT
+}
+
+/// Parses an amount into satoshis: `1000`, `1000 sats` or `0.001 bsv`. BSV amounts have at most 8 decimals.
+pub fn parse_amount<T: AsRef<str>>(amount: T) -> Result<u64, WalletError> {
+    let amount = amount.as_ref().trim().to_lowercase();
+    let bad = || {
+        let err = Error::new(&format!("{} isn't an amount like 1000 sats or 0.001 bsv", amount), ErrorKind::BadAmount);
+        WalletError::from(err)
+    };
+
+    let (number, bsv) = match amount.strip_suffix("bsv") {
+        Some(number) => (number.trim(), true),
+        None => (amount.trim_end_matches("sats").trim_end_matches("sat").trim(), false),
+    };
+    let (whole, frac) = match number.find('.') {
+        Some(pos) => (&number[..pos], &number[pos + 1..]),
+        None => (number, ""),
+    };
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && frac.is_empty()) || !digits(whole) || !digits(frac) || frac.len() > if bsv { 8 } else { 0 } {
+        return Err(bad());
+    }
+
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| bad())? };
+    let sats = if bsv {
+        let frac: u64 = format!("{:0<8}", frac).parse().map_err(|_| bad())?;
+        whole.checked_mul(SATS_PER_BSV).and_then(|sats| sats.checked_add(frac))
+    } else {
+        Some(whole)
+    };
+    match sats {
+        Some(sats) if sats > 0 => Ok(sats),
+        _ => Err(bad()),
+    }
+}
+
+/// The size `tx` will have once every input is signed, at most.
+fn signed_size(tx: &Transaction) -> usize {
+    let mut signed = tx.clone();
+    for input in signed.inputs.iter_mut() {
+        input.script_sig = vec![0; 2 + MAX_SIG_LEN + MAX_PUBKEY_LEN];
+    }
+
+    signed.serialize().len()
+}
+
+mod err {
+    use super::{KeyError, TxError};
+
+    #[derive(Debug)]
+    pub enum WalletError {
+        KeyError(KeyError),
+        TxError(TxError),
+        Error(Error),
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum ErrorKind {
+        InsufficientFunds,
+        Dust,
+        BadAmount,
+    }
+
+    #[derive(Debug)]
+    pub struct Error {
+        pub details: String,
+        pub kind: ErrorKind,
+    }
+
+    impl Error {
+        pub fn new(msg: &str, kind: ErrorKind) -> Error {
+            Error {
+                details: msg.to_string(),
+                kind,
+            }
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.details)
+        }
+    }
+
+    impl std::fmt::Display for WalletError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                WalletError::KeyError(e) => write!(f, "{}", e),
+                WalletError::TxError(e) => write!(f, "{}", e),
+                WalletError::Error(e) => write!(f, "{}", e),
+            }
+        }
+    }
+
+    impl From<KeyError> for WalletError {
+        fn from(error: KeyError) -> Self {
+            WalletError::KeyError(error)
+        }
+    }
+
+    impl From<TxError> for WalletError {
+        fn from(error: TxError) -> Self {
+            WalletError::TxError(error)
+        }
+    }
+
+    impl From<Error> for WalletError {
+        fn from(error: Error) -> Self {
+            WalletError::Error(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod funding {
+    use super::*;
+    use secp256k1::{Message, PublicKey, Secp256k1, Signature};
+
+    const PRIVKEY: &str = "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ";
+    const FUNDING: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+    fn utxo(vout: u32, value: u64, height: Option<u64>) -> Utxo {
+        Utxo { txid: FUNDING.to_string(), vout, value, height }
+    }
+
+    fn opreturn() -> Transaction {
+        let mut tx = Transaction::new();
+        tx.add_output(TxOut::opreturn(&[vec![0x72, 0x03], b"7db9a".to_vec()]));
+        tx
+    }
+
+    #[test]
+    fn fund_with_change() {
+        let hash = [0xab; 20];
+        let utxos = vec![utxo(0, 600, None), utxo(1, 10_000, Some(5)), utxo(2, 50_000, None)];
+        let funded = fund(opreturn(), None, &utxos, &hash, 0.5, false).unwrap();
+
+        // The confirmed coin comes first, even though it's smaller.
+        assert_eq!(funded.spent, vec![utxo(1, 10_000, Some(5))]);
+        assert_eq!(funded.fee, fee(signed_size(&funded.tx), 0.5));
+        assert_eq!(funded.change, Some(1));
+        assert_eq!(funded.tx.outputs[1], TxOut::p2pkh(10_000 - funded.fee, &hash));
+    }
+
+    #[test]
+    fn fund_without_dust_change() {
+        let funded = fund(opreturn(), None, &[utxo(0, 700, Some(5))], &[0xab; 20], 1.0, false).unwrap();
+        assert_eq!(funded.change, None);
+        assert_eq!(funded.fee, 700);
+
+        match fund(opreturn(), None, &[utxo(0, 100, Some(5))], &[0xab; 20], 1.0, false).unwrap_err() {
+            WalletError::Error(e) => assert_eq!(e.kind, ErrorKind::InsufficientFunds),
+            e => panic!("unexpected error {}", e),
+        }
+        let mut tip = opreturn();
+        tip.add_output(TxOut::p2pkh(100, &[0xcd; 20]));
+        match fund(tip, None, &[utxo(0, 10_000, Some(5))], &[0xab; 20], 1.0, false).unwrap_err() {
+            WalletError::Error(e) => assert_eq!(e.kind, ErrorKind::Dust),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn sign_inputs_with_forkid() {
+        let key = PrivateKey::from_wif(PRIVKEY).unwrap();
+        let hash = key::hash160(&key.public_key());
+        let mut tip = opreturn();
+        tip.add_output(TxOut::p2pkh(1_000, &[0xcd; 20]));
+        let mut funded = fund(tip, None, &[utxo(0, 800, None), utxo(1, 800, None)], &hash, 0.5, false).unwrap();
+        funded.sign(&key).unwrap();
+
+        let signed = Transaction::from_hex(funded.tx.to_hex()).unwrap();
+        assert_eq!(signed.inputs.len(), 2);
+        assert!(signed.serialize().len() <= signed_size(&signed));
+
+        let secp = Secp256k1::verification_only();
+        let pubkey = PublicKey::from_slice(&key.public_key()).unwrap();
+        for (i, input) in signed.inputs.iter().enumerate() {
+            let pushes = tx::script_pushes(&input.script_sig).unwrap();
+            let (sighash_type, der) = pushes[0].split_last().unwrap();
+            assert_eq!(*sighash_type, 0x41);
+            assert_eq!(pushes[1], key.public_key());
+
+            let digest = signed.sighash(i, &tx::p2pkh_script(&hash), 800, 0x41);
+            let message = Message::from_slice(&digest).unwrap();
+            assert!(secp.verify(&message, &Signature::from_der(der).unwrap(), &pubkey).is_ok());
+        }
+    }
+
+    #[test]
+    fn sign_chain_links_parents() {
+        let key = PrivateKey::from_wif(PRIVKEY).unwrap();
+        let hash = key::hash160(&key.public_key());
+        let utxos = [utxo(0, 10_000, Some(5)), utxo(1, 10_000, Some(5))];
+        let first = fund(opreturn(), None, &utxos[..1], &hash, 0.5, true).unwrap();
+        let parent = first.change_utxo().unwrap();
+        let second = fund(opreturn(), Some(&parent), &utxos[1..], &hash, 0.5, false).unwrap();
+        assert_eq!(second.spent, vec![parent]);
+
+        let mut chain = vec![first, second];
+        sign_chain(&mut chain, &key).unwrap();
+        let signed = chain[0].tx.txid();
+
+        assert_eq!(chain[1].parent.as_deref(), Some(signed.as_str()));
+        assert_eq!(chain[1].spent[0].txid, signed);
+        assert_eq!(chain[1].tx.inputs[0].prev_txid, tx::txid_bytes(&signed).unwrap());
+    }
+
+    #[test]
+    fn parse_amounts() {
+        assert_eq!(parse_amount("1000").unwrap(), 1000);
+        assert_eq!(parse_amount("1000 sats").unwrap(), 1000);
+        assert_eq!(parse_amount("1sat").unwrap(), 1);
+        assert_eq!(parse_amount("0.001 BSV").unwrap(), 100_000);
+        assert_eq!(parse_amount("1.5bsv").unwrap(), 150_000_000);
+        assert_eq!(parse_amount(".00000001 bsv").unwrap(), 1);
+        for bad in ["", "0", "1.5 sats", "0.000000001 bsv", "-5", "ten", "1e3", "bsv"].iter() {
+            match parse_amount(bad).unwrap_err() {
+                WalletError::Error(e) => assert_eq!(e.kind, ErrorKind::BadAmount),
+                e => panic!("unexpected error {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+/*
+This module talks to the WhatsOnChain REST API (https://developers.whatsonchain.com), or anything that serves the same endpoints under another base URL.
+*/
+extern crate serde_json;
+extern crate ureq;
+
+use crate::backend::{self, BackendError, ChainBackend, ChainTx, Error, ErrorKind, TxRef, Utxo};
+
+use serde_json::{json, Value};
+
+pub const MAINNET_URL: &str = "https://api.whatsonchain.com/v1/bsv/main";
+pub const TESTNET_URL: &str = "https://api.whatsonchain.com/v1/bsv/test";
+
+const TIMEOUT_MS: u64 = 30_000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WhatsOnChain {
+    /// e.g. `MAINNET_URL`, without a trailing slash.
+    pub url: String,
+}
+
+impl WhatsOnChain {
+    pub fn new<T: AsRef<str>>(url: T) -> WhatsOnChain {
+        WhatsOnChain {
+            url: url.as_ref().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Sends the request and returns the response if it's a 2xx. Other 4xx statuses
+    /// become `failed`, except 404 which is always `NotFound`; 5xx are `Unreachable`.
+    fn call(&self, method: &str, path: &str, body: Option<Value>, failed: ErrorKind) -> Result<ureq::Response, BackendError> {
+        let url = format!("{}{}", self.url, path);
+        let mut request = ureq::request(method, &url);
+        request.timeout_connect(TIMEOUT_MS).timeout_read(TIMEOUT_MS);
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+
+        if let Some(e) = response.synthetic_error() {
+            return Err(error(&format!("{}: {}", url, e), ErrorKind::Unreachable));
+        }
+        if response.ok() {
+            return Ok(response);
+        }
+
+        let kind = match response.status() {
+            404 => ErrorKind::NotFound,
+            500..=599 => ErrorKind::Unreachable,
+            _ => failed,
+        };
+        let status = response.status_line().to_string();
+        let body = response.into_string().unwrap_or_default();
+        Err(error(&format!("{}: {} {}", url, status, body.trim()), kind))
+    }
+
+    fn get_json(&self, path: &str) -> Result<Value, BackendError> {
+        Ok(self.call("GET", path, None, ErrorKind::InvalidResponse)?.into_json()?)
+    }
+}
+
+impl ChainBackend for WhatsOnChain {
+    fn broadcast(&self, hex: &str) -> Result<String, BackendError> {
+        let response = match self.call("POST", "/tx/raw", Some(json!({ "txhex": hex })), ErrorKind::Rejected) {
+            Ok(response) => response,
+            Err(e) => return backend::already_sent(hex, e),
+        };
+        let body = response.into_string()?;
+
+        // The txid comes back as a json string.
+        match serde_json::from_str::<Value>(&body) {
+            Ok(Value::String(txid)) => Ok(txid),
+            _ => Err(error(&format!("unexpected broadcast response {}", body.trim()), ErrorKind::InvalidResponse)),
+        }
+    }
+
+    fn get_tx(&self, txid: &str) -> Result<ChainTx, BackendError> {
+        let info = self.get_json(&format!("/tx/hash/{}", txid))?;
+        let hex = self
+            .call("GET", &format!("/tx/{}/hex", txid), None, ErrorKind::InvalidResponse)?
+            .into_string()?;
+
+        Ok(ChainTx {
+            txid: txid.to_string(),
+            hex: hex.trim().to_string(),
+            height: height(&info["blockheight"]),
+        })
+    }
+
+    fn address_history(&self, address: &str) -> Result<Vec<TxRef>, BackendError> {
+        let history = self.get_json(&format!("/address/{}/history", address))?;
+        let mut refs = vec![];
+        for item in array(&history)?.iter() {
+            refs.push(TxRef {
+                txid: string(item, "tx_hash")?,
+                height: height(&item["height"]),
+            });
+        }
+        // Confirmed by height, then the mempool.
+        refs.sort_by_key(|r| r.height.unwrap_or(u64::MAX));
+
+        Ok(refs)
+    }
+
+    fn utxos(&self, address: &str) -> Result<Vec<Utxo>, BackendError> {
+        let unspent = self.get_json(&format!("/address/{}/unspent", address))?;
+        let mut utxos = vec![];
+        for item in array(&unspent)?.iter() {
+            utxos.push(Utxo {
+                txid: string(item, "tx_hash")?,
+                vout: number(item, "tx_pos")? as u32,
+                value: number(item, "value")?,
+                height: height(&item["height"]),
+            });
+        }
+
+        Ok(utxos)
+    }
+}
+
+/// Unconfirmed transactions have no height, or zero or -1 depending on the endpoint.
+fn height(value: &Value) -> Option<u64> {
+    value.as_u64().filter(|h| *h > 0)
+}
+
+fn array(value: &Value) -> Result<&Vec<Value>, BackendError> {
+    value.as_array().ok_or_else(|| invalid("expected a json array"))
+}
+
+fn string(item: &Value, key: &str) -> Result<String, BackendError> {
+    item[key].as_str().map(|s| s.to_string()).ok_or_else(|| invalid(&format!("{} missing", key)))
+}
+
+fn number(item: &Value, key: &str) -> Result<u64, BackendError> {
+    item[key].as_u64().ok_or_else(|| invalid(&format!("{} missing", key)))
+}
+
+fn invalid(msg: &str) -> BackendError {
+    error(msg, ErrorKind::InvalidResponse)
+}
+
+fn error(msg: &str, kind: ErrorKind) -> BackendError {
+    BackendError::from(Error::new(msg, kind))
+}
+
+#[cfg(test)]
+mod api {
+    use super::*;
+    use crate::replay::ReplayServer;
+
+    const ADDRESS: &str = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S";
+    const TXID: &str = "06c496bbe950bec4725f1f9a077c88631e40632a94fd3242caa65feccf5ef367";
+    const FUNDING: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+    // Recorded from api.whatsonchain.com, trimmed to the fields repoint reads.
+    const TX_HASH: &str = r#"{"txid":"06c496bbe950bec4725f1f9a077c88631e40632a94fd3242caa65feccf5ef367","blockhash":"00000000000000000297e3ba53e1f7f0f24ad1e2a8bcb0f5bb0a8e1c1d8da2e2","blockheight":650000,"confirmations":12}"#;
+    const HISTORY: &str = r#"[{"tx_hash":"06c496bbe950bec4725f1f9a077c88631e40632a94fd3242caa65feccf5ef367","height":0},{"tx_hash":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b","height":649990}]"#;
+    const UNSPENT: &str = r#"[{"height":649990,"tx_pos":1,"tx_hash":"4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b","value":100000}]"#;
+
+    #[test]
+    fn whatsonchain_reads() {
+        let server = ReplayServer::start(vec![
+            ("GET", format!("/tx/hash/{}", TXID), 200, TX_HASH.to_string()),
+            ("GET", format!("/tx/{}/hex", TXID), 200, "0100000000".to_string()),
+            ("GET", format!("/address/{}/history", ADDRESS), 200, HISTORY.to_string()),
+            ("GET", format!("/address/{}/unspent", ADDRESS), 200, UNSPENT.to_string()),
+        ]);
+        let woc = WhatsOnChain::new(format!("{}/", server.url));
+
+        let tx = woc.get_tx(TXID).unwrap();
+        assert_eq!(tx.hex, "0100000000");
+        assert_eq!(tx.height, Some(650_000));
+
+        let history = woc.address_history(ADDRESS).unwrap();
+        assert_eq!(history[0], TxRef { txid: FUNDING.to_string(), height: Some(649_990) });
+        assert_eq!(history[1].height, None);
+
+        let utxos = woc.utxos(ADDRESS).unwrap();
+        assert_eq!(utxos, vec![Utxo { txid: FUNDING.to_string(), vout: 1, value: 100_000, height: Some(649_990) }]);
+    }
+
+    #[test]
+    fn whatsonchain_broadcast() {
+        let server = ReplayServer::start(vec![("POST", "/tx/raw".to_string(), 200, format!("\"{}\"", TXID))]);
+        let woc = WhatsOnChain::new(&server.url);
+
+        assert_eq!(woc.broadcast("0100000000").unwrap(), TXID);
+        assert_eq!(server.requests(), vec![r#"POST /tx/raw {"txhex":"0100000000"}"#]);
+    }
+
+    #[test]
+    fn whatsonchain_errors() {
+        let server = ReplayServer::start(vec![(
+            "POST",
+            "/tx/raw".to_string(),
+            400,
+            "\"unexpected response code 500: 64: dust\"".to_string(),
+        )]);
+        let woc = WhatsOnChain::new(&server.url);
+
+        match woc.broadcast("0100000000").unwrap_err() {
+            BackendError::Error(e) => assert_eq!(e.kind, ErrorKind::Rejected),
+            e => panic!("unexpected error {}", e),
+        }
+        match woc.get_tx(TXID).unwrap_err() {
+            BackendError::Error(e) => assert_eq!(e.kind, ErrorKind::NotFound),
+            e => panic!("unexpected error {}", e),
+        }
+        match WhatsOnChain::new("http://127.0.0.1:1").get_tx(TXID).unwrap_err() {
+            BackendError::Error(e) => assert_eq!(e.kind, ErrorKind::Unreachable),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+
+    #[test]
+    fn whatsonchain_broadcast_outcomes() {
+        let hex = crate::sign(&crate::opcode::Action::instantiate());
+        let txid = crate::tx::Transaction::from_hex(&hex).unwrap().txid();
+        let known = ReplayServer::start(vec![(
+            "POST",
+            "/tx/raw".to_string(),
+            400,
+            "\"unexpected response code 500: 257: txn-already-known\"".to_string(),
+        )]);
+        let down = ReplayServer::start(vec![("POST", "/tx/raw".to_string(), 503, "\"Service Unavailable\"".to_string())]);
+
+        assert_eq!(WhatsOnChain::new(&known.url).broadcast(&hex).unwrap(), txid);
+        match WhatsOnChain::new(&down.url).broadcast(&hex).unwrap_err() {
+            BackendError::Error(e) => assert_eq!(e.kind, ErrorKind::Unreachable),
+            e => panic!("unexpected error {}", e),
+        }
+    }
+}
@@ -0,0 +1,359 @@
+/*
+End to end runs of the repoint binary against a mock chain in a scratch home dir: no network, no Docker.
+*/
+extern crate repoint;
+
+use repoint::backend::ChainBackend;
+use repoint::key::PrivateKey;
+use repoint::mock_chain::MockChain;
+use repoint::opcode::Action;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const PRIVKEY: &str = "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ";
+const ALICE: &str = "1GAehh7TsJAHuUAeKZcXf5CnwuGuGgyX2S";
+// The same secret as PRIVKEY, for the compressed public key's address.
+const BOB_PRIVKEY: &str = "KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617";
+const BOB: &str = "1LoVGDgRs9hTfTNJNuXKSpywcbdvwRXpmK";
+
+struct Home {
+    dir: PathBuf,
+}
+
+impl Home {
+    fn new(name: &str) -> Home {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("repoint-cli-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(".repoint")).unwrap();
+
+        let config = format!(
+            "[backend]\nkind = \"mock\"\npath = \"{}\"\nsender = \"{}\"\n\n[index]\nwatch = [\"{}\"]\n",
+            dir.join("mock.toml").display(),
+            ALICE,
+            BOB
+        );
+        fs::write(dir.join(".repoint").join("config.toml"), config).unwrap();
+
+        Home { dir }
+    }
+
+    fn chain(&self) -> MockChain {
+        MockChain::open(self.dir.join("mock.toml")).unwrap()
+    }
+
+    /// Gives `address` coins of 10,000 sats.
+    fn fund(&self, address: &str, coins: usize) {
+        let chain = self.chain();
+        for _ in 0..coins {
+            chain.fund(address, 10_000).unwrap();
+        }
+    }
+
+    /// Runs `repoint args...` with `stdin` piped in, and returns its stdout. Panics if it fails.
+    fn run(&self, args: &[&str], stdin: &str) -> String {
+        let (stdout, stderr, success) = self.spawn(args, stdin);
+        assert!(success, "repoint {:?} failed:\n{}{}", args, stdout, stderr);
+        stdout
+    }
+
+    /// Runs `repoint args...`, and returns its stderr. Panics if it succeeds.
+    fn fail(&self, args: &[&str]) -> String {
+        let (stdout, stderr, success) = self.spawn(args, "");
+        assert!(!success, "repoint {:?} should have failed:\n{}", args, stdout);
+        stderr
+    }
+
+    fn spawn(&self, args: &[&str], stdin: &str) -> (String, String, bool) {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_repoint"))
+            .args(args)
+            .current_dir(&self.dir)
+            .env("HOME", &self.dir)
+            .env("REPOINT_PASSPHRASE", "correct horse")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+            output.status.success(),
+        )
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn write_manifest(dir: &Path, url: &str) {
+    fs::create_dir_all(dir).unwrap();
+    let manifest = format!(
+        "['repository']\nversion = \"0.1.0\"\nname = \"repoint\"\naccount = \"7db9a\"\ndescription = \"Point to your repos.\"\nurls = [\"{}\"]\n",
+        url
+    );
+    fs::write(dir.join("repoint.toml"), manifest).unwrap();
+}
+
+fn seed_bob(chain: &MockChain) {
+    let actions = [
+        Action::instantiate(),
+        Action::create_account("acme").unwrap(),
+        Action::create_repo("anvil").unwrap(),
+        Action::add_repo_url(0, "https://github.com/acme/anvil").unwrap(),
+    ];
+
+    chain.publish(&PrivateKey::from_wif(BOB_PRIVKEY).unwrap(), &actions).unwrap();
+    chain.mine(10).unwrap();
+}
+
+#[test]
+fn publish_and_look_up() {
+    let home = Home::new("publish");
+    seed_bob(&home.chain());
+    let repo = home.dir.join("repoint");
+    write_manifest(&repo, "https://github.com/7db9a/repoint");
+
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 1);
+    let init = home.run(&["init"], "");
+    assert_eq!(repoint::decode::decode_hex(init.trim()).unwrap(), vec![Action::instantiate()]);
+
+    let plan = home.run(&["push", "-y", repo.to_str().unwrap()], "");
+    assert!(plan.contains("+ 0x7206 create repo repoint"));
+    assert!(plan.contains("Fee: "));
+    home.run(&["broadcast"], "");
+    assert_eq!(home.run(&["push", "-y", repo.to_str().unwrap()], "").trim(), "repoint is up to date");
+
+    home.chain().mine(1).unwrap();
+    // Everything push planned went out in one transaction, in plan order.
+    let history = home.run(&["history", "--refresh"], "");
+    assert_eq!(history.lines().count(), 1);
+    assert!(history.contains("\tconfirmed\t11\t"));
+    assert!(history.contains("+ 0x7203 create account 7db9a; + 0x7206 create repo repoint"));
+
+    let found = home.run(&["query", "-q"], "");
+    assert_eq!(found.lines().collect::<Vec<_>>(), vec!["acme/anvil", "7db9a/repoint"]);
+    let uri = home.run(&["get", "--name", "7db9a/repoint", "--show-uri"], "");
+    assert_eq!(uri.trim(), "https://github.com/7db9a/repoint");
+    assert_eq!(home.run(&["get", "--addr", BOB, "--show-name"], "").trim(), "acme");
+}
+
+#[test]
+fn look_up_unwatched() {
+    let home = Home::new("unwatched");
+    seed_bob(&home.chain());
+    let config = home.dir.join(".repoint").join("config.toml");
+    let settings = fs::read_to_string(&config).unwrap();
+    fs::write(&config, settings.replace(&format!("watch = [\"{}\"]", BOB), "watch = []")).unwrap();
+
+    assert!(home.fail(&["query", "-q", "anvil"]).is_empty());
+    assert_eq!(home.run(&["query", "-q", "--author", BOB], "").trim(), "acme/anvil");
+    assert_eq!(home.run(&["get", "--addr", BOB, "--show-name"], "").trim(), "acme");
+}
+
+#[test]
+fn push_after_reorg() {
+    let home = Home::new("reorg");
+    let repo = home.dir.join("repoint");
+    write_manifest(&repo, "https://github.com/7db9a/repoint");
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 1);
+    home.run(&["push", "-y", repo.to_str().unwrap()], "");
+    home.run(&["broadcast"], "");
+    home.chain().mine(1).unwrap();
+
+    // The block is undone but its transactions stay in the mempool, so nothing needs publishing again.
+    let chain = home.chain();
+    assert_eq!(chain.reorg(1).unwrap().len(), 2);
+    assert!(chain.address_history(ALICE).unwrap().iter().all(|tx| tx.height.is_none()));
+    assert_eq!(home.run(&["push", "-y", repo.to_str().unwrap()], "").trim(), "repoint is up to date");
+
+    // One transaction per action, chained off the first push's change and each other.
+    write_manifest(&repo, "https://gitlab.com/7db9a/repoint");
+    let plan = home.run(&["push", "-y", "--batch", "1", repo.to_str().unwrap()], "");
+    assert!(plan.contains("for 2 transactions"));
+    assert!(plan.contains("- 0x7210 remove repo url [0] https://github.com/7db9a/repoint"));
+    assert!(plan.contains("+ 0x7209 add repo url [0] https://gitlab.com/7db9a/repoint"));
+
+    assert_eq!(home.run(&["broadcast"], "").lines().count(), 2);
+    let uri = home.run(&["get", "--name", "7db9a/repoint", "--show-uri"], "");
+    assert_eq!(uri.trim(), "https://gitlab.com/7db9a/repoint");
+}
+
+#[test]
+fn like_and_follow() {
+    let home = Home::new("social");
+    seed_bob(&home.chain());
+    let repo = home.dir.join("repoint");
+    write_manifest(&repo, "https://github.com/7db9a/repoint");
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 1);
+    home.run(&["push", "-y", repo.to_str().unwrap()], "");
+
+    let like = home.run(&["like", "-y", "acme/anvil"], "");
+    assert!(like.contains(&format!("+ 0x7211 like repo {}:0", BOB)));
+    // Signed but not yet broadcast still counts.
+    assert!(home.fail(&["like", "-y", "anvil"]).contains("already likes"));
+    assert!(home.fail(&["unfollow", "-y", "anvil"]).contains("doesn't follow"));
+    home.run(&["follow", "-y", "anvil"], "");
+
+    assert_eq!(home.run(&["broadcast"], "").lines().count(), 3);
+    home.chain().mine(1).unwrap();
+    assert_eq!(home.run(&["get", "--name", "anvil", "--show-likes"], "").trim(), "1");
+    assert_eq!(home.run(&["get", "--name", "anvil", "--show-followers"], "").trim(), ALICE);
+}
+
+#[test]
+fn tip_repo() {
+    let home = Home::new("tip");
+    seed_bob(&home.chain());
+    let repo = home.dir.join("repoint");
+    write_manifest(&repo, "https://github.com/7db9a/repoint");
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 2);
+    home.run(&["push", "-y", repo.to_str().unwrap()], "");
+
+    assert!(home.fail(&["tip", "-y", "anvil", "1", "bsv"]).contains("[tips] max"));
+    assert!(home.fail(&["tip", "-y", "anvil", "lots"]).contains("isn't an amount"));
+    let tip = home.run(&["tip", "-y", "anvil", "0.00001", "bsv"], "");
+    assert!(tip.contains(&format!("pay 1000 sats to {}", BOB)));
+
+    home.run(&["broadcast"], "");
+    home.chain().mine(1).unwrap();
+    assert_eq!(home.run(&["get", "--name", "anvil", "--show-tips"], "").trim(), format!("{}\t1000", ALICE));
+    assert!(home.chain().utxos(BOB).unwrap().iter().any(|u| u.value == 1000));
+}
+
+#[test]
+fn redirect_repo_and_account() {
+    let home = Home::new("redirect");
+    seed_bob(&home.chain());
+    let repo = home.dir.join("repoint");
+    write_manifest(&repo, "https://github.com/7db9a/repoint");
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 1);
+    home.run(&["push", "-y", repo.to_str().unwrap()], "");
+
+    let plan = home.run(&["redirect", "-y", "repo", "repoint", BOB, "0"], "");
+    assert!(plan.contains(&format!("+ 0x7219 redirect repo to repo [0] {}:0", BOB)));
+    assert!(home.fail(&["redirect", "-y", "repo", "repoint", BOB, "0"]).contains("already redirects"));
+    assert!(home.fail(&["redirect", "-y", "account", ALICE]).contains("loop"));
+    home.run(&["broadcast"], "");
+    home.chain().mine(1).unwrap();
+
+    let get = home.run(&["get", "--name", "7db9a/repoint"], "");
+    assert_eq!(get.trim(), format!("7db9a/repoint\t{}\t-> acme/anvil\t{}", ALICE, BOB));
+    assert_eq!(home.run(&["get", "--name", "7db9a/repoint", "--show-uri"], "").trim(), "https://github.com/acme/anvil");
+    assert!(home.run(&["query", "repoint"], "").trim().ends_with("\t-> acme/anvil"));
+}
+
+#[test]
+fn flag_and_moderate() {
+    let home = Home::new("flag");
+    seed_bob(&home.chain());
+    let repo = home.dir.join("repoint");
+    write_manifest(&repo, "https://github.com/7db9a/repoint");
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 1);
+    home.run(&["push", "-y", repo.to_str().unwrap()], "");
+
+    assert!(home.run(&["flag", "-y", "anvil"], "").contains(&format!("+ 0x7213 flag repo {}:0", BOB)));
+    assert!(home.fail(&["unflag", "-y", "repoint"]).contains("doesn't flag"));
+    home.run(&["broadcast"], "");
+    home.chain().mine(1).unwrap();
+
+    // Your own flags count by default, and flagged repos are marked.
+    let found = home.run(&["query"], "");
+    assert!(found.lines().any(|line| line.starts_with("acme/anvil") && line.ends_with("\tflagged by 7db9a")));
+
+    let config = home.dir.join(".repoint").join("config.toml");
+    let mut settings = fs::read_to_string(&config).unwrap();
+    settings.push_str("\n[moderation]\nflagged = \"hide\"\n");
+    fs::write(&config, settings).unwrap();
+    assert_eq!(home.run(&["query", "-q"], "").trim(), "7db9a/repoint");
+    assert_eq!(home.run(&["query", "-q", "--show-flagged"], "").lines().count(), 2);
+}
+
+#[test]
+fn flags_from_followed_owners() {
+    let home = Home::new("followed");
+    seed_bob(&home.chain());
+    let repo = home.dir.join("repoint");
+    write_manifest(&repo, "https://github.com/7db9a/repoint");
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 1);
+    home.run(&["push", "-y", repo.to_str().unwrap()], "");
+    home.run(&["follow", "-y", "anvil"], "");
+    home.run(&["broadcast"], "");
+
+    let chain = home.chain();
+    let flag = Action::builder(repoint::opcode::Opcode::FlagRepo).address(ALICE).repo_index(0).build().unwrap();
+    chain.publish(&PrivateKey::from_wif(BOB_PRIVKEY).unwrap(), &[flag]).unwrap();
+    chain.mine(1).unwrap();
+
+    // Nobody watches BOB any more, but ALICE follows a repo of theirs, so their flag still counts.
+    let config = home.dir.join(".repoint").join("config.toml");
+    let settings = fs::read_to_string(&config).unwrap().replace(&format!("watch = [\"{}\"]", BOB), "watch = []");
+    fs::write(&config, settings).unwrap();
+    let found = home.run(&["query", "repoint"], "");
+    assert!(found.trim().ends_with("\tflagged by acme"), "{}", found);
+}
+
+#[test]
+fn push_profile() {
+    let home = Home::new("profile");
+    home.run(&["create-account", "--stdin", "7db9a", ALICE], &format!("{}\n", PRIVKEY));
+    home.fund(ALICE, 1);
+
+    let accounts = home.dir.join(".repoint").join("account.toml");
+    let pubaddr = format!("pubaddr = \"{}\"\n", ALICE);
+    let settings = fs::read_to_string(&accounts).unwrap();
+    let with_avatar = |avatar: &str| {
+        let profile = format!("{}profile = \"Point to your repos.\"\navatar = \"{}\"\n", pubaddr, avatar);
+        fs::write(&accounts, settings.replace(&pubaddr, &profile)).unwrap();
+    };
+    with_avatar("example.com/7db9a.png");
+    assert!(home.fail(&["push", "--account", "-y"]).contains("isn't a uri"));
+
+    with_avatar("https://example.com/7db9a.png");
+    let plan = home.run(&["push", "--account", "-y"], "");
+    assert!(plan.contains("~ 0x7204 update profile text Point to your repos."));
+    assert!(plan.contains("~ 0x7205 new profile pic https://example.com/7db9a.png"));
+    assert!(home.run(&["push", "--account", "-y"], "").contains("up to date"));
+
+    home.run(&["broadcast"], "");
+    home.chain().mine(1).unwrap();
+    assert_eq!(
+        home.run(&["get", "--addr", ALICE, "--show-profile"], ""),
+        "profile\tPoint to your repos.\navatar\thttps://example.com/7db9a.png\n"
+    );
+}
+
+#[test]
+fn encrypt_every_account() {
+    let home = Home::new("encrypt");
+    let accounts = home.dir.join(".repoint").join("account.toml");
+    let settings = format!(
+        "default = \"7db9a\"\n\n[accounts.7db9a]\npubaddr = \"{}\"\nxpriv = \"{}\"\n\n[accounts.acme]\npubaddr = \"{}\"\nxpriv = \"{}\"\n",
+        ALICE, PRIVKEY, BOB, BOB_PRIVKEY
+    );
+    fs::write(&accounts, settings).unwrap();
+
+    let encrypted = home.run(&["encrypt-account"], "");
+    assert!(encrypted.contains("encrypted private key of 7db9a"), "{}", encrypted);
+    assert!(encrypted.contains("encrypted private key of acme"), "{}", encrypted);
+    let settings = fs::read_to_string(&accounts).unwrap();
+    assert!(!settings.contains("xpriv ="), "{}", settings);
+    assert_eq!(settings.matches("xpriv_ciphertext").count(), 2);
+    assert!(home.run(&["encrypt-account"], "").contains("already encrypted"));
+}